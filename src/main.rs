@@ -1,25 +1,57 @@
 #![allow(dead_code)]
 #![allow(unused_imports)]
+// The code's style : explicit returns, `field : field` initializations, `&Vec` parameters and index loops
+#![allow(clippy::needless_return, clippy::redundant_field_names, clippy::ptr_arg, clippy::needless_range_loop)]
 
 extern crate rand;
 extern crate time;
 extern crate rimd;
 extern crate crossbeam;
 
+use std::env;
+use std::path::Path;
 use std::time::Instant;
 
 mod network;
 mod utils;
 
+use network::cesure;
 use network::cesure::Cesure;
+use network::cesure::CesureConfig;
+use network::cesure::Generation;
+use network::gate::memory_gate;
+use network::recurrent_level::parse_levels;
+use network::attention::parse_attention;
+use network::positional_encoding::parse_positional_encoding;
+use network::gate::weight_init::WeightInit;
+use network::gate::weight_init::parse_weight_init;
 use network::music::CHORD_DIMENSION;
+use network::sampler::Sampler;
+use network::constraints::GenerationConstraints;
+use network::music::CesureMusic;
+use network::music::Meter;
+use network::music::new_infos;
+use network::conditioning::Conditioning;
+use network::conditioning::ConditioningSchema;
+use network::training::corpus::Corpus;
+use network::evaluation;
+use utils::string::parse_option;
 use utils::traits::Parse;
 
 use utils::io::{AsyncStdinReader, AsyncStdinRead};
+use utils::random;
 
 
+// The options setting up a new network, a loaded network ignores them
+const CONSTRUCTION_FLAGS : [&str; 7] = ["--conditioning", "--memory", "--levels", "--attention", "--positions", "--output-init", "--memory-init"];
+
 fn main() {
 
+    let args : Vec<String> = env::args().collect();
+    let mut rand = random::new_rng(parse_option::<usize>(&args, "--seed"));
+    let sampler = Sampler::from_args(&args);
+    let constraints = GenerationConstraints::from_args(&args);
+
     /*let mut stdin = AsyncStdinReader::new();
 
     println!("Welcome to CesureTrainer!");
@@ -34,7 +66,7 @@ fn main() {
                 println!("Enter a valid usize");
                 dimension = stdin.read_line_blocking().parse();
             }
-            Cesure::new(dimension.unwrap());
+            Cesure::new(dimension.unwrap(), cesure::OUTPUT_MODE_INDEPENDENT, memory_gate::MEMORY_TYPE_FEEDFORWARD, &mut rand);
         },
         "1" => {
             println!("Enter the network's file name");
@@ -47,11 +79,133 @@ fn main() {
     println!("- [0] Create new network");
     println!("- [1] Load network");*/
 
-    let mut cesure : Cesure = Cesure::load("cesure_test.ces");
-    cesure.train_n_save();
+    let meter = parse_meter(&args);
+
+    let mut cesure : Cesure = match Path::new("cesure_test.ces").exists() {
+        true => {
+            let ignored_flags : Vec<&str> = CONSTRUCTION_FLAGS.iter().cloned().filter(|flag| args.iter().any(|arg| arg == flag)).collect();
+            if !ignored_flags.is_empty() {
+                println!("Warning : cesure_test.ces is loaded, so {} only apply to a new network and are ignored. Move the file away to create a new one.",
+                         ignored_flags.join(", "));
+            }
+            Cesure::load("cesure_test.ces")
+        }
+        false => {
+            let schema = match parse_option::<String>(&args, "--conditioning") {
+                Some(fields) => {
+                    let mut schema = ConditioningSchema::from_string(format!("CONDITIONING {}", fields).as_str());
+                    Corpus::from_folder("./").fill_schema_labels(&mut schema);
+                    schema
+                }
+                None => ConditioningSchema::none(),
+            };
+            let memory_type = match parse_option::<String>(&args, "--memory") {
+                Some(name) => memory_gate::memory_type_from_string(name.as_str()),
+                None => memory_gate::MEMORY_TYPE_FEEDFORWARD,
+            };
+            let levels = match parse_option::<String>(&args, "--levels") {
+                Some(levels) => parse_levels(levels.as_str(), &meter),
+                None => Vec::new(),
+            };
+            let output_init = parse_option::<String>(&args, "--output-init").map_or(WeightInit::default(), |init| parse_weight_init(init.as_str()));
+            let memory_init = parse_option::<String>(&args, "--memory-init").map_or(WeightInit::default(), |init| parse_weight_init(init.as_str()));
+            let mut config = CesureConfig::new(70, cesure::OUTPUT_MODE_INDEPENDENT, memory_type)
+                .with_levels(levels)
+                .with_weight_init(output_init, memory_init)
+                .with_conditioning(schema);
+            if let Some(attention) = parse_option::<String>(&args, "--attention") {
+                let (attention_type, window) = parse_attention(attention.as_str(), &meter);
+                config = config.with_attention(attention_type, window);
+            }
+            if let Some(features) = parse_option::<String>(&args, "--positions") {
+                config = config.with_positional_encoding(parse_positional_encoding(features.as_str(), &meter));
+            }
+            Cesure::from_config(&config, &mut rand)
+        }
+    };
+
+    let output_path = parse_option(&args, "--output").unwrap_or("output_test.mid".to_string());
+    let conditioning = Conditioning::from_args(&args, &meter);
+    let mut generation = Generation::new(&sampler, &constraints, &mut rand);
+    if args.iter().any(|arg| arg == "--generate") {
+        let division = parse_option(&args, "--division").unwrap_or(480.0);
+        let base_key = parse_option(&args, "--base-key").unwrap_or(36);
+        let nb_ticks = parse_length(&args, &meter).expect("--generate needs --ticks, --bars or --seconds");
+        let music = match parse_option(&args, "--end-on-silence") {
+            Some(nb_silent_ticks) => {
+                let infos = cesure.build_infos(&new_infos(division, nb_ticks, base_key), &conditioning);
+                cesure.compute_music_until_silence(&infos, &Vec::new(), nb_ticks, nb_silent_ticks, &meter, &mut generation)
+            }
+            None => cesure.compute_music(division, nb_ticks, base_key, &conditioning, &mut generation),
+        };
+        music.save_with_meter(output_path.as_str(), &meter);
+        return;
+    }
+    if let Some(path) = parse_option::<String>(&args, "--continue") {
+        let music = CesureMusic::from_path_str(path.as_str());
+        let nb_ticks = parse_length(&args, &meter).expect("--continue needs --ticks, --bars or --seconds");
+        let nb_injected = parse_option(&args, "--inject").unwrap_or(music.chords.len());
+        cesure.continue_music(&music, nb_injected, nb_ticks, &conditioning, &mut generation).save_with_meter(output_path.as_str(), &meter);
+        return;
+    }
+    if let Some(path) = parse_option::<String>(&args, "--infill") {
+        let music = CesureMusic::from_path_str(path.as_str());
+        let begin = parse_option(&args, "--from").expect("--infill needs --from <tick>");
+        let end = parse_option(&args, "--to").expect("--infill needs --to <tick>");
+        let nb_candidates = parse_option(&args, "--candidates").unwrap_or(1);
+        cesure.infill_music(&music, begin, end, nb_candidates, &conditioning, &mut generation).save_with_meter(output_path.as_str(), &meter);
+        return;
+    }
+
+    let batch_size = parse_option(&args, "--batch-size").unwrap_or(1);
+    let nb_threads = parse_option(&args, "--threads").unwrap_or(1);
+    if let Some(path) = parse_option::<String>(&args, "--evaluate") {
+        let music = CesureMusic::from_path_str(path.as_str());
+        let mut training_set = music.to_training_set(parse_option(&args, "--inject").unwrap_or(0));
+        training_set.infos = cesure.build_infos(&training_set.infos, &conditioning);
+        let evaluation = evaluation::evaluate_predictions(&mut cesure, &training_set, parse_option(&args, "--tolerance").unwrap_or(1));
+        let ideal_stats = evaluation::chords_stats(&training_set.compute_sequence, music.min_key());
+        evaluation.print_title(format!("Predictions on {} :", path).as_str());
+        println!("- Pitch class distance : {:.4}", evaluation::pitch_class_distance(&evaluation.stats.pitch_class_histogram, &ideal_stats.pitch_class_histogram));
+        return;
+    }
+
+    cesure.train_n_save(batch_size, nb_threads, &sampler, &constraints, &mut rand);
 
 }
 
+/**
+* Parse the --bpm <bpm> and --time-signature <n/d> options, 120 bpm in 2/4 by default
+*/
+fn parse_meter(args : &Vec<String>) -> Meter {
+    let default = Meter::default();
+    let bpm = parse_option(args, "--bpm").unwrap_or(default.bpm);
+    match parse_option::<String>(args, "--time-signature") {
+        Some(signature) => {
+            let parts : Vec<usize> = signature.split('/').map(|x| x.parse().expect("--time-signature expects n/d")).collect();
+            assert!(parts.len() == 2, "--time-signature expects n/d");
+            Meter::new(bpm, parts[0], parts[1])
+        }
+        None => Meter::new(bpm, default.numerator, default.denominator),
+    }
+}
+
+/**
+* Parse the generated length from the --ticks, --bars or --seconds option
+*/
+fn parse_length(args : &Vec<String>, meter : &Meter) -> Option<usize> {
+    if let Some(nb_ticks) = parse_option(args, "--ticks") {
+        return Some(nb_ticks);
+    }
+    if let Some(nb_bars) = parse_option(args, "--bars") {
+        return Some(meter.bars_to_ticks(nb_bars));
+    }
+    if let Some(seconds) = parse_option(args, "--seconds") {
+        return Some(meter.seconds_to_ticks(seconds));
+    }
+    None
+}
+
 
 /*fn m_dot_test() {
     let mut rand = rand::thread_rng();
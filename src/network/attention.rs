@@ -0,0 +1,429 @@
+extern crate rand;
+
+use std::ops::Add;
+
+use rand::Rng;
+
+use network::gate::traits::WeightsChanges;
+use network::gate::weight_init::WeightInit;
+use network::music::Meter;
+
+use utils::matrix::Matrix;
+use utils::matrix_math::m_dot;
+use utils::matrix_math::mult_scl;
+use utils::matrix_math::transpose;
+use utils::matrix_math::row_append;
+use utils::traits::Parse;
+
+
+// The attended items are the contexts of the previous ticks
+pub const ATTENTION_TYPE_CONTEXT : u8 = 0;
+// The attended items are the chords fed to the memory gate on the previous ticks
+pub const ATTENTION_TYPE_CHORD : u8 = 1;
+
+pub const DEFAULT_KEY_DIMENSION : usize = 16;
+
+
+/**
+* A dot-product attention over a sliding window of past items
+* The query is computed from the infos and the context, each item's key from the item,
+* and the attended vector is the items' average weighted by the softmax of the
+* query-key dot products
+* - attention_type : ATTENTION_TYPE_CONTEXT or ATTENTION_TYPE_CHORD
+* - window : The maximum number of past items attended
+* - query_dimension : The query's input dimension
+* - item_dimension : The dimension of an item, and of the attended vector
+* - key_dimension : The queries' and keys' dimension
+* - query_weights : The (query_dimension+1, key_dimension) weights, the last row holding the bias
+* - key_weights : The (item_dimension, key_dimension) weights
+*/
+pub struct Attention {
+    pub attention_type : u8,
+    pub window : usize,
+    pub query_dimension : usize,
+    pub item_dimension : usize,
+    pub key_dimension : usize,
+    pub query_weights : Matrix,
+    pub key_weights : Matrix,
+}
+
+pub struct VerboseOutput {
+    pub query_input_bias : Matrix,
+    pub query : Matrix,
+    pub keys : Vec<Matrix>,
+    pub items : Vec<Matrix>,
+    pub attention_weights : Vec<f64>,
+    pub output : Matrix,
+}
+
+
+
+impl Clone for Attention {
+    fn clone(&self) -> Attention {
+        return Attention {
+            attention_type : self.attention_type,
+            window : self.window,
+            query_dimension : self.query_dimension,
+            item_dimension : self.item_dimension,
+            key_dimension : self.key_dimension,
+            query_weights : self.query_weights.clone(),
+            key_weights : self.key_weights.clone(),
+        }
+    }
+    fn clone_from(&mut self, source: &Attention) {
+        self.attention_type = source.attention_type;
+        self.window = source.window;
+        self.query_dimension = source.query_dimension;
+        self.item_dimension = source.item_dimension;
+        self.key_dimension = source.key_dimension;
+        self.query_weights.clone_from(&source.query_weights);
+        self.key_weights.clone_from(&source.key_weights);
+    }
+}
+impl Parse for Attention {
+    fn to_string(&self) -> String {
+        let output = format!("{} {} {} {} {}", attention_type_to_string(self.attention_type), self.window,
+                             self.query_dimension, self.item_dimension, self.key_dimension);
+        return output.add(&format!("\n{}\n{}", self.query_weights.to_string(), self.key_weights.to_string()));
+    }
+    fn from_string(str : &str) -> Attention {
+        let lines : Vec<&str> = str.split("\n").collect();
+        assert!(lines.len() == 3); // header + query weights + key weights
+
+        let header : Vec<&str> = lines[0].split(" ").collect();
+        assert!(header.len() == 5);
+        let query_dimension = header[2].parse().unwrap();
+        let item_dimension = header[3].parse().unwrap();
+        let key_dimension = header[4].parse().unwrap();
+        let query_weights = Matrix::from_string(lines[1]);
+        let key_weights = Matrix::from_string(lines[2]);
+        assert!(query_weights.rows == query_dimension+1 && query_weights.cols == key_dimension);
+        assert!(key_weights.rows == item_dimension && key_weights.cols == key_dimension);
+
+        return Attention {
+            attention_type : attention_type_from_string(header[0]),
+            window : header[1].parse().unwrap(),
+            query_dimension : query_dimension,
+            item_dimension : item_dimension,
+            key_dimension : key_dimension,
+            query_weights : query_weights,
+            key_weights : key_weights,
+        }
+    }
+}
+
+
+
+impl Attention {
+
+    /**
+    * Create a new Attention object
+    * @input attention_type : ATTENTION_TYPE_CONTEXT or ATTENTION_TYPE_CHORD
+    * @input window : The maximum number of past items attended
+    * @input query_dimension : The query's input dimension
+    * @input item_dimension : The dimension of an item
+    * @input key_dimension : The queries' and keys' dimension
+    * @input weight_init : The weights initialization
+    * @input rand : The random number generator used to initialize the weights
+    * @return The newly created Attention object
+    */
+    pub fn new<R: Rng>(attention_type : u8, window : usize, query_dimension : usize, item_dimension : usize, key_dimension : usize,
+                       weight_init : &WeightInit, rand : &mut R) -> Attention {
+        assert!(attention_type <= ATTENTION_TYPE_CHORD, "Unknown attention type");
+        assert!(window > 0 && query_dimension > 0 && item_dimension > 0 && key_dimension > 0);
+        let mut query_weights = Matrix::new(query_dimension+1, key_dimension);
+        weight_init.init_layer(&mut query_weights, rand);
+        // The keys have no bias, as it would add the same value to every dot product
+        let mut key_layer = Matrix::new(item_dimension+1, key_dimension);
+        weight_init.init_layer(&mut key_layer, rand);
+        let mut key_weights = Matrix::new(item_dimension, key_dimension);
+        for i in 0..key_weights.len {
+            key_weights.datas[i] = key_layer.datas[i];
+        }
+        return Attention {
+            attention_type : attention_type,
+            window : window,
+            query_dimension : query_dimension,
+            item_dimension : item_dimension,
+            key_dimension : key_dimension,
+            query_weights : query_weights,
+            key_weights : key_weights,
+        }
+    }
+
+    /**
+    * @input magnitude : The amount of random
+    * @input rand : The random number generator
+    * @return The cloned Attention
+    */
+    pub fn clone_randomized<R: Rng>(&self, magnitude : f64, rand : &mut R) -> Attention {
+        let mut output = self.clone();
+        output.query_weights = self.query_weights.clone_randomized(magnitude, rand);
+        output.key_weights = self.key_weights.clone_randomized(magnitude, rand);
+        output
+    }
+
+    /**
+    * Print the weights
+    */
+    pub fn print(&self) {
+        println!("Query weights :");
+        self.query_weights.print();
+        println!("Key weights :");
+        self.key_weights.print();
+    }
+
+    /**
+    * Return the number of neurons in this network
+    * @return The number of neurons
+    */
+    pub fn get_nb_neurons(&self) -> usize {
+        self.query_weights.len + self.key_weights.len
+    }
+
+    /**
+    * Attend to the past items
+    * @input query_input : The infos and the context
+    * @input items : The past items, from the oldest
+    * @return The attended vector, zeros when there is no item yet
+    */
+    pub fn compute(&self, query_input : &Matrix, items : &Vec<Matrix>) -> Matrix {
+        self.compute_verbose(query_input, items).output
+    }
+
+    /**
+    * Attend to the past items and return a VerboseOutput object
+    * @input query_input : The infos and the context
+    * @input items : The past items, from the oldest
+    * @return The VerboseOutput object
+    */
+    pub fn compute_verbose(&self, query_input : &Matrix, items : &Vec<Matrix>) -> VerboseOutput {
+        assert!(query_input.is_row() && query_input.len == self.query_dimension);
+        assert!(items.len() <= self.window);
+        let query_input_bias = row_append(query_input, 1.0);
+        let query = m_dot(&query_input_bias, &self.query_weights);
+        let scale = 1.0 / (self.key_dimension as f64).sqrt();
+
+        let keys : Vec<Matrix> = items.iter().map(|item| m_dot(item, &self.key_weights)).collect();
+        let scores : Vec<f64> = keys.iter().map(|key| scale * dot(&query, key)).collect();
+        let max_score = scores.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+        let exps : Vec<f64> = scores.iter().map(|score| (score - max_score).exp()).collect();
+        let exps_sum : f64 = exps.iter().sum();
+        let attention_weights : Vec<f64> = exps.iter().map(|exp| exp / exps_sum).collect();
+
+        let mut output = Matrix::new_row(self.item_dimension);
+        for j in 0..items.len() {
+            output.add(&mult_scl(&items[j], attention_weights[j]));
+        }
+
+        return VerboseOutput {
+            query_input_bias : query_input_bias,
+            query : query,
+            keys : keys,
+            items : items.clone(),
+            attention_weights : attention_weights,
+            output : output,
+        }
+    }
+
+    /**
+    * Backpropagate an error signal to get the weights changes
+    * It won't apply the weights changes, use apply_changes() if you want to apply them
+    * @input output_v : The last computation's VerboseOutput object
+    * @input signal : The attended vector's error signal
+    * @input last_changes : The last weights changes, None if it's the first iteration
+    * @input momentum : The momentum
+    * @return A tuple composed by the query input's error signal, the items' error signals
+    *         and the weights changes
+    */
+    pub fn backpropagate_no_change(&self, output_v : &VerboseOutput, signal : &Matrix, learning_rate : f64,
+                                   last_changes : &Option<WeightsChanges>, momentum : f64) -> (Matrix, Vec<Matrix>, WeightsChanges) {
+        assert!(signal.len == self.item_dimension);
+        let nb_items = output_v.items.len();
+        let scale = 1.0 / (self.key_dimension as f64).sqrt();
+
+        // Softmax backpropagation
+        let weights_signals : Vec<f64> = output_v.items.iter().map(|item| dot(signal, item)).collect();
+        let mean_signal : f64 = (0..nb_items).map(|j| output_v.attention_weights[j] * weights_signals[j]).sum();
+        let scores_signals : Vec<f64> = (0..nb_items).map(|j| output_v.attention_weights[j] * (weights_signals[j] - mean_signal)).collect();
+
+        let mut query_signal = Matrix::new_row(self.key_dimension);
+        let mut key_weights_delta = Matrix::new(self.item_dimension, self.key_dimension);
+        let mut items_signals = Vec::with_capacity(nb_items);
+        for j in 0..nb_items {
+            query_signal.add(&mult_scl(&output_v.keys[j], scale * scores_signals[j]));
+            let key_signal = mult_scl(&output_v.query, scale * scores_signals[j]);
+            key_weights_delta.add(&m_dot(&transpose(&output_v.items[j]), &key_signal));
+            let mut item_signal = mult_scl(signal, output_v.attention_weights[j]);
+            item_signal.add(&m_dot(&key_signal, &transpose(&self.key_weights)));
+            items_signals.push(item_signal);
+        }
+
+        let mut query_weights_delta = m_dot(&transpose(&output_v.query_input_bias), &query_signal);
+        query_weights_delta.mult_scl(-learning_rate);
+        key_weights_delta.mult_scl(-learning_rate);
+        let mut weights_deltas = vec![query_weights_delta, key_weights_delta];
+        if let Some(ref last_changes) = *last_changes {
+            for i in 0..weights_deltas.len() {
+                weights_deltas[i].add(&mult_scl(&last_changes[i], momentum));
+            }
+        }
+
+        let mut query_input_signal = m_dot(&query_signal, &transpose(&self.query_weights));
+        query_input_signal.delete_last_col();
+
+        assert!(query_input_signal.is_finite());
+        return (query_input_signal, items_signals, weights_deltas);
+    }
+
+    /**
+    * Apply the weights changes
+    * @input weights_changes : The query weights' then the key weights' changes
+    */
+    pub fn apply_changes(&mut self, weights_changes : &WeightsChanges) {
+        assert!(weights_changes.len() == 2);
+        self.query_weights.add(&weights_changes[0]);
+        self.key_weights.add(&weights_changes[1]);
+    }
+
+}
+
+
+
+/**
+* The dot product of two rows
+*/
+fn dot(row1 : &Matrix, row2 : &Matrix) -> f64 {
+    assert!(row1.len == row2.len);
+    (0..row1.len).map(|i| row1.datas[i] * row2.datas[i]).sum()
+}
+
+/**
+* Parse the attention's description
+* It's written <attended items>:<window>, the items being "context" or "chord" and the
+* window a number of ticks, "beat" or "bar"
+* @input str : The description, like "chord:bar"
+* @input meter : The Meter giving the beat and bar lengths
+* @return The (attention type, window) of the attention
+*/
+pub fn parse_attention(str : &str, meter : &Meter) -> (u8, usize) {
+    let parsed : Vec<&str> = str.split(":").collect();
+    assert!(parsed.len() == 2, "An attention is written <context|chord>:<window>");
+    let window = match parsed[1] {
+        "beat" => meter.ticks_per_bar() / meter.numerator,
+        "bar" => meter.ticks_per_bar(),
+        ticks => ticks.parse().expect("Invalid attention window"),
+    };
+    (attention_type_from_string(parsed[0]), window)
+}
+
+/**
+* Convert an attention type to its name
+* @input attention_type : ATTENTION_TYPE_CONTEXT or ATTENTION_TYPE_CHORD
+* @return The name
+*/
+pub fn attention_type_to_string(attention_type : u8) -> &'static str {
+    match attention_type {
+        ATTENTION_TYPE_CONTEXT => "context",
+        ATTENTION_TYPE_CHORD => "chord",
+        _ => panic!("Unknown attention type"),
+    }
+}
+
+/**
+* Convert an attention type name to the attention type
+* @input str : "context" or "chord"
+* @return The attention type
+*/
+pub fn attention_type_from_string(str : &str) -> u8 {
+    match str {
+        "context" => ATTENTION_TYPE_CONTEXT,
+        "chord" => ATTENTION_TYPE_CHORD,
+        _ => panic!("Unknown attention type {}", str),
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use utils::random::new_rng;
+
+    const EPSILON : f64 = 1e-6;
+    const TOLERANCE : f64 = 1e-6;
+
+    fn tiny_attention() -> Attention {
+        Attention::new(ATTENTION_TYPE_CONTEXT, 3, 4, 2, 2, &WeightInit::default(), &mut new_rng(Some(49)))
+    }
+
+    fn error(attention : &Attention, query_input : &Matrix, items : &Vec<Matrix>, signal : &Matrix) -> f64 {
+        dot(&attention.compute(query_input, items), signal)
+    }
+
+    fn assert_close(analytic : f64, numeric : f64, what : &str) {
+        assert!((analytic - numeric).abs() < TOLERANCE * (1.0 + numeric.abs()),
+                "{} : backpropagated {} but the finite difference gives {}", what, analytic, numeric);
+    }
+
+    #[test]
+    fn gradients_match_finite_differences() {
+        let attention = tiny_attention();
+        let query_input = Matrix::new_row_from_datas(vec![0.4, -0.7, 0.2, -0.5]);
+        let items = vec![Matrix::new_row_from_datas(vec![0.9, -0.3]),
+                         Matrix::new_row_from_datas(vec![-0.6, 0.8]),
+                         Matrix::new_row_from_datas(vec![0.1, 0.7])];
+        let signal = Matrix::new_row_from_datas(vec![0.5, -0.4]);
+        let output_v = attention.compute_verbose(&query_input, &items);
+        // With a learning rate of 1 and no momentum, the changes are the opposite of the gradient
+        let (query_input_signal, items_signals, changes) = attention.backpropagate_no_change(&output_v, &signal, 1.0, &None, 0.0);
+
+        for p in 0..changes.len() {
+            for i in 0..changes[p].len {
+                let mut plus = attention.clone();
+                let mut minus = attention.clone();
+                match p {
+                    0 => {
+                        plus.query_weights.datas[i] += EPSILON;
+                        minus.query_weights.datas[i] -= EPSILON;
+                    }
+                    _ => {
+                        plus.key_weights.datas[i] += EPSILON;
+                        minus.key_weights.datas[i] -= EPSILON;
+                    }
+                }
+                let numeric = (error(&plus, &query_input, &items, &signal) - error(&minus, &query_input, &items, &signal)) / (2.0 * EPSILON);
+                assert_close(-changes[p].datas[i], numeric, &format!("Parameter {} weight {}", p, i));
+            }
+        }
+
+        for i in 0..query_input.len {
+            let mut plus = query_input.clone();
+            plus.datas[i] += EPSILON;
+            let mut minus = query_input.clone();
+            minus.datas[i] -= EPSILON;
+            let numeric = (error(&attention, &plus, &items, &signal) - error(&attention, &minus, &items, &signal)) / (2.0 * EPSILON);
+            assert_close(query_input_signal.datas[i], numeric, &format!("Query input {}", i));
+        }
+
+        for j in 0..items.len() {
+            for i in 0..items[j].len {
+                let mut plus = items.clone();
+                plus[j].datas[i] += EPSILON;
+                let mut minus = items.clone();
+                minus[j].datas[i] -= EPSILON;
+                let numeric = (error(&attention, &query_input, &plus, &signal) - error(&attention, &query_input, &minus, &signal)) / (2.0 * EPSILON);
+                assert_close(items_signals[j].datas[i], numeric, &format!("Item {} value {}", j, i));
+            }
+        }
+    }
+
+    #[test]
+    fn parse_round_trip() {
+        let attention = tiny_attention();
+        let parsed = Attention::from_string(&attention.to_string());
+        assert_eq!(parsed.attention_type, attention.attention_type);
+        assert_eq!(parsed.window, attention.window);
+        assert_eq!(parsed.to_string(), attention.to_string());
+    }
+}
@@ -0,0 +1,406 @@
+use std::cmp::Ordering;
+
+use network::cesure::Cesure;
+use network::cesure::CesureState;
+use network::gate::traits::Gate;
+use network::cesure::OUTPUT_MODE_CATEGORICAL;
+use network::music::CesureMusic;
+use network::music::CATEGORICAL_DIMENSION;
+use network::music::infos_min_key;
+use network::constraints::GenerationConstraints;
+use network::training::error_calculation::EPSILON;
+
+use utils::matrix::Matrix;
+
+
+/**
+* A beam search generator, keeping the beam_width most likely partial sequences
+* - beam_width : The number of sequences kept after every tick
+* - nb_flips : In independent mode, the candidates of a tick are the most likely chord and
+*   every combination of flips of its nb_flips most uncertain keys
+* - length_penalty : The alpha of the score's length normalization, 0.0 to disable it
+* - repetition_penalty : Subtracted from the log-likelihood every time a chord change
+*   repeats one made in the last repetition_window ticks
+*/
+pub struct BeamSearch {
+    pub beam_width : usize,
+    pub nb_flips : usize,
+    pub length_penalty : f64,
+    pub repetition_penalty : f64,
+    pub repetition_window : usize,
+}
+
+/**
+* A generated chord, the sequences are stored as a tree to avoid copying them
+*/
+struct Node {
+    chord : Matrix,
+    parent : Option<usize>,
+}
+
+/**
+* A partial sequence
+* - node : Its last chord's node, None if nothing was generated yet
+* - state : The cesure's CesureState after its last chord
+*/
+struct Beam {
+    node : Option<usize>,
+    state : CesureState,
+    log_likelihood : f64,
+    penalty : f64,
+}
+
+
+
+impl BeamSearch {
+
+    pub fn new(beam_width : usize, nb_flips : usize) -> BeamSearch {
+        assert!(beam_width > 0);
+        return BeamSearch {
+            beam_width : beam_width,
+            nb_flips : nb_flips,
+            length_penalty : 0.0,
+            repetition_penalty : 0.0,
+            repetition_window : 0,
+        }
+    }
+
+    /**
+    * Return the same beam search, with a length normalization of the scores
+    * @input length_penalty : The alpha of the normalization ((5+length)/6)^alpha
+    */
+    pub fn with_length_penalty(mut self, length_penalty : f64) -> BeamSearch {
+        self.length_penalty = length_penalty;
+        self
+    }
+
+    /**
+    * Return the same beam search, with a repetition penalty
+    * @input repetition_penalty : The penalty of a repeated chord change
+    * @input repetition_window : The number of past ticks the changes are searched in
+    */
+    pub fn with_repetition_penalty(mut self, repetition_penalty : f64, repetition_window : usize) -> BeamSearch {
+        self.repetition_penalty = repetition_penalty;
+        self.repetition_window = repetition_window;
+        self
+    }
+
+    /**
+    * Generate a music with beam search
+    * @input cesure : The cesure, it isn't modified
+    * @input infos : A matrix representing the music infos
+    * @input inject_sequence : The notes sequence to inject before computing
+    * @input nb_ticks : The number of ticks to generate
+    * @input constraints : The GenerationConstraints every candidate respects
+    * @return The most likely music found
+    */
+    pub fn compute_music<O: Gate, M: Gate>(&self, cesure : &Cesure<O, M>, infos : &Matrix, inject_sequence : &Vec<Matrix>, nb_ticks : usize,
+                                           constraints : &GenerationConstraints) -> CesureMusic {
+        let mut chords = self.search(cesure, infos, inject_sequence, nb_ticks, constraints).swap_remove(0);
+        let mut music = CesureMusic {
+            infos : infos.clone(),
+            chords : inject_sequence.clone(),
+        };
+        music.chords.append(&mut chords);
+        music
+    }
+
+    /**
+    * Run the beam search, see compute_music
+    * @return The generated chords of every final beam, the best one first
+    */
+    fn search<O: Gate, M: Gate>(&self, cesure : &Cesure<O, M>, infos : &Matrix, inject_sequence : &Vec<Matrix>, nb_ticks : usize,
+                                constraints : &GenerationConstraints) -> Vec<Vec<Matrix>> {
+        let min_key = infos_min_key(infos);
+        let mut cesure = cesure.clone();
+        cesure.new_sequence(infos);
+        for chord in inject_sequence {
+            cesure.inject_next(chord);
+        }
+
+        let mut nodes : Vec<Node> = Vec::new();
+        let mut beams = vec![Beam {
+            node : None,
+            state : cesure.state(),
+            log_likelihood : 0.0,
+            penalty : 0.0,
+        }];
+
+        for tick_i in 0..nb_ticks {
+            let mut expanded = Vec::with_capacity(beams.len() * self.beam_width);
+            for beam in &beams {
+                cesure.set_state(&beam.state);
+                let previous_chords = self.previous_chords(&nodes, beam.node, inject_sequence, constraints.min_duration);
+                let output = cesure.compute_output();
+                let output = constraints.mask_output(&output, cesure.output_mode, min_key, &previous_chords);
+                let mut beam_chords : Vec<Matrix> = Vec::new();
+                for mut chord in self.candidates(&output, cesure.output_mode) {
+                    constraints.enforce(&mut chord, &output, min_key, &previous_chords);
+                    if beam_chords.iter().any(|other| other.datas == chord.datas) {
+                        continue;
+                    }
+                    beam_chords.push(chord.clone());
+                    let log_likelihood = chord_log_likelihood(&output, &chord, cesure.output_mode);
+                    let penalty = beam.penalty + self.repetition_penalty * self.nb_repetitions(&nodes, beam.node, &chord) as f64;
+                    cesure.set_state(&beam.state);
+                    cesure.inject_next(&chord);
+                    nodes.push(Node {
+                        chord : chord,
+                        parent : beam.node,
+                    });
+                    expanded.push(Beam {
+                        node : Some(nodes.len() - 1),
+                        state : cesure.state(),
+                        log_likelihood : beam.log_likelihood + log_likelihood,
+                        penalty : penalty,
+                    });
+                }
+            }
+            let length = tick_i + 1;
+            expanded.sort_by(|a, b| self.score(b, length).partial_cmp(&self.score(a, length)).unwrap_or(Ordering::Equal));
+            expanded.truncate(self.beam_width);
+            beams = expanded;
+        }
+
+        beams.iter().map(|beam| {
+            let mut chords = Vec::with_capacity(nb_ticks);
+            let mut node = beam.node;
+            while let Some(node_i) = node {
+                chords.push(nodes[node_i].chord.clone());
+                node = nodes[node_i].parent;
+            }
+            chords.reverse();
+            chords
+        }).collect()
+    }
+
+    /**
+    * Return the candidate chords of a tick, before the constraints are enforced
+    * @input output : The masked output
+    * @input output_mode : The cesure's OUTPUT_MODE_ constant
+    */
+    fn candidates(&self, output : &Matrix, output_mode : u8) -> Vec<Matrix> {
+        match output_mode {
+            OUTPUT_MODE_CATEGORICAL => {
+                let mut indexes : Vec<usize> = (0..CATEGORICAL_DIMENSION).collect();
+                indexes.sort_by(|&a, &b| output.datas[b].partial_cmp(&output.datas[a]).unwrap_or(Ordering::Equal));
+                indexes.truncate(self.beam_width);
+                indexes.iter().map(|&i| CesureMusic::categorical_to_chord(i)).collect()
+            }
+            _ => {
+                let mut best = output.clone();
+                for i in 0..output.len {
+                    best.datas[i] = if output.datas[i] > 0.5 { 1.0 } else { 0.0 };
+                }
+
+                let mut uncertain : Vec<usize> = (0..output.len).collect();
+                uncertain.sort_by(|&a, &b| (output.datas[a] - 0.5).abs().partial_cmp(&(output.datas[b] - 0.5).abs()).unwrap_or(Ordering::Equal));
+                uncertain.truncate(self.nb_flips);
+
+                let mut candidates = Vec::with_capacity(1 << uncertain.len());
+                for flips in 0..(1 << uncertain.len()) {
+                    let mut chord = best.clone();
+                    for (bit, &i) in uncertain.iter().enumerate() {
+                        if flips & (1 << bit) != 0 {
+                            chord.datas[i] = 1.0 - chord.datas[i];
+                        }
+                    }
+                    candidates.push(chord);
+                }
+                candidates
+            }
+        }
+    }
+
+    /**
+    * Return the number of times the change from the beam's last chord to the new chord
+    * was made in the last repetition_window ticks
+    */
+    fn nb_repetitions(&self, nodes : &Vec<Node>, last : Option<usize>, chord : &Matrix) -> usize {
+        if self.repetition_penalty == 0.0 {
+            return 0;
+        }
+        let last_i = match last {
+            Some(last_i) => last_i,
+            None => { return 0; },
+        };
+        if nodes[last_i].chord.datas == chord.datas {
+            return 0;
+        }
+        let mut nb_repetitions = 0;
+        let mut next_chord = &nodes[last_i].chord;
+        let mut node = nodes[last_i].parent;
+        let mut nb_ticks = 0;
+        while let Some(node_i) = node {
+            if nb_ticks >= self.repetition_window {
+                break;
+            }
+            if nodes[node_i].chord.datas == nodes[last_i].chord.datas && next_chord.datas == chord.datas {
+                nb_repetitions += 1;
+            }
+            next_chord = &nodes[node_i].chord;
+            node = nodes[node_i].parent;
+            nb_ticks += 1;
+        }
+        nb_repetitions
+    }
+
+    /**
+    * Return the last chords of a beam, including the injected ones, at most nb_chords of them
+    */
+    fn previous_chords(&self, nodes : &Vec<Node>, last : Option<usize>, inject_sequence : &Vec<Matrix>, nb_chords : usize) -> Vec<Matrix> {
+        let mut chords = Vec::with_capacity(nb_chords);
+        let mut node = last;
+        while let Some(node_i) = node {
+            if chords.len() >= nb_chords {
+                break;
+            }
+            chords.push(nodes[node_i].chord.clone());
+            node = nodes[node_i].parent;
+        }
+        for chord in inject_sequence.iter().rev() {
+            if chords.len() >= nb_chords {
+                break;
+            }
+            chords.push(chord.clone());
+        }
+        chords.reverse();
+        chords
+    }
+
+    fn score(&self, beam : &Beam, length : usize) -> f64 {
+        let normalization = ((5.0 + length as f64) / 6.0).powf(self.length_penalty);
+        (beam.log_likelihood - beam.penalty) / normalization
+    }
+
+}
+
+
+/**
+* Return the log-likelihood of a chord
+* @input output : The masked output the chord was decoded from
+* @input chord : The chord, once the constraints are enforced
+* @input output_mode : The cesure's OUTPUT_MODE_ constant
+*/
+fn chord_log_likelihood(output : &Matrix, chord : &Matrix, output_mode : u8) -> f64 {
+    match output_mode {
+        OUTPUT_MODE_CATEGORICAL => {
+            let categorical = CesureMusic::chord_to_categorical(chord);
+            let index = categorical.datas.iter().position(|&x| x > 0.5).unwrap();
+            output.datas[index].max(EPSILON).ln()
+        }
+        _ => {
+            let mut log_likelihood = 0.0;
+            for i in 0..chord.len {
+                let p = output.datas[i].clamp(EPSILON, 1.0 - EPSILON);
+                log_likelihood += match chord.datas[i] > 0.5 {
+                    true => p.ln(),
+                    false => (1.0 - p).ln(),
+                };
+            }
+            log_likelihood
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use network::cesure;
+    use network::constraints::MAJOR_SCALE;
+    use network::gate::memory_gate;
+    use network::music::new_infos;
+    use network::music::CHORD_DIMENSION;
+    use utils::random::new_rng;
+
+    fn chord(keys : &[usize]) -> Matrix {
+        let mut chord = Matrix::new_row(CHORD_DIMENSION);
+        for &key in keys {
+            chord.datas[key] = 1.0;
+        }
+        chord
+    }
+
+    fn tiny_cesure(output_mode : u8) -> Cesure {
+        Cesure::new(4, output_mode, memory_gate::MEMORY_TYPE_GRU, &mut new_rng(Some(38)))
+    }
+
+    #[test]
+    fn a_single_beam_is_greedy() {
+        let infos = new_infos(480.0, 8, 36);
+        let inject_sequence = vec![chord(&[0, 4, 7])];
+        for &output_mode in &[cesure::OUTPUT_MODE_INDEPENDENT, cesure::OUTPUT_MODE_CATEGORICAL] {
+            let mut cesure = tiny_cesure(output_mode);
+            let music = BeamSearch::new(1, 3).compute_music(&cesure, &infos, &inject_sequence, 8, &GenerationConstraints::none());
+
+            cesure.new_sequence(&infos);
+            cesure.inject_next(&inject_sequence[0]);
+            let mut greedy = inject_sequence.clone();
+            for _ in 0..8 {
+                let output = cesure.compute_output();
+                let chord = match output_mode {
+                    cesure::OUTPUT_MODE_CATEGORICAL => {
+                        let mut best = 0;
+                        for i in 0..output.len {
+                            if output.datas[i] > output.datas[best] {
+                                best = i;
+                            }
+                        }
+                        CesureMusic::categorical_to_chord(best)
+                    }
+                    _ => {
+                        let mut chord = output.clone();
+                        for x in chord.datas.iter_mut() {
+                            *x = if *x > 0.5 { 1.0 } else { 0.0 };
+                        }
+                        chord
+                    }
+                };
+                cesure.inject_next(&chord);
+                greedy.push(chord);
+            }
+
+            assert_eq!(music.chords.len(), greedy.len());
+            for (chord, greedy_chord) in music.chords.iter().zip(greedy.iter()) {
+                assert_eq!(chord.datas, greedy_chord.datas);
+            }
+        }
+    }
+
+    #[test]
+    fn every_beam_meets_the_constraints() {
+        let min_key = 36;
+        let infos = new_infos(480.0, 12, min_key);
+        let inject_sequence = vec![chord(&[0, 4, 7])];
+        let constraints = GenerationConstraints::none()
+            .with_scale(0, &MAJOR_SCALE)
+            .with_key_range(48, 72)
+            .with_max_notes(8)
+            .with_min_duration(2);
+        let cesure = tiny_cesure(cesure::OUTPUT_MODE_INDEPENDENT);
+        let beams = BeamSearch::new(4, 3).search(&cesure, &infos, &inject_sequence, 12, &constraints);
+        assert!(beams.len() > 1 && beams.len() <= 4);
+
+        for chords in &beams {
+            assert_eq!(chords.len(), 12);
+            for (tick, chord) in chords.iter().enumerate() {
+                let played : Vec<usize> = (0..CHORD_DIMENSION).filter(|&i| chord.datas[i] > 0.5).collect();
+                assert!(played.len() <= 8);
+                for &key_i in &played {
+                    assert!(constraints.is_allowed(key_i, min_key));
+                }
+                // A note started on the tick before is still played
+                if tick > 0 {
+                    let previous = &chords[tick - 1];
+                    let before = if tick > 1 { &chords[tick - 2] } else { &inject_sequence[0] };
+                    for key_i in 0..CHORD_DIMENSION {
+                        if previous.datas[key_i] > 0.5 && before.datas[key_i] <= 0.5 {
+                            assert!(chord.datas[key_i] > 0.5);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
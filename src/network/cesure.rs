@@ -3,106 +3,338 @@ extern crate rand;
 
 use std::clone::Clone;
 use std::ops::Add;
-use std::fs;
+use std::f64;
+
+use rand::Rng;
 
 use network::gate::activation::Activation;
 use network::gate::activation;
-use network::gate::feedforward_gate;
 use network::gate::feedforward_gate::FeedforwardGate;
+use network::gate::lstm_gate::LstmGate;
+use network::gate::gru_gate::GruGate;
+use network::gate::memory_gate;
+use network::gate::memory_gate::MemoryGate;
+use network::gate::traits::Gate;
+use network::gate::traits::GateOutput;
+use network::gate::weight_init::WeightInit;
 use network::training::training_set::TrainingSet;
+use network::training::training_set::SetWeighting;
 use network::training::error_calculation::ErrorCalculation;
+use network::training::error_calculation::Loss;
 use network::training::error_calculation;
 use network::training::genetic;
 use network::training::backpropagation;
+use network::training::backpropagation::BatchConfig;
+use network::training::corpus::SPLIT_TRAIN;
+use network::training::corpus::Corpus;
+use network::sampler::Sampler;
+use network::sampler;
+use network::constraints::GenerationConstraints;
+use network::conditioning::ConditioningSchema;
+use network::conditioning::Conditioning;
+use network::recurrent_level::RecurrentLevel;
+use network::attention;
+use network::attention::Attention;
+use network::positional_encoding::PositionalEncoding;
 
 use network::music::CesureMusic;
-use network::music::DIVISION_RANGE;
-use network::music::NB_TICKS_RANGE;
-use network::music::MIN_KEY_RANGE;
 use network::music::INFOS_DIMENSION;
 use network::music::CHORD_DIMENSION;
+use network::music::CATEGORICAL_DIMENSION;
+use network::music::infos_min_key;
+use network::music::set_infos_nb_ticks;
+use network::music::new_infos;
+use network::music::Meter;
 
 use utils::matrix::Matrix;
 use utils::matrix_math::row_concatenate;
+use utils::matrix_math::row_slice;
+use utils::matrix_math::row_softmax;
+use utils::matrix_math::row_argmax;
 use utils::traits::Parse;
 
 
+// Each key is an independent sigmoid output, any number of notes can be played at once
+pub const OUTPUT_MODE_INDEPENDENT : u8 = 0;
+// A softmax over the keys and a rest token, trained with cross-entropy, one note per tick
+pub const OUTPUT_MODE_CATEGORICAL : u8 = 1;
+
 
-pub struct Cesure {
+/**
+* The network, generic over its output and memory gates' types
+* A Cesure without types is the one built by Cesure::new, with a FeedforwardGate
+* output gate and a MemoryGate chosen when it's created
+* The context is the first level's context, computed by the memory gate every tick,
+* followed by the upper levels' contexts, context_dimension being their total
+* With an attention, the output gate is also given the vector it attends to in the
+* history, the past contexts or chords of the sequence, and with a positional encoding
+* the tick's position in its beat and bar, appended after it
+*/
+pub struct Cesure<O : Gate = FeedforwardGate, M : Gate = MemoryGate> {
     pub infos_dimension : usize,
     pub context_dimension : usize,
     pub output_dimension : usize,
-    pub output_gate : FeedforwardGate,
-    pub memory_gate : FeedforwardGate,
+    pub output_mode : u8,
+    pub conditioning : ConditioningSchema,
+    pub output_gate : O,
+    pub memory_gate : M,
+    pub levels : Vec<RecurrentLevel<M>>,
+    pub attention : Option<Attention>,
+    pub positional_encoding : Option<PositionalEncoding>,
     pub infos : Matrix,
     pub context : Matrix,
+    pub tick : usize,
+    pub history : Vec<Matrix>,
 }
 
-pub struct VerboseOutput {
-    pub output_out : feedforward_gate::VerboseOutput,
-    pub memory_out : feedforward_gate::VerboseOutput,
+pub struct VerboseOutput<O : Gate = FeedforwardGate, M : Gate = MemoryGate> {
+    pub attention_out : Option<attention::VerboseOutput>,
+    pub output_out : O::VerboseOutput,
+    pub memory_out : M::VerboseOutput,
+    pub levels_out : Vec<Option<M::VerboseOutput>>,
+    pub output : Matrix,
+}
+
+/**
+* What a sequence changes in Cesure, to go back to a previous tick
+* - context : The context
+* - tick : The tick, counted from the beginning of the sequence
+* - history : The items the attention attends to
+*/
+pub struct CesureState {
+    pub context : Matrix,
+    pub tick : usize,
+    pub history : Vec<Matrix>,
 }
 
 
 
-impl Clone for Cesure {
-    fn clone(&self) -> Cesure {
+impl Clone for CesureState {
+    fn clone(&self) -> CesureState {
+        return CesureState {
+            context : self.context.clone(),
+            tick : self.tick,
+            history : self.history.clone(),
+        }
+    }
+    fn clone_from(&mut self, source: &CesureState) {
+        self.context.clone_from(&source.context);
+        self.tick = source.tick;
+        self.history.clone_from(&source.history);
+    }
+}
+impl<O : Gate, M : Gate> Clone for Cesure<O, M> {
+    fn clone(&self) -> Cesure<O, M> {
         return Cesure {
             infos_dimension : self.infos_dimension,
             context_dimension : self.context_dimension,
             output_dimension : self.output_dimension,
+            output_mode : self.output_mode,
+            conditioning : self.conditioning.clone(),
             output_gate : self.output_gate.clone(),
             memory_gate : self.memory_gate.clone(),
+            levels : self.levels.clone(),
+            attention : self.attention.clone(),
+            positional_encoding : self.positional_encoding.clone(),
             infos : self.infos.clone(),
             context : self.context.clone(),
+            tick : self.tick,
+            history : self.history.clone(),
         }
     }
-    fn clone_from(&mut self, source: &Cesure) {
+    fn clone_from(&mut self, source: &Cesure<O, M>) {
         self.infos_dimension = source.infos_dimension;
         self.context_dimension = source.context_dimension;
         self.output_dimension = source.output_dimension;
+        self.output_mode = source.output_mode;
+        self.conditioning = source.conditioning.clone();
         self.output_gate.clone_from(&source.output_gate);
         self.memory_gate.clone_from(&source.memory_gate);
+        self.levels.clone_from(&source.levels);
+        self.attention.clone_from(&source.attention);
+        self.positional_encoding.clone_from(&source.positional_encoding);
         self.infos.clone_from(&source.infos);
         self.context.clone_from(&source.context);
+        self.tick = source.tick;
+        self.history.clone_from(&source.history);
     }
 }
-impl Parse for Cesure {
+impl<O : Gate, M : Gate> Parse for Cesure<O, M> {
     fn to_string(&self) -> String {
-        let mut output = format!("{} {} {}", self.infos_dimension, self.context_dimension, self.output_dimension);
+        let mut output = format!("{} {} {} {}", self.infos_dimension, self.context_dimension, self.output_dimension, output_mode_to_string(self.output_mode));
+        if !self.conditioning.fields.is_empty() {
+            output = output.add("\n");
+            output = output.add(self.conditioning.to_string().as_str());
+        }
         output = output.add("\nOUTPUT_GATE\n");
         output = output.add(self.output_gate.to_string().as_str());
         output = output.add("\nMEMORY_GATE\n");
         output = output.add(self.memory_gate.to_string().as_str());
+        for level in &self.levels {
+            output = output.add("\nLEVEL ");
+            output = output.add(level.to_string().as_str());
+        }
+        if let Some(ref positional_encoding) = self.positional_encoding {
+            output = output.add("\nPOSITIONS ");
+            output = output.add(positional_encoding.to_string().as_str());
+        }
+        if let Some(ref attention) = self.attention {
+            output = output.add("\nATTENTION ");
+            output = output.add(attention.to_string().as_str());
+        }
         return output;
     }
-    fn from_string(str : &str) -> Cesure {
+    fn from_string(str : &str) -> Cesure<O, M> {
         let lines : Vec<&str> = str.split("\n").collect();
 
         let header : Vec<&str> = lines[0].split(" ").collect();
-        assert!(header.len() == 3);
+        assert!(header.len() == 3 || header.len() == 4);
         let infos_dimension = header[0].parse().unwrap();
         let context_dimension = header[1].parse().unwrap();
         let output_dimension = header[2].parse().unwrap();
+        let output_mode = match header.len() {
+            4 => output_mode_from_string(header[3]),
+            _ => OUTPUT_MODE_INDEPENDENT,
+        };
+        let conditioning = match lines[1].starts_with("CONDITIONING") {
+            true => ConditioningSchema::from_string(lines[1]),
+            false => ConditioningSchema::none(),
+        };
+        assert!(conditioning.dimension() == infos_dimension);
 
         let gates_str : Vec<&str> = str.split("\nOUTPUT_GATE\n").collect();
         assert!(gates_str.len() == 2);
         let gates_str : Vec<&str> = gates_str[1].split("\nMEMORY_GATE\n").collect();
         assert!(gates_str.len() == 2);
 
-        let output_gate = FeedforwardGate::from_string(gates_str[0]);
-        let memory_gate = FeedforwardGate::from_string(gates_str[1]);
+        let output_gate = O::from_string(gates_str[0]);
+        let attention_str : Vec<&str> = gates_str[1].split("\nATTENTION ").collect();
+        assert!(attention_str.len() <= 2);
+        let attention = attention_str.get(1).map(|attention_str| Attention::from_string(attention_str));
+        let positions_str : Vec<&str> = attention_str[0].split("\nPOSITIONS ").collect();
+        assert!(positions_str.len() <= 2);
+        let positional_encoding = positions_str.get(1).map(|positions_str| PositionalEncoding::from_string(positions_str));
+        let levels_str : Vec<&str> = positions_str[0].split("\nLEVEL ").collect();
+        let memory_gate = M::from_string(levels_str[0]);
+        let levels = levels_str[1..].iter().map(|level_str| RecurrentLevel::from_string(level_str)).collect();
+
+        let cesure = Cesure::from_gates(output_mode, conditioning, output_gate, memory_gate, levels, attention, positional_encoding);
+        assert!(cesure.context_dimension == context_dimension && cesure.output_dimension == output_dimension);
+        return cesure;
+    }
+}
+
 
-        return Cesure {
-            infos_dimension : infos_dimension,
+
+/**
+* Describe a new Cesure, see Cesure::from_config
+* - context_dimension : The first level's context dimension
+* - output_mode : OUTPUT_MODE_INDEPENDENT or OUTPUT_MODE_CATEGORICAL
+* - memory_type : MEMORY_TYPE_FEEDFORWARD, MEMORY_TYPE_LSTM or MEMORY_TYPE_GRU, a LSTM keeps
+*   its hidden and cell states in the context, each of half its dimension
+* - levels : The (context dimension, period in ticks) of each upper level, from the lowest,
+*   their memory gates being of the same type
+* - attention : The (ATTENTION_TYPE_CONTEXT or ATTENTION_TYPE_CHORD, window) of the attention,
+*   None for no attention
+* - positional_encoding : The PositionalEncoding of the ticks given to the output gate, None for no positional features
+* - output_init : The weights initialization of the output gate and the attention
+* - memory_init : The weights initialization of the memory gate and the levels' gates
+* - conditioning : The ConditioningSchema of the infos
+*/
+pub struct CesureConfig {
+    pub context_dimension : usize,
+    pub output_mode : u8,
+    pub memory_type : u8,
+    pub levels : Vec<(usize, usize)>,
+    pub attention : Option<(u8, usize)>,
+    pub positional_encoding : Option<PositionalEncoding>,
+    pub output_init : WeightInit,
+    pub memory_init : WeightInit,
+    pub conditioning : ConditioningSchema,
+}
+
+impl CesureConfig {
+
+    /**
+    * A cesure without upper levels, attention, positional features nor conditioning,
+    * whose gates get the default weights initialization
+    */
+    pub fn new(context_dimension : usize, output_mode : u8, memory_type : u8) -> CesureConfig {
+        return CesureConfig {
             context_dimension : context_dimension,
-            output_dimension : output_dimension,
-            output_gate : output_gate,
-            memory_gate : memory_gate,
-            infos : Matrix::new_row(infos_dimension),
-            context : Matrix::new_row(context_dimension),
+            output_mode : output_mode,
+            memory_type : memory_type,
+            levels : Vec::new(),
+            attention : None,
+            positional_encoding : None,
+            output_init : WeightInit::default(),
+            memory_init : WeightInit::default(),
+            conditioning : ConditioningSchema::none(),
+        }
+    }
+
+    pub fn with_levels(mut self, levels : Vec<(usize, usize)>) -> CesureConfig {
+        self.levels = levels;
+        self
+    }
+
+    pub fn with_attention(mut self, attention_type : u8, window : usize) -> CesureConfig {
+        self.attention = Some((attention_type, window));
+        self
+    }
+
+    pub fn with_positional_encoding(mut self, positional_encoding : PositionalEncoding) -> CesureConfig {
+        self.positional_encoding = Some(positional_encoding);
+        self
+    }
+
+    pub fn with_weight_init(mut self, output_init : WeightInit, memory_init : WeightInit) -> CesureConfig {
+        self.output_init = output_init;
+        self.memory_init = memory_init;
+        self
+    }
+
+    pub fn with_conditioning(mut self, conditioning : ConditioningSchema) -> CesureConfig {
+        self.conditioning = conditioning;
+        self
+    }
+
+}
+
+
+
+/**
+* How the chords of a generated music are computed
+* - sampler : The Sampler used to decode the outputs
+* - constraints : The GenerationConstraints the computed chords respect
+* - rand : The random number generator used by the sampler
+*/
+pub struct Generation<'a, R : Rng + 'a> {
+    pub sampler : &'a Sampler,
+    pub constraints : &'a GenerationConstraints,
+    pub rand : &'a mut R,
+}
+
+impl<'a, R : Rng + 'a> Generation<'a, R> {
+
+    pub fn new(sampler : &'a Sampler, constraints : &'a GenerationConstraints, rand : &'a mut R) -> Generation<'a, R> {
+        return Generation {
+            sampler : sampler,
+            constraints : constraints,
+            rand : rand,
         }
     }
+
+    /**
+    * Generate the next chord of a cesure's current sequence, see Cesure::generate_next
+    * @input cesure : The generating Cesure
+    * @input previous_chords : The chords of the sequence before this tick
+    * @return The generated chord
+    */
+    pub fn next_chord<O : Gate, M : Gate>(&mut self, cesure : &mut Cesure<O, M>, previous_chords : &[Matrix]) -> Matrix {
+        cesure.generate_next(self.sampler, self.constraints, previous_chords, self.rand)
+    }
+
 }
 
 
@@ -112,38 +344,131 @@ impl Cesure {
 
     /**
     * Constructor
+    * @input context_dimension : The context dimension
+    * @input output_mode : OUTPUT_MODE_INDEPENDENT or OUTPUT_MODE_CATEGORICAL
+    * @input memory_type : MEMORY_TYPE_FEEDFORWARD, MEMORY_TYPE_LSTM or MEMORY_TYPE_GRU
+    * @input rand : The random number generator used to initialize the weights
+    */
+    pub fn new<R: Rng>(context_dimension : usize, output_mode : u8, memory_type : u8, rand : &mut R) -> Cesure {
+        Cesure::from_config(&CesureConfig::new(context_dimension, output_mode, memory_type), rand)
+    }
+
+    /**
+    * Constructor of a cesure described by a CesureConfig
+    * @input config : The CesureConfig
+    * @input rand : The random number generator used to initialize the weights
     */
-    pub fn new(context_dimension : usize) -> Cesure {
-        let infos_context_dimension = INFOS_DIMENSION + context_dimension;
-        let infos_context_output_dimension = infos_context_dimension + CHORD_DIMENSION;
+    pub fn from_config<R: Rng>(config : &CesureConfig, rand : &mut R) -> Cesure {
+        let context_dimension = config.context_dimension;
+        assert!(config.conditioning.fields.iter().all(|field| field.dimension() > 0), "A conditioning label field has no labels");
+        let infos_dimension = config.conditioning.dimension();
+        let (output_dimension, output_activation) = match config.output_mode {
+            OUTPUT_MODE_INDEPENDENT => (CHORD_DIMENSION, activation::TYPE_SIGMOID),
+            OUTPUT_MODE_CATEGORICAL => (CATEGORICAL_DIMENSION, activation::TYPE_LINEAR), // logits, the softmax is applied by Cesure
+            _ => panic!("Unknown output mode"),
+        };
+        let mut output_activations = vec![Activation::new(activation::TYPE_SIGMOID); 10];
+        output_activations[9] = Activation::new(output_activation);
+        let total_context_dimension = context_dimension + config.levels.iter().map(|&(dimension, _)| dimension).sum::<usize>();
+        let infos_context_dimension = infos_dimension + total_context_dimension;
+        let infos_context_output_dimension = infos_context_dimension + output_dimension;
+        let attention = config.attention.map(|(attention_type, window)| {
+            let item_dimension = match attention_type {
+                attention::ATTENTION_TYPE_CONTEXT => total_context_dimension,
+                _ => output_dimension,
+            };
+            Attention::new(attention_type, window, infos_context_dimension, item_dimension, attention::DEFAULT_KEY_DIMENSION, &config.output_init, rand)
+        });
+        let attended_dimension = attention.as_ref().map_or(0, |attention| attention.item_dimension);
+        let positions_dimension = config.positional_encoding.as_ref().map_or(0, |positional_encoding| positional_encoding.dimension());
+        //let output_gate = FeedforwardGate::new2(infos_context_dimension, CHORD_DIMENSION, hidden_layers_dimensions.clone(), Activation::new(&ActivationType::Sigmoid));
+        //let memory_gate = FeedforwardGate::new2(infos_context_output_dimension, context_dimension, hidden_layers_dimensions.clone(), Activation::new(&ActivationType::Sigmoid));
+        let output_gate = FeedforwardGate::new_auto(infos_context_dimension + attended_dimension + positions_dimension, output_dimension, 10, output_activations, &config.output_init, rand);
+        let memory_gate = new_memory_gate(config.memory_type, infos_context_output_dimension, context_dimension, infos_dimension, &config.memory_init, rand);
+        let mut upper_levels = Vec::with_capacity(config.levels.len());
+        let mut state_offset = infos_dimension + context_dimension;
+        for &(level_dimension, period) in &config.levels {
+            let level_gate = new_memory_gate(config.memory_type, infos_context_dimension, level_dimension, state_offset, &config.memory_init, rand);
+            upper_levels.push(RecurrentLevel::new(level_dimension, period, level_gate));
+            state_offset += level_dimension;
+        }
+        Cesure::from_gates(config.output_mode, config.conditioning.clone(), output_gate, memory_gate, upper_levels, attention, config.positional_encoding.clone())
+    }
+
+}
+
+
+
+impl<O : Gate, M : Gate> Cesure<O, M> {
+
+    /**
+    * Constructor from already built gates
+    * @input output_mode : OUTPUT_MODE_INDEPENDENT or OUTPUT_MODE_CATEGORICAL
+    * @input conditioning : The ConditioningSchema of the infos
+    * @input output_gate : The output gate, from infos ++ context (++ attended vector) (++ position) to the output
+    * @input memory_gate : The memory gate, from infos ++ context ++ output to the first level's next context
+    * @input levels : The upper RecurrentLevel objects, from the lowest
+    * @input attention : The Attention, from infos ++ context and the history to the attended vector, None for no attention
+    * @input positional_encoding : The PositionalEncoding of the ticks, None for no positional features
+    */
+    pub fn from_gates(output_mode : u8, conditioning : ConditioningSchema, output_gate : O, memory_gate : M, levels : Vec<RecurrentLevel<M>>,
+                      attention : Option<Attention>, positional_encoding : Option<PositionalEncoding>) -> Cesure<O, M> {
+        let infos_dimension = conditioning.dimension();
+        let context_dimension = memory_gate.output_dimension() + levels.iter().map(|level| level.context_dimension).sum::<usize>();
+        let output_dimension = output_gate.output_dimension();
+        let attended_dimension = attention.as_ref().map_or(0, |attention| attention.item_dimension);
+        let positions_dimension = positional_encoding.as_ref().map_or(0, |positional_encoding| positional_encoding.dimension());
+        assert!(output_gate.input_dimension() == infos_dimension + context_dimension + attended_dimension + positions_dimension);
+        assert!(memory_gate.input_dimension() == infos_dimension + context_dimension + output_dimension);
+        assert!(levels.iter().all(|level| level.memory_gate.input_dimension() == infos_dimension + context_dimension));
+        if let Some(ref attention) = attention {
+            assert!(attention.query_dimension == infos_dimension + context_dimension);
+            assert!(attention.item_dimension == match attention.attention_type {
+                attention::ATTENTION_TYPE_CONTEXT => context_dimension,
+                _ => output_dimension,
+            });
+        }
         return Cesure {
-            infos_dimension : INFOS_DIMENSION,
+            infos_dimension : infos_dimension,
             context_dimension : context_dimension,
-            output_dimension : CHORD_DIMENSION,
-            //output_gate : FeedforwardGate::new2(infos_context_dimension, CHORD_DIMENSION, hidden_layers_dimensions.clone(), Activation::new(&ActivationType::Sigmoid)),
-            //memory_gate : FeedforwardGate::new2(infos_context_output_dimension, context_dimension, hidden_layers_dimensions.clone(), Activation::new(&ActivationType::Sigmoid)),
-            output_gate : FeedforwardGate::new_auto(infos_context_dimension, CHORD_DIMENSION, 10, Activation::new(activation::TYPE_SIGMOID)),
-            memory_gate : FeedforwardGate::new_auto(infos_context_output_dimension, context_dimension, 10, Activation::new(activation::TYPE_SIGMOID)),
-            infos : Matrix::new_row(INFOS_DIMENSION),
+            output_dimension : output_dimension,
+            output_mode : output_mode,
+            conditioning : conditioning,
+            output_gate : output_gate,
+            memory_gate : memory_gate,
+            levels : levels,
+            attention : attention,
+            positional_encoding : positional_encoding,
+            infos : Matrix::new_row(infos_dimension),
             context : Matrix::new_row(context_dimension),
+            tick : 0,
+            history : Vec::new(),
         }
     }
 
     /**
     * Return a cloned object with some random changes
     * @input magnitude : The amount of random to apply
-    * @input rand : The ThreadRng object to use for random
+    * @input rand : The random number generator
     * @return The cloned Cesure object
     */
-    pub fn clone_randomized(&self, magnitude : f64, rand : &mut rand::ThreadRng) -> Cesure {
+    pub fn clone_randomized<R: Rng>(&self, magnitude : f64, rand : &mut R) -> Cesure<O, M> {
         return Cesure {
             infos_dimension : self.infos_dimension,
             context_dimension : self.context_dimension,
             output_dimension : self.output_dimension,
+            output_mode : self.output_mode,
+            conditioning : self.conditioning.clone(),
             output_gate : self.output_gate.clone_randomized(magnitude, rand),
             memory_gate : self.memory_gate.clone_randomized(magnitude, rand),
+            levels : self.levels.iter().map(|level| RecurrentLevel::new(level.context_dimension, level.period,
+                                                                       level.memory_gate.clone_randomized(magnitude, rand))).collect(),
+            attention : self.attention.as_ref().map(|attention| attention.clone_randomized(magnitude, rand)),
+            positional_encoding : self.positional_encoding.clone(),
             infos : self.infos.clone(),
             context : self.context.clone(),
+            tick : self.tick,
+            history : self.history.clone(),
         }
     }
 
@@ -159,6 +484,14 @@ impl Cesure {
         self.output_gate.print();
         println!("- Memory Gate :");
         self.memory_gate.print();
+        for (i, level) in self.levels.iter().enumerate() {
+            println!("- Level #{} Memory Gate (every {} ticks) :", i+1, level.period);
+            level.memory_gate.print();
+        }
+        if let Some(ref attention) = self.attention {
+            println!("- Attention (over {} ticks) :", attention.window);
+            attention.print();
+        }
     }
 
     /**
@@ -167,6 +500,8 @@ impl Cesure {
     */
     pub fn get_nb_neurons(&self) -> usize {
         self.output_gate.get_nb_neurons() + self.memory_gate.get_nb_neurons()
+            + self.levels.iter().map(|level| level.memory_gate.get_nb_neurons()).sum::<usize>()
+            + self.attention.as_ref().map_or(0, |attention| attention.get_nb_neurons())
     }
 
     /**
@@ -174,9 +509,261 @@ impl Cesure {
     * @input infos : The infos to use for this new sequence
     */
     pub fn new_sequence(&mut self, infos : &Matrix) {
-        assert!(infos.is_row() && infos.len == INFOS_DIMENSION);
+        assert!(infos.is_row() && infos.len == self.infos_dimension);
         self.infos.clone_from(infos);
         self.context.set_zero();
+        self.tick = 0;
+        self.history.clear();
+    }
+
+    /**
+    * @return The current CesureState, to be restored with set_state
+    */
+    pub fn state(&self) -> CesureState {
+        return CesureState {
+            context : self.context.clone(),
+            tick : self.tick,
+            history : self.history.clone(),
+        }
+    }
+
+    /**
+    * Go back to a previous tick of the sequence
+    * @input state : The CesureState returned by state()
+    */
+    pub fn set_state(&mut self, state : &CesureState) {
+        self.context.clone_from(&state.context);
+        self.tick = state.tick;
+        self.history.clone_from(&state.history);
+    }
+
+    /**
+    * Return where a level's context is in the context
+    * @input level : The level, 0 being the memory gate's and i the upper level levels[i-1]
+    * @return The level's first index in the context and the index following its last one
+    */
+    pub fn level_range(&self, level : usize) -> (usize, usize) {
+        let mut begin = 0;
+        let mut end = self.memory_gate.output_dimension();
+        for i in 0..level {
+            begin = end;
+            end += self.levels[i].context_dimension;
+        }
+        (begin, end)
+    }
+
+    /**
+    * Replace a level's context
+    * @input level : The level, 0 being the memory gate's
+    * @input level_context : The level's new context
+    */
+    fn set_level_context(&mut self, level : usize, level_context : &Matrix) {
+        let (begin, end) = self.level_range(level);
+        assert!(level_context.len == end - begin);
+        for i in begin..end {
+            self.context.datas[i] = level_context.datas[i - begin];
+        }
+    }
+
+    /**
+    * Add the tick's context or chord to the history the attention attends to,
+    * forgetting the items older than the attention's window
+    * @input infos_context_output : The infos, the context and the tick's output, before the context is updated
+    */
+    fn update_history(&mut self, infos_context_output : &Matrix) {
+        let (attention_type, window) = match self.attention {
+            Some(ref attention) => (attention.attention_type, attention.window),
+            None => return,
+        };
+        let (_, context_output) = row_slice(infos_context_output, self.infos_dimension);
+        let (context, output) = row_slice(&context_output, self.context_dimension);
+        self.history.push(match attention_type {
+            attention::ATTENTION_TYPE_CONTEXT => context,
+            _ => output,
+        });
+        if self.history.len() > window {
+            self.history.remove(0);
+        }
+    }
+
+    /**
+    * Build the output gate's input, appending the attended vector when there is an attention
+    * and the tick's position when there is a positional encoding
+    * @input infos_context : The infos and the context
+    * @return The output gate's input
+    */
+    fn output_gate_input(&self, infos_context : &Matrix) -> Matrix {
+        let input = match self.attention {
+            Some(ref attention) => row_concatenate(infos_context, &attention.compute(infos_context, &self.history)),
+            None => infos_context.clone(),
+        };
+        self.append_position(input)
+    }
+
+    /**
+    * Build the output gate's input, keeping the attention's VerboseOutput
+    * @input infos_context : The infos and the context
+    * @return The output gate's input and the attention's VerboseOutput, None when there is no attention
+    */
+    fn output_gate_input_verbose(&self, infos_context : &Matrix) -> (Matrix, Option<attention::VerboseOutput>) {
+        match self.attention {
+            Some(ref attention) => {
+                let attention_out = attention.compute_verbose(infos_context, &self.history);
+                (self.append_position(row_concatenate(infos_context, &attention_out.output)), Some(attention_out))
+            }
+            None => (self.append_position(infos_context.clone()), None),
+        }
+    }
+
+    /**
+    * Append the current tick's position to the output gate's input
+    * @input input : The output gate's input without the position
+    * @return The input followed by the position, or unchanged when there is no positional encoding
+    */
+    fn append_position(&self, mut input : Matrix) -> Matrix {
+        if let Some(ref positional_encoding) = self.positional_encoding {
+            input.row_concatenate(&positional_encoding.encode(self.tick));
+        }
+        input
+    }
+
+    /**
+    * Compute the next context and end the tick
+    * The first level is computed every tick, the upper levels at the end of their periods
+    * from the context whose lower levels are already updated
+    * @input infos_context_output : The infos, the context and the tick's output
+    */
+    fn update_context(&mut self, infos_context_output : &Matrix) {
+        self.update_history(infos_context_output);
+        let memory_out = self.memory_gate.compute(infos_context_output);
+        self.set_level_context(0, &memory_out);
+        for i in 0..self.levels.len() {
+            if self.levels[i].is_updated(self.tick) {
+                let infos_context = row_concatenate(&self.infos, &self.context);
+                let level_out = self.levels[i].memory_gate.compute(&infos_context);
+                self.set_level_context(i+1, &level_out);
+            }
+        }
+        self.tick += 1;
+    }
+
+    /**
+    * Compute the next context and end the tick, keeping the memory gates' VerboseOutput objects
+    * @input infos_context_output : The infos, the context and the tick's output
+    * @return The memory gate's VerboseOutput and the upper levels' ones, None for the levels not updated
+    */
+    fn update_context_verbose(&mut self, infos_context_output : &Matrix) -> (M::VerboseOutput, Vec<Option<M::VerboseOutput>>) {
+        self.update_history(infos_context_output);
+        let memory_out = self.memory_gate.compute_verbose(infos_context_output);
+        self.set_level_context(0, memory_out.output());
+        let mut levels_out = Vec::with_capacity(self.levels.len());
+        for i in 0..self.levels.len() {
+            if self.levels[i].is_updated(self.tick) {
+                let infos_context = row_concatenate(&self.infos, &self.context);
+                let level_out = self.levels[i].memory_gate.compute_verbose(&infos_context);
+                self.set_level_context(i+1, level_out.output());
+                levels_out.push(Some(level_out));
+            } else {
+                levels_out.push(None);
+            }
+        }
+        self.tick += 1;
+        (memory_out, levels_out)
+    }
+
+    /**
+    * Build infos following the cesure's conditioning schema
+    * @input base_infos : The three base infos, or infos following another schema
+    * @input conditioning : The values of the conditioning fields
+    * @return The infos Matrix
+    */
+    pub fn build_infos(&self, base_infos : &Matrix, conditioning : &Conditioning) -> Matrix {
+        self.conditioning.build_infos(base_infos, conditioning)
+    }
+
+    /**
+    * Apply the output head to the output gate's output
+    * In categorical mode, the output gate computes logits and the head is a softmax
+    * @input gate_output : The output gate's output
+    * @return The cesure's output
+    */
+    fn apply_output_head(&self, gate_output : &Matrix) -> Matrix {
+        match self.output_mode {
+            OUTPUT_MODE_CATEGORICAL => row_softmax(gate_output),
+            _ => gate_output.clone(),
+        }
+    }
+
+    /**
+    * Convert a chord to the representation used by the cesure's outputs
+    * @input chord : The chord to convert
+    * @return The chord, or its one-hot categorical row in categorical mode
+    */
+    pub fn chord_to_output(&self, chord : &Matrix) -> Matrix {
+        match self.output_mode {
+            OUTPUT_MODE_CATEGORICAL => CesureMusic::chord_to_categorical(chord),
+            _ => chord.clone(),
+        }
+    }
+
+    /**
+    * Return the loss of an output compared to the ideal chord, and its error signal
+    * In categorical mode the loss is the cross-entropy and the signal its gradient
+    * with respect to the output gate's logits, the error calculation isn't used
+    * Like the independent losses, the value is averaged over the outputs, so the errors
+    * of the two modes can be compared
+    * @input output : The cesure's output
+    * @input ideal_chord : The ideal chord
+    * @input error_calculation : The error calculation used in independent mode
+    * @return The Loss object
+    */
+    pub fn calculate_output_error(&self, output : &Matrix, ideal_chord : &Matrix, error_calculation : &ErrorCalculation) -> Loss {
+        match self.output_mode {
+            OUTPUT_MODE_CATEGORICAL => {
+                let target = CesureMusic::chord_to_categorical(ideal_chord);
+                let target_i = row_argmax(&target);
+                let value = -output.datas[target_i].max(error_calculation::EPSILON).ln() / CATEGORICAL_DIMENSION as f64;
+                let mut gradient = output.clone();
+                gradient.sub(&target);
+                Loss::new(value, gradient)
+            }
+            _ => error_calculation.calculate(output, ideal_chord),
+        }
+    }
+
+    /**
+    * Decode an output into a chord
+    * In independent mode the output is thresholded, in categorical mode one note
+    * (or a rest) is sampled from the softmax
+    * @input output : The cesure's output
+    * @input rand : The random number generator
+    * @return The decoded chord
+    */
+    pub fn decode_output<R: Rng>(&self, output : &Matrix, rand : &mut R) -> Matrix {
+        match self.output_mode {
+            OUTPUT_MODE_CATEGORICAL => CesureMusic::sample_categorical(output, rand),
+            _ => {
+                let mut chord = output.clone();
+                CesureMusic::normalize_chord(&mut chord);
+                chord
+            }
+        }
+    }
+
+    /**
+    * Decode an output into its most likely chord
+    * @input output : The cesure's output
+    * @return The decoded chord
+    */
+    pub fn decode_output_greedy(&self, output : &Matrix) -> Matrix {
+        match self.output_mode {
+            OUTPUT_MODE_CATEGORICAL => CesureMusic::categorical_to_chord(row_argmax(output)),
+            _ => {
+                let mut chord = output.clone();
+                CesureMusic::normalize_chord(&mut chord);
+                chord
+            }
+        }
     }
 
     /**
@@ -185,43 +772,112 @@ impl Cesure {
     */
     pub fn compute_next(&mut self) -> Matrix {
         let infos_context = row_concatenate(&self.infos, &self.context);
-        let output = self.output_gate.compute(&infos_context);
+        let output = self.output_gate.compute(&self.output_gate_input(&infos_context));
+        let output = self.apply_output_head(&output);
 
         let infos_context_output = row_concatenate(&infos_context, &output);
-        let memory_out = self.memory_gate.compute(&infos_context_output);
-        self.context = memory_out;
+        self.update_context(&infos_context_output);
 
         output
     }
 
+    /**
+    * Compute the next output of the current sequence, without changing the context
+    * The tick must then be ended with inject_next
+    * @return The computed output
+    */
+    pub fn compute_output(&self) -> Matrix {
+        let infos_context = row_concatenate(&self.infos, &self.context);
+        let output = self.output_gate.compute(&self.output_gate_input(&infos_context));
+        self.apply_output_head(&output)
+    }
+
     /**
     * Compute the next output of the current sequence, and return
     * a VerboseOutput object, containing all the computing infos
     * @return The VerboseOutput object
     */
-    pub fn compute_next_verbose(&mut self) -> VerboseOutput {
+    pub fn compute_next_verbose(&mut self) -> VerboseOutput<O, M> {
+        let infos_context = row_concatenate(&self.infos, &self.context);
+        let (output_gate_input, attention_out) = self.output_gate_input_verbose(&infos_context);
+        let output_out = self.output_gate.compute_verbose(&output_gate_input);
+        let output = self.apply_output_head(output_out.output());
+
+        let infos_context_output = row_concatenate(&infos_context, &output);
+        let (memory_out, levels_out) = self.update_context_verbose(&infos_context_output);
+
+        return VerboseOutput {
+            attention_out : attention_out,
+            output_out : output_out,
+            memory_out : memory_out,
+            levels_out : levels_out,
+            output : output,
+        }
+    }
+
+    /**
+    * Compute the next output of the current sequence, and return a VerboseOutput
+    * object, feeding the memory gate with a chord instead of the raw output
+    * @input chord : The chord to feed, None to feed the chord decoded from the output
+    * @input sampler : The Sampler decoding the output when no chord is given
+    * @input rand : The random number generator used by the sampler
+    * @return The VerboseOutput object
+    */
+    pub fn compute_next_verbose_fed<R: Rng>(&mut self, chord : Option<&Matrix>, sampler : &Sampler, rand : &mut R) -> VerboseOutput<O, M> {
         let infos_context = row_concatenate(&self.infos, &self.context);
-        let output = self.output_gate.compute_verbose(&infos_context);
+        let (output_gate_input, attention_out) = self.output_gate_input_verbose(&infos_context);
+        let output_out = self.output_gate.compute_verbose(&output_gate_input);
+        let output = self.apply_output_head(output_out.output());
 
-        let infos_context_output = row_concatenate(&infos_context, &output.output);
-        let memory_out = self.memory_gate.compute_verbose(&infos_context_output);
-        self.context.clone_from(&memory_out.output);
+        let fed_chord = match chord {
+            Some(chord) => self.chord_to_output(chord),
+            None => self.chord_to_output(&sampler.sample(&output, self.output_mode, rand)),
+        };
+        let infos_context_output = row_concatenate(&infos_context, &fed_chord);
+        let (memory_out, levels_out) = self.update_context_verbose(&infos_context_output);
 
         return VerboseOutput {
-            output_out : output,
+            attention_out : attention_out,
+            output_out : output_out,
             memory_out : memory_out,
+            levels_out : levels_out,
+            output : output,
+        }
+    }
+
+    /**
+    * Generate the next chord of the current sequence
+    * The output is masked by the constraints before being decoded, depending on the
+    * sampler's feedback, the memory gate is then fed the decoded chord or the raw output
+    * @input sampler : The Sampler decoding the output
+    * @input constraints : The GenerationConstraints the chord has to respect
+    * @input previous_chords : The chords of the sequence before this tick
+    * @input rand : The random number generator used by the sampler
+    * @return The generated chord
+    */
+    pub fn generate_next<R: Rng>(&mut self, sampler : &Sampler, constraints : &GenerationConstraints, previous_chords : &[Matrix], rand : &mut R) -> Matrix {
+        let output = match sampler.feedback {
+            sampler::FEEDBACK_OUTPUT => self.compute_next(),
+            _ => self.compute_output(),
+        };
+        let min_key = infos_min_key(&self.infos);
+        let masked = constraints.mask_output(&output, self.output_mode, min_key, previous_chords);
+        let mut chord = sampler.sample(&masked, self.output_mode, rand);
+        constraints.enforce(&mut chord, &masked, min_key, previous_chords);
+        if sampler.feedback != sampler::FEEDBACK_OUTPUT {
+            self.inject_next(&chord);
         }
+        chord
     }
 
     /**
     * Manually inject the next output, changing the context
-    * @input input : The input to inject
+    * @input input : The chord to inject
     */
     pub fn inject_next(&mut self, input : &Matrix) {
         let mut infos_context_output = row_concatenate(&self.infos, &self.context);
-        infos_context_output.row_concatenate(&input);
-        let memory_out = self.memory_gate.compute(&infos_context_output);
-        self.context = memory_out;
+        infos_context_output.row_concatenate(&self.chord_to_output(input));
+        self.update_context(&infos_context_output);
     }
 
     /**
@@ -237,9 +893,8 @@ impl Cesure {
         let mut error_sum = 0.0;
         for i in 0..training_set.compute_sequence.len() {
             let output = self.compute_next();
-            let error = (error_calculation.calculate)(&output, &training_set.compute_sequence[i]);
-            let error_avg = error.get_abs_avg();
-            error_sum += error_avg;
+            let error = self.calculate_output_error(&output, &training_set.compute_sequence[i], error_calculation);
+            error_sum += error.value;
             /*output.print_title(format!("#{}, Output :", i).as_str());
             training_set.compute_sequence[i].print_title(format!("#{}, Ideal :", i).as_str());
             error.print_title("Error :");
@@ -261,15 +916,34 @@ impl Cesure {
         error_sum
     }
 
+    /**
+    * Return the weighted error of this network, on a Vec<TrainingSet>
+    * The sets aren't drawn, every set is used
+    * @input training_sets : The TrainingSet objects to calculate the error on
+    * @input error_calculation : The error calculation
+    * @input weighting : The SetWeighting
+    * @return The error
+    */
+    pub fn calculate_error_weighted(&mut self, training_sets : &Vec<TrainingSet>, error_calculation: &ErrorCalculation, weighting : &SetWeighting) -> f64 {
+        let mut error_sum = 0.0;
+        for training_set in training_sets {
+            error_sum += weighting.tick_factor(training_set) * self.calculate_error_sum(training_set, error_calculation);
+        }
+        error_sum
+    }
+
     /**
     * Compute a music from a infos Matrix
+    * Every output is decoded by the sampler, see generate_next
     * @input infos : A matrix representing the music infos
     * @input inject_sequence : The notes sequence to inject before computing
     * @input nb_ticks : The music number of ticks
+    * @input generation : The sampler, constraints and random number generator computing the chords
     * @return The computed music
     */
-    pub fn compute_music_from_infos(&mut self, infos : &Matrix, inject_sequence: &Vec<Matrix>, nb_ticks : usize) -> CesureMusic {
-        self.new_sequence(&infos);
+    pub fn compute_music_from_infos<R: Rng>(&mut self, infos : &Matrix, inject_sequence: &Vec<Matrix>, nb_ticks : usize,
+                                            generation : &mut Generation<R>) -> CesureMusic {
+        self.new_sequence(infos);
         let mut output = CesureMusic {
             infos : infos.clone(),
             chords : Vec::new(),
@@ -280,9 +954,8 @@ impl Cesure {
             output.chords.push(inject_sequence[i].clone());
         }
         for _ in 0..nb_ticks {
-            let mut out = self.compute_next();
-            CesureMusic::normalize_chord(&mut out);
-            output.chords.push(out);
+            let chord = generation.next_chord(self, &output.chords);
+            output.chords.push(chord);
         }
 
         output
@@ -293,137 +966,289 @@ impl Cesure {
     * @input division : The music division
     * @input nb_ticks : The music number of ticks
     * @input min_key : The minimum note key
+    * @input conditioning : The values of the conditioning fields
+    * @input generation : The sampler, constraints and random number generator computing the chords
     * @return The computed music
     */
-    pub fn compute_music(&mut self, division: f64, nb_ticks: usize, min_key: usize) -> CesureMusic {
-        let infos = Matrix::new_row_from_datas(vec![division/DIVISION_RANGE, (nb_ticks as f64)/NB_TICKS_RANGE, (min_key as f64)/MIN_KEY_RANGE]);
-        self.new_sequence(&infos);
+    pub fn compute_music<R: Rng>(&mut self, division: f64, nb_ticks: usize, min_key: usize, conditioning : &Conditioning,
+                                 generation : &mut Generation<R>) -> CesureMusic {
+        let infos = self.build_infos(&new_infos(division, nb_ticks, min_key), conditioning);
+        self.compute_music_from_infos(&infos, &Vec::new(), nb_ticks, generation)
+    }
+
+    /**
+    * Compute a music lasting a number of bars
+    * @input division : The music division
+    * @input meter : The tempo and time signature
+    * @input nb_bars : The music number of bars
+    * @input min_key : The minimum note key
+    * @input conditioning : The values of the conditioning fields
+    * @input generation : The sampler, constraints and random number generator computing the chords
+    * @return The computed music
+    */
+    pub fn compute_music_bars<R: Rng>(&mut self, division: f64, meter : &Meter, nb_bars: f64, min_key: usize, conditioning : &Conditioning,
+                                      generation : &mut Generation<R>) -> CesureMusic {
+        self.compute_music(division, meter.bars_to_ticks(nb_bars), min_key, conditioning, generation)
+    }
+
+    /**
+    * Compute a music lasting a number of seconds
+    * @input division : The music division
+    * @input meter : The tempo and time signature
+    * @input seconds : The music duration
+    * @input min_key : The minimum note key
+    * @input conditioning : The values of the conditioning fields
+    * @input generation : The sampler, constraints and random number generator computing the chords
+    * @return The computed music
+    */
+    pub fn compute_music_seconds<R: Rng>(&mut self, division: f64, meter : &Meter, seconds: f64, min_key: usize, conditioning : &Conditioning,
+                                         generation : &mut Generation<R>) -> CesureMusic {
+        self.compute_music(division, meter.seconds_to_ticks(seconds), min_key, conditioning, generation)
+    }
+
+    /**
+    * Compute a music until it ends naturally : the computing stops once nb_silent_ticks
+    * silent ticks were computed in a row, the music then ends on the next bar line
+    * The infos' number of ticks is max_ticks, the expected length
+    * @input infos : A matrix representing the music infos
+    * @input inject_sequence : The notes sequence to inject before computing
+    * @input max_ticks : The maximum number of computed ticks
+    * @input nb_silent_ticks : The number of silent ticks ending the music
+    * @input meter : The time signature the bar lines follow
+    * @input generation : The sampler, constraints and random number generator computing the chords
+    * @return The computed music
+    */
+    pub fn compute_music_until_silence<R: Rng>(&mut self, infos : &Matrix, inject_sequence : &Vec<Matrix>, max_ticks : usize, nb_silent_ticks : usize,
+                                               meter : &Meter, generation : &mut Generation<R>) -> CesureMusic {
+        assert!(nb_silent_ticks > 0);
+        self.new_sequence(infos);
         let mut output = CesureMusic {
             infos : infos.clone(),
             chords : Vec::new(),
         };
 
-        for _ in 0..nb_ticks {
-            let mut out = self.compute_next();
-            CesureMusic::normalize_chord(&mut out);
-            output.chords.push(out);
+        for i in 0..inject_sequence.len() {
+            self.inject_next(&inject_sequence[i]);
+            output.chords.push(inject_sequence[i].clone());
+        }
+        let mut silence_len = 0;
+        for _ in 0..max_ticks {
+            let chord = generation.next_chord(self, &output.chords);
+            match chord.datas.iter().any(|&x| x > 0.5) {
+                true => { silence_len = 0; },
+                false => { silence_len += 1; },
+            }
+            output.chords.push(chord);
+            if silence_len >= nb_silent_ticks {
+                break;
+            }
         }
 
+        let music_len = output.chords.len() - silence_len;
+        let ticks_per_bar = meter.ticks_per_bar();
+        let bar_end = music_len.div_ceil(ticks_per_bar) * ticks_per_bar;
+        while output.chords.len() > bar_end {
+            output.chords.pop();
+        }
+        while output.chords.len() < bar_end {
+            output.chords.push(Matrix::new_row(CHORD_DIMENSION));
+        }
         output
     }
 
-    pub fn train_genetic_from_folder(&mut self, magnitude0 : f64, magnitude1 : f64, iterations : usize) {
-        let paths = fs::read_dir("./").unwrap();
-
-        let mut midi_paths = Vec::new();
-        for path in paths {
-            let path = path.unwrap().path();
-            print!("{}", path.display());
-            if path.display().to_string().ends_with(".mid") {
-                midi_paths.push(path);
-                println!(" is midi");
-            } else {
-                println!(" is not midi");
-            }
-        }
-
-        assert!(midi_paths.len() > 0);
-        println!("Found {} midi files to train on!", midi_paths.len());
+    /**
+    * Continue an existing music
+    * The infos' number of ticks is set to the length of the continued music
+    * @input music : The music to continue
+    * @input nb_injected : The number of the music's first ticks to inject, the others are dropped
+    * @input nb_ticks : The number of ticks to compute after them
+    * @input conditioning : The values of the conditioning fields
+    * @input generation : The sampler, constraints and random number generator computing the chords
+    * @return The injected ticks followed by the computed ones
+    */
+    pub fn continue_music<R: Rng>(&mut self, music : &CesureMusic, nb_injected : usize, nb_ticks : usize, conditioning : &Conditioning,
+                                  generation : &mut Generation<R>) -> CesureMusic {
+        assert!(nb_injected <= music.chords.len());
+        // The continued music is nb_injected + nb_ticks long
+        let mut music_infos = music.infos.clone();
+        set_infos_nb_ticks(&mut music_infos, nb_injected + nb_ticks);
+        let infos = self.build_infos(&music_infos, conditioning);
+        let inject_sequence = music.chords[..nb_injected].to_vec();
+        self.compute_music_from_infos(&infos, &inject_sequence, nb_ticks, generation)
+    }
 
-        let mut musics = Vec::with_capacity(midi_paths.len());
-        for path in &midi_paths {
-            musics.push(CesureMusic::from_path(path));
+    /**
+    * Compute again the ticks from begin (included) to end (excluded) of an existing music
+    * The ticks before begin are injected, then nb_candidates fillings are computed and the
+    * one after which the following ticks are the most likely is kept
+    * @input music : The music to fill
+    * @input begin : The first computed tick
+    * @input end : The tick following the last computed tick
+    * @input nb_candidates : The number of fillings to choose from
+    * @input conditioning : The values of the conditioning fields
+    * @input generation : The sampler, constraints and random number generator computing the chords
+    * @return The music, with the range replaced by the best filling
+    */
+    pub fn infill_music<R: Rng>(&mut self, music : &CesureMusic, begin : usize, end : usize, nb_candidates : usize, conditioning : &Conditioning,
+                                generation : &mut Generation<R>) -> CesureMusic {
+        assert!(begin <= end && end <= music.chords.len() && nb_candidates > 0);
+        let infos = self.build_infos(&music.infos, conditioning);
+        self.new_sequence(&infos);
+        for i in 0..begin {
+            self.inject_next(&music.chords[i]);
         }
-
-        let mut training_sets = Vec::with_capacity(musics.len());
-        for music in &musics {
-            training_sets.push(music.to_training_set(15));
+        let begin_state = self.state();
+
+        let mut best_filling = Vec::new();
+        let mut best_log_likelihood = f64::NEG_INFINITY;
+        for _ in 0..nb_candidates {
+            self.set_state(&begin_state);
+            let mut chords = music.chords[..begin].to_vec();
+            for _ in begin..end {
+                let chord = generation.next_chord(self, &chords);
+                chords.push(chord);
+            }
+            let mut log_likelihood = 0.0;
+            for i in end..music.chords.len() {
+                let output = self.compute_output();
+                log_likelihood += self.chord_log_likelihood(&output, &music.chords[i]);
+                self.inject_next(&music.chords[i]);
+            }
+            if best_filling.is_empty() || log_likelihood > best_log_likelihood {
+                best_log_likelihood = log_likelihood;
+                best_filling = chords.split_off(begin);
+            }
         }
 
-        let error_calc = ErrorCalculation::new(error_calculation::ERROR_CALCULATION_TYPE_SMART);
-        genetic::train(self, &training_sets, &error_calc, magnitude0, magnitude1, iterations);
-
+        let mut output = CesureMusic {
+            infos : infos,
+            chords : music.chords[..begin].to_vec(),
+        };
+        output.chords.append(&mut best_filling);
+        output.chords.extend_from_slice(&music.chords[end..]);
+        output
     }
-    pub fn train_backpropagation_from_folder(&mut self, learning_rate : f64, momentum : f64, iterations : usize) {
-        let paths = fs::read_dir("./").unwrap();
 
-        let mut midi_paths = Vec::new();
-        for path in paths {
-            let path = path.unwrap().path();
-            print!("{}", path.display());
-            if path.display().to_string().ends_with(".mid") {
-                midi_paths.push(path);
-                println!(" is midi");
-            } else {
-                println!(" is not midi");
+    /**
+    * Return the log-likelihood of a chord under an output
+    * @input output : The cesure's output
+    * @input chord : The chord
+    * @return The log-likelihood
+    */
+    pub fn chord_log_likelihood(&self, output : &Matrix, chord : &Matrix) -> f64 {
+        match self.output_mode {
+            OUTPUT_MODE_CATEGORICAL => {
+                let index = row_argmax(&CesureMusic::chord_to_categorical(chord));
+                output.datas[index].max(error_calculation::EPSILON).ln()
+            }
+            _ => {
+                let mut log_likelihood = 0.0;
+                for i in 0..CHORD_DIMENSION {
+                    let p = match chord.datas[i] > 0.5 {
+                        true => output.datas[i],
+                        false => 1.0 - output.datas[i],
+                    };
+                    log_likelihood += p.max(error_calculation::EPSILON).ln();
+                }
+                log_likelihood
             }
         }
+    }
 
-        assert!(midi_paths.len() > 0);
-        println!("Found {} midi files to train on!", midi_paths.len());
+    pub fn train_genetic_from_folder<R: Rng>(&mut self, magnitude0 : f64, magnitude1 : f64, iterations : usize, rand : &mut R) {
+        let corpus = Corpus::from_folder("./");
+        let training_sets = corpus.training_sets(self, SPLIT_TRAIN, 15);
+        assert!(!training_sets.is_empty());
+        println!("Found {} training sets to train on!", training_sets.len());
 
-        let mut musics = Vec::with_capacity(midi_paths.len());
-        for path in &midi_paths {
-            musics.push(CesureMusic::from_path(path));
-        }
+        let error_calc = ErrorCalculation::new(error_calculation::ERROR_CALCULATION_TYPE_SMART);
+        genetic::train(self, &training_sets, &error_calc, &SetWeighting::default(), (magnitude0, magnitude1), iterations, rand);
 
-        let mut training_sets = Vec::with_capacity(musics.len());
-        for music in &musics {
-            training_sets.push(music.to_training_set(15));
-        }
+    }
+    pub fn train_backpropagation_from_folder<R: Rng>(&mut self, learning_rate : f64, momentum : f64, iterations : usize, rand : &mut R) {
+        let corpus = Corpus::from_folder("./");
+        let training_sets = corpus.training_sets(self, SPLIT_TRAIN, 15);
+        assert!(!training_sets.is_empty());
+        println!("Found {} training sets to train on!", training_sets.len());
 
         let error_calculation = ErrorCalculation::new(error_calculation::ERROR_CALCULATION_TYPE_SMART);
-        backpropagation::train(self, &training_sets, &error_calculation, learning_rate, momentum, iterations);
+        backpropagation::train(self, &training_sets, &error_calculation, learning_rate, momentum, iterations, rand);
     }
 
     /**
     * A method for automatically train on the foler's midi files
     * The computing a music and saving the network and the computed
     * music
+    * @input batch_size : The number of windows a weights update is computed on, 1 to update after every window
+    * @input nb_threads : The number of threads a batch's windows are split between
+    * @input sampler : The Sampler used to decode the computed music
+    * @input constraints : The GenerationConstraints the computed music respects
+    * @input rand : The random number generator used by the sampler
     */
-    pub fn train_n_save(&mut self) {
-        let paths = fs::read_dir("./").unwrap();
-
-        let mut midi_paths = Vec::new();
-        for path in paths {
-            let path = path.unwrap().path();
-            print!("{}", path.display());
-            if path.display().to_string().ends_with(".mid") {
-                midi_paths.push(path);
-                println!(" is midi");
-            } else {
-                println!(" is not midi");
-            }
-        }
-
-        assert!(midi_paths.len() > 0);
-        println!("Found {} midi files to train on!", midi_paths.len());
-
-        let mut musics = Vec::with_capacity(midi_paths.len());
-        for path in &midi_paths {
-            musics.push(CesureMusic::from_path(path));
-        }
-
-        let mut training_sets = Vec::with_capacity(musics.len());
-        for music in &musics {
-            training_sets.push(music.to_training_set(15));
-        }
+    pub fn train_n_save<R: Rng>(&mut self, batch_size : usize, nb_threads : usize, sampler : &Sampler, constraints : &GenerationConstraints, rand : &mut R) {
+        let corpus = Corpus::from_folder("./");
+        let training_sets = corpus.training_sets(self, SPLIT_TRAIN, 15);
+        assert!(!training_sets.is_empty());
+        println!("Found {} training sets to train on!", training_sets.len());
 
         let error_calc = ErrorCalculation::new(error_calculation::ERROR_CALCULATION_TYPE_SMART);
         for _ in 0..1 {
             //backpropagation::train(self, &training_sets, &error_calc, 0.1, 0.9, 1000);
-            backpropagation::train_mod2(self, &training_sets, &error_calc, 0.1, 0.9, 5, 100);
+            let update_mode = if batch_size > 1 { backpropagation::UPDATE_MODE_PER_BATCH } else { backpropagation::UPDATE_MODE_PER_STEP };
+            let batch_config = BatchConfig::new(update_mode, backpropagation::BATCH_UNIT_WINDOW, batch_size, 5, false)
+                .with_learning_rate(0.1, 0.9)
+                .with_threads(nb_threads);
+            backpropagation::train_batched(self, &training_sets, &error_calc, &batch_config, 100, rand);
             //genetic::train(self, &training_sets, &error_calc, 1.0, 0.0, 100);
             //genetic::train_mod2(self, &training_sets, &error_calc, 0.1, 0.0, 10000);
         }
 
-        self.compute_music_from_infos(&training_sets[0].infos, &training_sets[0].inject_sequence, 1000).save("output_test.mid");
+        self.compute_music_from_infos(&training_sets[0].infos, &training_sets[0].inject_sequence, 1000, &mut Generation::new(sampler, constraints, rand)).save("output_test.mid");
         //self.save("cesure_test.ces");
     }
 
 }
 
 
+/**
+* Create a memory gate
+* @input memory_type : MEMORY_TYPE_FEEDFORWARD, MEMORY_TYPE_LSTM or MEMORY_TYPE_GRU
+* @input input_dimension : The gate input dimension
+* @input context_dimension : The computed context's dimension
+* @input state_offset : The position of the gate's previous context in its input
+* @input weight_init : The weights initialization
+* @input rand : The random number generator used to initialize the weights
+* @return The MemoryGate
+*/
+fn new_memory_gate<R: Rng>(memory_type : u8, input_dimension : usize, context_dimension : usize, state_offset : usize,
+                           weight_init : &WeightInit, rand : &mut R) -> MemoryGate {
+    match memory_type {
+        memory_gate::MEMORY_TYPE_FEEDFORWARD => MemoryGate::Feedforward(FeedforwardGate::new_auto(input_dimension, context_dimension, 10,
+                                                                            vec![Activation::new(activation::TYPE_SIGMOID); 10], weight_init, rand)),
+        memory_gate::MEMORY_TYPE_LSTM => {
+            assert!(context_dimension.is_multiple_of(2), "A LSTM context holds the hidden and cell states, its dimension must be even");
+            MemoryGate::Lstm(LstmGate::new(input_dimension, context_dimension / 2, state_offset, weight_init, rand))
+        }
+        memory_gate::MEMORY_TYPE_GRU => MemoryGate::Gru(GruGate::new(input_dimension, context_dimension, state_offset, weight_init, rand)),
+        _ => panic!("Unknown memory type"),
+    }
+}
+fn output_mode_to_string(output_mode : u8) -> &'static str {
+    match output_mode {
+        OUTPUT_MODE_INDEPENDENT => "independent",
+        OUTPUT_MODE_CATEGORICAL => "categorical",
+        _ => panic!("Unknown output mode"),
+    }
+}
+fn output_mode_from_string(str : &str) -> u8 {
+    match str {
+        "independent" => OUTPUT_MODE_INDEPENDENT,
+        "categorical" => OUTPUT_MODE_CATEGORICAL,
+        _ => panic!("Unknown output mode"),
+    }
+}
+
+
 /*/**
     * Compute the next output of the current sequence and compute
     * it's error compared to the ideal chord
@@ -459,4 +1284,68 @@ impl Cesure {
             chord.datas[i] = 0.0;
         }
     }
-}*/
\ No newline at end of file
+}*/
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use network::attention::ATTENTION_TYPE_CHORD;
+    use network::music::new_infos;
+    use utils::random::new_rng;
+
+    fn chord(keys : &[usize]) -> Matrix {
+        let mut chord = Matrix::new_row(CHORD_DIMENSION);
+        for &key in keys {
+            chord.datas[key] = 1.0;
+        }
+        chord
+    }
+
+    fn tiny_cesure() -> Cesure {
+        let config = CesureConfig::new(4, OUTPUT_MODE_INDEPENDENT, memory_gate::MEMORY_TYPE_GRU)
+            .with_levels(vec![(3, 2)])
+            .with_attention(ATTENTION_TYPE_CHORD, 3);
+        Cesure::from_config(&config, &mut new_rng(Some(37)))
+    }
+
+    #[test]
+    fn levels_change_at_the_end_of_their_period() {
+        let config = CesureConfig::new(4, OUTPUT_MODE_INDEPENDENT, memory_gate::MEMORY_TYPE_GRU)
+            .with_levels(vec![(3, 2), (2, 3)]);
+        let mut cesure = Cesure::from_config(&config, &mut new_rng(Some(48)));
+        cesure.new_sequence(&new_infos(480.0, 12, 36));
+        for tick in 0..12 {
+            let before = cesure.context.clone();
+            cesure.inject_next(&chord(&[tick % 12, 24 + tick]));
+            let (begin, end) = cesure.level_range(0);
+            assert!(before.datas[begin..end] != cesure.context.datas[begin..end]);
+            for (i, &period) in [2, 3].iter().enumerate() {
+                let (begin, end) = cesure.level_range(i + 1);
+                let changed = before.datas[begin..end] != cesure.context.datas[begin..end];
+                assert_eq!(changed, (tick + 1) % period == 0, "Level {} at tick {}", i + 1, tick);
+            }
+        }
+    }
+
+    #[test]
+    fn feeding_the_ideal_chord_is_injecting_it() {
+        let mut fed = tiny_cesure();
+        fed.new_sequence(&new_infos(480.0, 6, 36));
+        let mut injected = fed.clone();
+        let mut rand = new_rng(Some(37));
+        for tick in 0..6 {
+            let ideal_chord = chord(&[tick, 12 + tick * 3]);
+            fed.compute_next_verbose_fed(Some(&ideal_chord), &Sampler::threshold(), &mut rand);
+            injected.inject_next(&ideal_chord);
+
+            let (fed_state, injected_state) = (fed.state(), injected.state());
+            assert_eq!(fed_state.context.datas, injected_state.context.datas);
+            assert_eq!(fed_state.tick, injected_state.tick);
+            assert_eq!(fed_state.history.len(), injected_state.history.len());
+            for (fed_item, injected_item) in fed_state.history.iter().zip(injected_state.history.iter()) {
+                assert_eq!(fed_item.datas, injected_item.datas);
+            }
+        }
+    }
+}
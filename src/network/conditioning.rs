@@ -0,0 +1,458 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+use network::music::CesureMusic;
+use network::music::Meter;
+use network::music::INFOS_DIMENSION;
+use network::music::CHORD_DIMENSION;
+use network::evaluation;
+
+use utils::matrix::Matrix;
+use utils::string::parse_option;
+use utils::traits::Parse;
+
+
+// The tempo in bpm, one value
+pub const FIELD_TYPE_TEMPO : u8 = 0;
+// The key, a one-hot row over the 12 roots followed by 1.0 for major and 0.0 for minor
+pub const FIELD_TYPE_KEY : u8 = 1;
+// The time signature's numerator and denominator
+pub const FIELD_TYPE_METER : u8 = 2;
+// The average number of notes per tick
+pub const FIELD_TYPE_DENSITY : u8 = 3;
+// A label such as the style or the composer, a one-hot row over the field's labels
+pub const FIELD_TYPE_LABEL : u8 = 4;
+
+pub const TEMPO_RANGE : f64 = 300.0;
+pub const METER_RANGE : f64 = 16.0;
+pub const DENSITY_RANGE : f64 = 10.0;
+
+
+/**
+* A conditioning field, its values are appended to the three base infos
+* - name : The name of a label field, such as "style", empty for the other types
+* - labels : The possible values of a label field
+*/
+pub struct ConditioningField {
+    pub field_type : u8,
+    pub name : String,
+    pub labels : Vec<String>,
+}
+
+/**
+* The layout of a cesure's infos : division, number of ticks and min key, always
+* in this order, followed by the conditioning fields
+*/
+pub struct ConditioningSchema {
+    pub fields : Vec<ConditioningField>,
+}
+
+/**
+* The values used to fill the conditioning fields of a music
+* A missing value leaves its field to zero
+* - key : The root pitch class (C = 0) and whether the key is major
+* - meter : The time signature's numerator and denominator
+* - labels : The label of every label field, by field name
+*/
+pub struct Conditioning {
+    pub tempo : Option<f64>,
+    pub key : Option<(usize, bool)>,
+    pub meter : Option<(usize, usize)>,
+    pub density : Option<f64>,
+    pub labels : HashMap<String, String>,
+}
+
+
+
+impl Clone for ConditioningField {
+    fn clone(&self) -> ConditioningField {
+        return ConditioningField {
+            field_type : self.field_type,
+            name : self.name.clone(),
+            labels : self.labels.clone(),
+        }
+    }
+}
+
+impl Clone for ConditioningSchema {
+    fn clone(&self) -> ConditioningSchema {
+        return ConditioningSchema {
+            fields : self.fields.clone(),
+        }
+    }
+}
+
+impl Clone for Conditioning {
+    fn clone(&self) -> Conditioning {
+        return Conditioning {
+            tempo : self.tempo,
+            key : self.key,
+            meter : self.meter,
+            density : self.density,
+            labels : self.labels.clone(),
+        }
+    }
+}
+
+impl Parse for ConditioningField {
+    fn to_string(&self) -> String {
+        match self.field_type {
+            FIELD_TYPE_TEMPO => "tempo".to_string(),
+            FIELD_TYPE_KEY => "key".to_string(),
+            FIELD_TYPE_METER => "meter".to_string(),
+            FIELD_TYPE_DENSITY => "density".to_string(),
+            FIELD_TYPE_LABEL if self.labels.is_empty() => format!("label:{}", self.name),
+            FIELD_TYPE_LABEL => format!("label:{}={}", self.name, self.labels.join(",")),
+            _ => panic!("Unknown conditioning field type"),
+        }
+    }
+
+    fn from_string(str : &str) -> ConditioningField {
+        match str {
+            "tempo" => ConditioningField::new(FIELD_TYPE_TEMPO),
+            "key" => ConditioningField::new(FIELD_TYPE_KEY),
+            "meter" => ConditioningField::new(FIELD_TYPE_METER),
+            "density" => ConditioningField::new(FIELD_TYPE_DENSITY),
+            _ => {
+                assert!(str.starts_with("label:"), "Unknown conditioning field {}", str);
+                let parts : Vec<&str> = str["label:".len()..].split("=").collect();
+                match parts.len() {
+                    1 => ConditioningField::new_label(parts[0], Vec::new()),
+                    2 => ConditioningField::new_label(parts[0], parts[1].split(",").map(|x| x.to_string()).collect()),
+                    _ => panic!("Invalid conditioning field {}", str),
+                }
+            }
+        }
+    }
+}
+
+impl Parse for ConditioningSchema {
+    /**
+    * Return the schema's line in the saved cesures, without the fields there is no line
+    */
+    fn to_string(&self) -> String {
+        let fields : Vec<String> = self.fields.iter().map(|field| field.to_string()).collect();
+        format!("CONDITIONING {}", fields.join(" "))
+    }
+
+    fn from_string(str : &str) -> ConditioningSchema {
+        let mut tokens = str.split_whitespace();
+        assert!(tokens.next() == Some("CONDITIONING"));
+        ConditioningSchema::new(tokens.map(ConditioningField::from_string).collect())
+    }
+}
+
+
+
+impl ConditioningField {
+
+    pub fn new(field_type : u8) -> ConditioningField {
+        assert!(field_type < FIELD_TYPE_LABEL);
+        return ConditioningField {
+            field_type : field_type,
+            name : String::new(),
+            labels : Vec::new(),
+        }
+    }
+
+    /**
+    * Constructor of a label field
+    * @input name : The field's name
+    * @input labels : The possible values, they can be left empty and filled from a corpus
+    */
+    pub fn new_label(name : &str, labels : Vec<String>) -> ConditioningField {
+        assert!(is_token(name) && labels.iter().all(|label| is_token(label)));
+        return ConditioningField {
+            field_type : FIELD_TYPE_LABEL,
+            name : name.to_string(),
+            labels : labels,
+        }
+    }
+
+    pub fn dimension(&self) -> usize {
+        match self.field_type {
+            FIELD_TYPE_TEMPO => 1,
+            FIELD_TYPE_KEY => 13,
+            FIELD_TYPE_METER => 2,
+            FIELD_TYPE_DENSITY => 1,
+            FIELD_TYPE_LABEL => self.labels.len(),
+            _ => panic!("Unknown conditioning field type"),
+        }
+    }
+
+    /**
+    * Return the field's values
+    * @input conditioning : The values to encode
+    */
+    fn encode(&self, conditioning : &Conditioning) -> Vec<f64> {
+        let mut values = vec![0.0; self.dimension()];
+        match self.field_type {
+            FIELD_TYPE_TEMPO => {
+                if let Some(tempo) = conditioning.tempo {
+                    values[0] = tempo / TEMPO_RANGE;
+                }
+            }
+            FIELD_TYPE_KEY => {
+                if let Some((root, is_major)) = conditioning.key {
+                    values[root % 12] = 1.0;
+                    values[12] = if is_major { 1.0 } else { 0.0 };
+                }
+            }
+            FIELD_TYPE_METER => {
+                if let Some((numerator, denominator)) = conditioning.meter {
+                    values[0] = numerator as f64 / METER_RANGE;
+                    values[1] = denominator as f64 / METER_RANGE;
+                }
+            }
+            FIELD_TYPE_DENSITY => {
+                if let Some(density) = conditioning.density {
+                    values[0] = density / DENSITY_RANGE;
+                }
+            }
+            FIELD_TYPE_LABEL => {
+                if let Some(label) = conditioning.labels.get(&self.name) {
+                    match self.labels.iter().position(|x| x == label) {
+                        Some(i) => { values[i] = 1.0; },
+                        None => { println!("Unknown {} label {}, left to zero", self.name, label); },
+                    }
+                }
+            }
+            _ => panic!("Unknown conditioning field type"),
+        }
+        values
+    }
+
+}
+
+
+
+impl ConditioningSchema {
+
+    /**
+    * Return the schema of the original cesures : only the three base infos
+    */
+    pub fn none() -> ConditioningSchema {
+        return ConditioningSchema {
+            fields : Vec::new(),
+        }
+    }
+
+    pub fn new(fields : Vec<ConditioningField>) -> ConditioningSchema {
+        return ConditioningSchema {
+            fields : fields,
+        }
+    }
+
+    /**
+    * Return the infos dimension
+    */
+    pub fn dimension(&self) -> usize {
+        INFOS_DIMENSION + self.fields.iter().map(|field| field.dimension()).sum::<usize>()
+    }
+
+    /**
+    * Build an infos Matrix
+    * @input base_infos : The three base infos, or infos following another schema
+    * @input conditioning : The values of the conditioning fields
+    * @return The infos Matrix
+    */
+    pub fn build_infos(&self, base_infos : &Matrix, conditioning : &Conditioning) -> Matrix {
+        assert!(base_infos.is_row() && base_infos.len >= INFOS_DIMENSION);
+        let mut datas = base_infos.datas[..INFOS_DIMENSION].to_vec();
+        for field in &self.fields {
+            datas.append(&mut field.encode(conditioning));
+        }
+        Matrix::new_row_from_datas(datas)
+    }
+
+}
+
+
+
+impl Conditioning {
+
+    pub fn new() -> Conditioning {
+        return Conditioning {
+            tempo : None,
+            key : None,
+            meter : None,
+            density : None,
+            labels : HashMap::new(),
+        }
+    }
+
+    /**
+    * Derive the conditioning values from a music : its tempo and time signature,
+    * the major or minor key fitting its notes best and its density
+    * @input music : The music
+    * @input meter : The music's tempo and time signature
+    * @return The Conditioning object
+    */
+    pub fn from_music(music : &CesureMusic, meter : &Meter) -> Conditioning {
+        let stats = evaluation::music_stats(music);
+        let mut nb_notes = 0;
+        for chord in &music.chords {
+            nb_notes += (0..CHORD_DIMENSION).filter(|&i| chord.datas[i] > 0.5).count();
+        }
+        let mut conditioning = Conditioning::new();
+        conditioning.tempo = Some(meter.bpm);
+        conditioning.meter = Some((meter.numerator, meter.denominator));
+        if nb_notes > 0 {
+            conditioning.key = Some((stats.scale_root, stats.scale_is_major));
+            conditioning.density = Some(nb_notes as f64 / music.chords.len() as f64);
+        }
+        conditioning
+    }
+
+    /**
+    * Override the values with the ones of a sidecar file, if it exists
+    * The sidecar of "song.mid" is "song.mid.cond", every line is "name = value" :
+    * tempo = <bpm>, key = <root> <major|minor>, meter = <n/d>, density = <notes per tick>,
+    * any other name being a label field, such as style = baroque
+    * @input midi_path : The midi file's path
+    */
+    pub fn read_sidecar(&mut self, midi_path : &Path) {
+        let sidecar_path = Path::new(&format!("{}.cond", midi_path.display())).to_path_buf();
+        let file = match File::open(&sidecar_path) {
+            Ok(file) => file,
+            Err(_) => { return; },
+        };
+        for line in BufReader::new(file).lines() {
+            let line = line.expect("Couldn't read the sidecar file");
+            let line = line.trim();
+            if line.is_empty() || line.starts_with("#") {
+                continue;
+            }
+            let parts : Vec<&str> = line.splitn(2, "=").map(|x| x.trim()).collect();
+            assert!(parts.len() == 2, "Invalid line in {} : {}", sidecar_path.display(), line);
+            self.set(parts[0], parts[1]);
+        }
+    }
+
+    /**
+    * Set a value from its name and its textual value, as written in the sidecar files
+    */
+    pub fn set(&mut self, name : &str, value : &str) {
+        match name {
+            "tempo" => { self.tempo = Some(value.parse().expect("tempo expects a number")); },
+            "density" => { self.density = Some(value.parse().expect("density expects a number")); },
+            "key" => {
+                let parts : Vec<&str> = value.split_whitespace().collect();
+                assert!(parts.len() == 2, "key expects <root> <major|minor>");
+                let root = parts[0].parse().expect("key expects <root> <major|minor>");
+                let is_major = match parts[1] {
+                    "major" => true,
+                    "minor" => false,
+                    _ => panic!("key expects <root> <major|minor>"),
+                };
+                self.key = Some((root, is_major));
+            }
+            "meter" => {
+                let parts : Vec<usize> = value.split("/").map(|x| x.trim().parse().expect("meter expects n/d")).collect();
+                assert!(parts.len() == 2, "meter expects n/d");
+                self.meter = Some((parts[0], parts[1]));
+            }
+            _ => {
+                assert!(is_token(value), "Labels can't contain spaces, commas or equal signs");
+                self.labels.insert(name.to_string(), value.to_string());
+            }
+        }
+    }
+
+    /**
+    * Parse the conditioning used to steer the generation from the command line arguments :
+    * the tempo and time signature are the meter's, --scale <root> <major|minor> sets
+    * the key, --density <notes per tick> the density and every --label <name>=<value>
+    * a label
+    * @input args : The command line arguments
+    * @input meter : The generated music's tempo and time signature
+    * @return The Conditioning object
+    */
+    pub fn from_args(args : &Vec<String>, meter : &Meter) -> Conditioning {
+        let mut conditioning = Conditioning::new();
+        conditioning.tempo = Some(meter.bpm);
+        conditioning.meter = Some((meter.numerator, meter.denominator));
+        if let Some(i) = args.iter().position(|arg| arg == "--scale") {
+            if let (Some(root), Some(mode)) = (args.get(i+1), args.get(i+2)) {
+                conditioning.set("key", format!("{} {}", root, mode).as_str());
+            }
+        }
+        conditioning.density = parse_option(args, "--density");
+        for i in 0..args.len() {
+            if args[i] == "--label" {
+                let label = args.get(i+1).expect("No argument on option --label");
+                let parts : Vec<&str> = label.splitn(2, "=").collect();
+                assert!(parts.len() == 2, "--label expects <name>=<value>");
+                conditioning.set(parts[0], parts[1]);
+            }
+        }
+        conditioning
+    }
+
+}
+
+
+
+/**
+* Return whether a string can be written in a schema's line
+*/
+fn is_token(str : &str) -> bool {
+    !str.is_empty() && !str.contains(|c : char| c.is_whitespace() || c == ',' || c == '=')
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use network::music::new_infos;
+
+    #[test]
+    fn schema_string_round_trip() {
+        let line = "CONDITIONING tempo key meter density label:style=baroque,jazz label:composer";
+        let schema = ConditioningSchema::from_string(line);
+        assert_eq!(schema.fields.len(), 6);
+        assert_eq!(schema.fields[4].name, "style");
+        assert_eq!(schema.fields[4].labels, vec!["baroque".to_string(), "jazz".to_string()]);
+        assert_eq!(schema.to_string(), line);
+        assert_eq!(ConditioningSchema::from_string(schema.to_string().as_str()).to_string(), schema.to_string());
+        assert_eq!(ConditioningSchema::from_string(ConditioningSchema::none().to_string().as_str()).fields.len(), 0);
+    }
+
+    #[test]
+    fn build_infos_appends_the_fields() {
+        let schema = ConditioningSchema::from_string("CONDITIONING tempo key meter density label:style=baroque,jazz");
+        assert_eq!(schema.dimension(), INFOS_DIMENSION + 1 + 13 + 2 + 1 + 2);
+
+        let mut conditioning = Conditioning::new();
+        conditioning.set("tempo", "150");
+        conditioning.set("key", "9 minor");
+        conditioning.set("meter", "6/8");
+        conditioning.set("style", "jazz");
+        let base_infos = new_infos(480.0, 100, 36);
+        let infos = schema.build_infos(&base_infos, &conditioning);
+
+        let mut expected = base_infos.datas.clone();
+        expected.push(150.0 / TEMPO_RANGE);
+        let mut key = vec![0.0; 13];
+        key[9] = 1.0;
+        expected.append(&mut key);
+        expected.push(6.0 / METER_RANGE);
+        expected.push(8.0 / METER_RANGE);
+        // The density is missing, so it is left to zero
+        expected.push(0.0);
+        expected.push(0.0);
+        expected.push(1.0);
+        assert_eq!(infos.datas, expected);
+
+        // Infos following the schema only keep their three base infos
+        assert_eq!(schema.build_infos(&infos, &Conditioning::new()).datas[..INFOS_DIMENSION], base_infos.datas[..]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn key_mode_is_major_or_minor() {
+        Conditioning::new().set("key", "9 dorian");
+    }
+}
@@ -0,0 +1,304 @@
+use std::cmp::Ordering;
+
+use network::cesure::OUTPUT_MODE_CATEGORICAL;
+use network::music::CHORD_DIMENSION;
+use network::music::REST_INDEX;
+
+use utils::matrix::Matrix;
+use utils::string::parse_option;
+
+
+pub const MAJOR_SCALE : [usize; 7] = [0, 2, 4, 5, 7, 9, 11];
+pub const MINOR_SCALE : [usize; 7] = [0, 2, 3, 5, 7, 8, 10];
+
+
+/**
+* Restrictions applied to the generated music
+* - pitch_classes : The allowed pitch classes (C = 0)
+* - min_key, max_key : The allowed keys, as midi keys
+* - max_notes : The maximum number of simultaneous notes, 0 for no limit
+* - min_duration : The minimum number of ticks a note is held once started
+*/
+pub struct GenerationConstraints {
+    pub pitch_classes : [bool; 12],
+    pub min_key : usize,
+    pub max_key : usize,
+    pub max_notes : usize,
+    pub min_duration : usize,
+}
+
+impl Clone for GenerationConstraints {
+    fn clone(&self) -> GenerationConstraints {
+        return GenerationConstraints {
+            pitch_classes : self.pitch_classes,
+            min_key : self.min_key,
+            max_key : self.max_key,
+            max_notes : self.max_notes,
+            min_duration : self.min_duration,
+        }
+    }
+}
+
+impl GenerationConstraints {
+
+    /**
+    * Return constraints allowing everything
+    */
+    pub fn none() -> GenerationConstraints {
+        return GenerationConstraints {
+            pitch_classes : [true; 12],
+            min_key : 0,
+            max_key : 127,
+            max_notes : 0,
+            min_duration : 0,
+        }
+    }
+
+    /**
+    * Only allow the pitch classes of a scale
+    * @input root : The scale's root pitch class
+    * @input intervals : The scale's intervals from the root, such as MAJOR_SCALE
+    */
+    pub fn with_scale(mut self, root : usize, intervals : &[usize]) -> GenerationConstraints {
+        self.pitch_classes = [false; 12];
+        for interval in intervals {
+            self.pitch_classes[(root + interval) % 12] = true;
+        }
+        self
+    }
+
+    pub fn with_key_range(mut self, min_key : usize, max_key : usize) -> GenerationConstraints {
+        assert!(min_key <= max_key);
+        self.min_key = min_key;
+        self.max_key = max_key;
+        self
+    }
+
+    pub fn with_max_notes(mut self, max_notes : usize) -> GenerationConstraints {
+        self.max_notes = max_notes;
+        self
+    }
+
+    pub fn with_min_duration(mut self, min_duration : usize) -> GenerationConstraints {
+        self.min_duration = min_duration;
+        self
+    }
+
+    /**
+    * Parse the constraints options from the command line arguments :
+    * --scale <root> <major|minor>, --min-key <k>, --max-key <k>,
+    * --max-notes <n> and --min-duration <ticks>
+    * @input args : The command line arguments
+    * @return The GenerationConstraints object
+    */
+    pub fn from_args(args : &Vec<String>) -> GenerationConstraints {
+        let mut constraints = GenerationConstraints::none();
+        if let Some(root) = parse_option::<usize>(args, "--scale") {
+            let i = args.iter().position(|arg| arg == "--scale").unwrap();
+            constraints = match args.get(i+2).map(|mode| mode.as_str()) {
+                Some("major") => constraints.with_scale(root, &MAJOR_SCALE),
+                Some("minor") => constraints.with_scale(root, &MINOR_SCALE),
+                _ => panic!("--scale expects a root and major or minor"),
+            };
+        }
+        let min_key = parse_option(args, "--min-key").unwrap_or(constraints.min_key);
+        let max_key = parse_option(args, "--max-key").unwrap_or(constraints.max_key);
+        constraints
+            .with_key_range(min_key, max_key)
+            .with_max_notes(parse_option(args, "--max-notes").unwrap_or(0))
+            .with_min_duration(parse_option(args, "--min-duration").unwrap_or(0))
+    }
+
+    /**
+    * Return whether a chord's key can be played
+    * @input key_i : The key's index in the chord
+    * @input min_key : The key of the chords' first note
+    */
+    pub fn is_allowed(&self, key_i : usize, min_key : usize) -> bool {
+        let key = key_i + min_key;
+        self.pitch_classes[key % 12] && key >= self.min_key && key <= self.max_key
+    }
+
+    /**
+    * Mask a cesure's output before decoding : the disallowed keys get a zero probability
+    * and the notes held for less than min_duration ticks get a probability of one
+    * In categorical mode the probabilities are normalized again
+    * @input output : The cesure's output
+    * @input output_mode : The cesure's OUTPUT_MODE_ constant
+    * @input min_key : The key of the chords' first note
+    * @input previous_chords : The chords generated before this tick
+    * @return The masked output
+    */
+    pub fn mask_output(&self, output : &Matrix, output_mode : u8, min_key : usize, previous_chords : &[Matrix]) -> Matrix {
+        let mut masked = output.clone();
+        for key_i in 0..CHORD_DIMENSION {
+            if !self.is_allowed(key_i, min_key) {
+                masked.datas[key_i] = 0.0;
+            }
+        }
+        let held = self.held_keys(min_key, previous_chords);
+        match output_mode {
+            OUTPUT_MODE_CATEGORICAL => {
+                if let Some(&key_i) = held.first() {
+                    masked.set_zero();
+                    masked.datas[key_i] = 1.0;
+                }
+                let sum : f64 = masked.datas.iter().sum();
+                match sum > 0.0 {
+                    true => {
+                        for i in 0..masked.len {
+                            masked.datas[i] /= sum;
+                        }
+                    }
+                    false => { masked.datas[REST_INDEX] = 1.0; },
+                }
+            }
+            _ => {
+                for key_i in held {
+                    masked.datas[key_i] = 1.0;
+                }
+            }
+        }
+        masked
+    }
+
+    /**
+    * Make a decoded chord respect the constraints : the disallowed keys are removed, the
+    * held notes are added, then the most likely notes are kept up to max_notes
+    * @input chord : The decoded chord
+    * @input output : The masked output the chord was decoded from
+    * @input min_key : The key of the chords' first note
+    * @input previous_chords : The chords generated before this tick
+    */
+    pub fn enforce(&self, chord : &mut Matrix, output : &Matrix, min_key : usize, previous_chords : &[Matrix]) {
+        for key_i in 0..CHORD_DIMENSION {
+            if !self.is_allowed(key_i, min_key) {
+                chord.datas[key_i] = 0.0;
+            }
+        }
+        let held = self.held_keys(min_key, previous_chords);
+        for &key_i in &held {
+            chord.datas[key_i] = 1.0;
+        }
+        if self.max_notes > 0 {
+            let mut played : Vec<usize> = (0..CHORD_DIMENSION).filter(|&i| chord.datas[i] > 0.5).collect();
+            if played.len() > self.max_notes {
+                played.sort_by(|&a, &b| {
+                    let a_held = held.contains(&a);
+                    let b_held = held.contains(&b);
+                    match b_held.cmp(&a_held) {
+                        Ordering::Equal => output.datas[b].partial_cmp(&output.datas[a]).unwrap_or(Ordering::Equal),
+                        ordering => ordering,
+                    }
+                });
+                for &key_i in &played[self.max_notes..] {
+                    chord.datas[key_i] = 0.0;
+                }
+            }
+        }
+    }
+
+    /**
+    * Return the allowed keys played for less than min_duration ticks at the end of the previous chords
+    */
+    fn held_keys(&self, min_key : usize, previous_chords : &[Matrix]) -> Vec<usize> {
+        let mut held = Vec::new();
+        if self.min_duration <= 1 || previous_chords.is_empty() {
+            return held;
+        }
+        for key_i in 0..CHORD_DIMENSION {
+            if !self.is_allowed(key_i, min_key) {
+                continue;
+            }
+            let mut duration = 0;
+            for chord in previous_chords.iter().rev() {
+                if chord.datas[key_i] <= 0.5 {
+                    break;
+                }
+                duration += 1;
+            }
+            if duration > 0 && duration < self.min_duration {
+                held.push(key_i);
+            }
+        }
+        held
+    }
+
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use network::cesure::OUTPUT_MODE_INDEPENDENT;
+
+    fn chord(keys : &[usize]) -> Matrix {
+        let mut chord = Matrix::new_row(CHORD_DIMENSION);
+        for &key in keys {
+            chord.datas[key] = 1.0;
+        }
+        chord
+    }
+
+    fn played(chord : &Matrix) -> Vec<usize> {
+        (0..chord.len).filter(|&i| chord.datas[i] > 0.5).collect()
+    }
+
+    fn flat_output(probability : f64) -> Matrix {
+        Matrix::new_row_from_datas(vec![probability; CHORD_DIMENSION])
+    }
+
+    #[test]
+    fn scale_masks_the_other_pitch_classes() {
+        // With a first key of 36 (C), the key indexes are pitch classes modulo 12
+        let constraints = GenerationConstraints::none().with_scale(2, &MAJOR_SCALE);
+        let mut all_keys = chord(&(0..CHORD_DIMENSION).collect::<Vec<usize>>());
+        constraints.enforce(&mut all_keys, &flat_output(0.9), 36, &[]);
+        let d_major = [1, 2, 4, 6, 7, 9, 11];
+        assert_eq!(played(&all_keys), (0..CHORD_DIMENSION).filter(|i| d_major.contains(&(i % 12))).collect::<Vec<usize>>());
+
+        let masked = constraints.mask_output(&flat_output(0.9), OUTPUT_MODE_INDEPENDENT, 36, &[]);
+        for key_i in 0..CHORD_DIMENSION {
+            assert_eq!(masked.datas[key_i], if d_major.contains(&(key_i % 12)) { 0.9 } else { 0.0 });
+        }
+    }
+
+    #[test]
+    fn key_range_and_max_notes() {
+        let constraints = GenerationConstraints::none().with_key_range(40, 50).with_max_notes(3);
+        let mut output = flat_output(0.0);
+        for key_i in 0..CHORD_DIMENSION {
+            output.datas[key_i] = key_i as f64 / CHORD_DIMENSION as f64;
+        }
+        let mut all_keys = chord(&(0..CHORD_DIMENSION).collect::<Vec<usize>>());
+        constraints.enforce(&mut all_keys, &output, 36, &[]);
+        // Keys 40 to 50 are the indexes 4 to 14, the 3 most likely being kept
+        assert_eq!(played(&all_keys), vec![12, 13, 14]);
+
+        let mut few_keys = chord(&[2, 5, 20]);
+        constraints.enforce(&mut few_keys, &output, 36, &[]);
+        assert_eq!(played(&few_keys), vec![5]);
+    }
+
+    #[test]
+    fn min_duration_holds_the_notes() {
+        let constraints = GenerationConstraints::none().with_min_duration(3).with_max_notes(2);
+        let output = flat_output(0.1);
+        let mut previous_chords = vec![chord(&[]), chord(&[3, 7])];
+        let expected = [vec![3, 7], vec![3, 7], vec![]];
+        for expected_keys in expected.iter() {
+            let mut next = chord(&[20]);
+            constraints.enforce(&mut next, &output, 36, &previous_chords);
+            if !expected_keys.is_empty() {
+                // The held notes take the max_notes places first
+                assert_eq!(&played(&next), expected_keys);
+                let masked = constraints.mask_output(&output, OUTPUT_MODE_INDEPENDENT, 36, &previous_chords);
+                assert_eq!(masked.datas[3], 1.0);
+                assert_eq!(masked.datas[7], 1.0);
+            } else {
+                assert_eq!(played(&next), vec![20]);
+            }
+            previous_chords.push(next);
+        }
+    }
+}
@@ -0,0 +1,396 @@
+use std::cmp::max;
+use std::cmp::min;
+
+use network::cesure::Cesure;
+use network::gate::traits::Gate;
+use network::music::CesureMusic;
+use network::music::CHORD_DIMENSION;
+use network::music::infos_min_key;
+use network::constraints::MAJOR_SCALE;
+use network::constraints::MINOR_SCALE;
+use network::training::training_set::TrainingSet;
+
+use utils::matrix::Matrix;
+
+
+/**
+* Precision, recall and F1 score of a prediction
+*/
+pub struct F1Score {
+    pub precision : f64,
+    pub recall : f64,
+    pub f1 : f64,
+}
+
+/**
+* Musical statistics of a chords sequence
+* - polyphony_mean : The average number of notes per non silent tick
+* - polyphony_max : The maximum number of simultaneous notes
+* - silence_ratio : The ratio of ticks without any note
+* - pitch_class_histogram : The ratio of note ticks on each pitch class (C = 0)
+* - scale_consistency : The ratio of note ticks belonging to the best fitting
+*   major or minor scale
+* - scale_root : That scale's root pitch class
+* - scale_is_major : Whether that scale is major or minor
+*/
+pub struct MusicStats {
+    pub polyphony_mean : f64,
+    pub polyphony_max : usize,
+    pub silence_ratio : f64,
+    pub pitch_class_histogram : [f64; 12],
+    pub scale_consistency : f64,
+    pub scale_root : usize,
+    pub scale_is_major : bool,
+}
+
+/**
+* The evaluation of a model's predictions over a TrainingSet
+* - frame : The scores of the active notes, tick by tick
+* - onset : The scores of the notes' onsets
+* - stats : The predicted sequence's statistics
+*/
+pub struct PredictionEvaluation {
+    pub frame : F1Score,
+    pub onset : F1Score,
+    pub stats : MusicStats,
+}
+
+
+
+impl F1Score {
+
+    pub fn new(true_positives : usize, false_positives : usize, false_negatives : usize) -> F1Score {
+        let precision = match true_positives + false_positives {
+            0 => 1.0,
+            nb_predicted => true_positives as f64 / nb_predicted as f64,
+        };
+        let recall = match true_positives + false_negatives {
+            0 => 1.0,
+            nb_ideal => true_positives as f64 / nb_ideal as f64,
+        };
+        let f1 = match precision + recall > 0.0 {
+            true => 2.0 * precision * recall / (precision + recall),
+            false => 0.0,
+        };
+        return F1Score {
+            precision : precision,
+            recall : recall,
+            f1 : f1,
+        }
+    }
+
+    pub fn print_title(&self, s : &str) {
+        println!("{} Precision = {:.4}, Recall = {:.4}, F1 = {:.4}", s, self.precision, self.recall, self.f1);
+    }
+
+}
+
+impl MusicStats {
+
+    pub fn print_title(&self, s : &str) {
+        println!("{}", s);
+        println!("- Polyphony : mean = {:.3}, max = {}", self.polyphony_mean, self.polyphony_max);
+        println!("- Silence ratio : {:.4}", self.silence_ratio);
+        println!("- Scale consistency : {:.4} ({} {})", self.scale_consistency, self.scale_root,
+                 if self.scale_is_major { "major" } else { "minor" });
+        let histogram : Vec<String> = self.pitch_class_histogram.iter().map(|x| format!("{:.3}", x)).collect();
+        println!("- Pitch classes : {}", histogram.join(" "));
+    }
+
+}
+
+impl PredictionEvaluation {
+
+    pub fn print_title(&self, s : &str) {
+        println!("{}", s);
+        self.frame.print_title("- Frame :");
+        self.onset.print_title("- Onset :");
+        self.stats.print_title("- Predicted music :");
+    }
+
+}
+
+
+
+/**
+* Compute the frame level scores : a note is a true positive on a tick
+* if it is active in both the predicted and the ideal chord
+* @input predicted : The predicted chords
+* @input ideal : The ideal chords
+* @return The F1Score object
+*/
+pub fn frame_score(predicted : &Vec<Matrix>, ideal : &Vec<Matrix>) -> F1Score {
+    assert!(predicted.len() == ideal.len());
+    let mut true_positives = 0;
+    let mut false_positives = 0;
+    let mut false_negatives = 0;
+    for tick_i in 0..predicted.len() {
+        for key in 0..CHORD_DIMENSION {
+            match (is_on(&predicted[tick_i], key), is_on(&ideal[tick_i], key)) {
+                (true, true) => { true_positives += 1; },
+                (true, false) => { false_positives += 1; },
+                (false, true) => { false_negatives += 1; },
+                (false, false) => {},
+            }
+        }
+    }
+    F1Score::new(true_positives, false_positives, false_negatives)
+}
+
+/**
+* Compute the note level onset scores : a predicted onset is a true positive if an
+* ideal onset of the same key is at most tolerance ticks away, each ideal onset
+* being matched at most once
+* @input predicted : The predicted chords
+* @input ideal : The ideal chords
+* @input tolerance : The maximum distance, in ticks, between two matched onsets
+* @return The F1Score object
+*/
+pub fn onset_score(predicted : &Vec<Matrix>, ideal : &Vec<Matrix>, tolerance : usize) -> F1Score {
+    let predicted_onsets = onsets(predicted);
+    let ideal_onsets = onsets(ideal);
+    let mut true_positives = 0;
+    let mut nb_predicted = 0;
+    let mut nb_ideal = 0;
+    for key in 0..CHORD_DIMENSION {
+        let mut matched = vec![false; ideal_onsets[key].len()];
+        nb_predicted += predicted_onsets[key].len();
+        nb_ideal += ideal_onsets[key].len();
+        for &onset in &predicted_onsets[key] {
+            for i in 0..ideal_onsets[key].len() {
+                let ideal_onset = ideal_onsets[key][i];
+                let distance = max(onset, ideal_onset) - min(onset, ideal_onset);
+                if !matched[i] && distance <= tolerance {
+                    matched[i] = true;
+                    true_positives += 1;
+                    break;
+                }
+            }
+        }
+    }
+    F1Score::new(true_positives, nb_predicted - true_positives, nb_ideal - true_positives)
+}
+
+/**
+* Compute a chords sequence's statistics
+* @input chords : The chords
+* @input min_key : The key of the chords' first note
+* @return The MusicStats object
+*/
+pub fn chords_stats(chords : &Vec<Matrix>, min_key : usize) -> MusicStats {
+    let mut nb_notes = 0;
+    let mut nb_sounding_ticks = 0;
+    let mut polyphony_max = 0;
+    let mut pitch_class_counts = [0.0; 12];
+    for chord in chords {
+        let mut polyphony = 0;
+        for key in 0..CHORD_DIMENSION {
+            if is_on(chord, key) {
+                polyphony += 1;
+                pitch_class_counts[(key + min_key) % 12] += 1.0;
+            }
+        }
+        nb_notes += polyphony;
+        if polyphony > 0 {
+            nb_sounding_ticks += 1;
+        }
+        polyphony_max = max(polyphony_max, polyphony);
+    }
+
+    let mut pitch_class_histogram = [0.0; 12];
+    if nb_notes > 0 {
+        for i in 0..12 {
+            pitch_class_histogram[i] = pitch_class_counts[i] / nb_notes as f64;
+        }
+    }
+
+    let mut scale_consistency = 0.0;
+    let mut scale_root = 0;
+    let mut scale_is_major = true;
+    for root in 0..12 {
+        for &(scale, is_major) in &[(&MAJOR_SCALE, true), (&MINOR_SCALE, false)] {
+            let mut in_scale = 0.0;
+            for degree in scale.iter() {
+                in_scale += pitch_class_histogram[(root + degree) % 12];
+            }
+            if in_scale > scale_consistency {
+                scale_consistency = in_scale;
+                scale_root = root;
+                scale_is_major = is_major;
+            }
+        }
+    }
+
+    return MusicStats {
+        polyphony_mean : match nb_sounding_ticks {
+            0 => 0.0,
+            _ => nb_notes as f64 / nb_sounding_ticks as f64,
+        },
+        polyphony_max : polyphony_max,
+        silence_ratio : match chords.len() {
+            0 => 0.0,
+            nb_ticks => (nb_ticks - nb_sounding_ticks) as f64 / nb_ticks as f64,
+        },
+        pitch_class_histogram : pitch_class_histogram,
+        scale_consistency : scale_consistency,
+        scale_root : scale_root,
+        scale_is_major : scale_is_major,
+    }
+}
+
+/**
+* Compute a music's statistics
+* @input music : The music
+* @return The MusicStats object
+*/
+pub fn music_stats(music : &CesureMusic) -> MusicStats {
+    chords_stats(&music.chords, music.min_key())
+}
+
+/**
+* Compute the pitch class histogram of a whole corpus, every note tick having the same weight
+* @input musics : The corpus
+* @return The histogram
+*/
+pub fn corpus_pitch_class_histogram(musics : &Vec<CesureMusic>) -> [f64; 12] {
+    let mut histogram = [0.0; 12];
+    let mut nb_notes = 0.0;
+    for music in musics {
+        let min_key = music.min_key();
+        for chord in &music.chords {
+            for key in 0..CHORD_DIMENSION {
+                if is_on(chord, key) {
+                    histogram[(key + min_key) % 12] += 1.0;
+                    nb_notes += 1.0;
+                }
+            }
+        }
+    }
+    if nb_notes > 0.0 {
+        for i in 0..12 {
+            histogram[i] /= nb_notes;
+        }
+    }
+    histogram
+}
+
+/**
+* Return the total variation distance between two pitch class histograms,
+* from 0.0 (identical) to 1.0 (disjoint)
+*/
+pub fn pitch_class_distance(histogram1 : &[f64; 12], histogram2 : &[f64; 12]) -> f64 {
+    let mut distance = 0.0;
+    for i in 0..12 {
+        distance += (histogram1[i] - histogram2[i]).abs();
+    }
+    distance / 2.0
+}
+
+/**
+* Evaluate a cesure's predictions over a TrainingSet
+* The inject sequence is injected, then the compute sequence is predicted tick
+* by tick and every output is decoded into its most likely chord
+* @input cesure : The cesure to evaluate
+* @input training_set : The TrainingSet object
+* @input onset_tolerance : The onset matching tolerance, in ticks
+* @return The PredictionEvaluation object
+*/
+pub fn evaluate_predictions<O: Gate, M: Gate>(cesure : &mut Cesure<O, M>, training_set : &TrainingSet, onset_tolerance : usize) -> PredictionEvaluation {
+    cesure.new_sequence(&training_set.infos);
+    for injection in &training_set.inject_sequence {
+        cesure.inject_next(injection);
+    }
+    let mut predicted = Vec::with_capacity(training_set.compute_sequence.len());
+    for _ in 0..training_set.compute_sequence.len() {
+        let output = cesure.compute_next();
+        predicted.push(cesure.decode_output_greedy(&output));
+    }
+
+    let min_key = infos_min_key(&training_set.infos);
+    return PredictionEvaluation {
+        frame : frame_score(&predicted, &training_set.compute_sequence),
+        onset : onset_score(&predicted, &training_set.compute_sequence, onset_tolerance),
+        stats : chords_stats(&predicted, min_key),
+    }
+}
+
+
+
+#[inline]
+fn is_on(chord : &Matrix, key : usize) -> bool {
+    chord.datas[key] > 0.5
+}
+
+/**
+* Return the onset ticks of every key
+*/
+fn onsets(chords : &Vec<Matrix>) -> Vec<Vec<usize>> {
+    let mut onsets = vec![Vec::new(); CHORD_DIMENSION];
+    for tick_i in 0..chords.len() {
+        for key in 0..CHORD_DIMENSION {
+            if is_on(&chords[tick_i], key) && (tick_i == 0 || !is_on(&chords[tick_i-1], key)) {
+                onsets[key].push(tick_i);
+            }
+        }
+    }
+    onsets
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chord(keys : &[usize]) -> Matrix {
+        let mut chord = Matrix::new_row(CHORD_DIMENSION);
+        for &key in keys {
+            chord.datas[key] = 1.0;
+        }
+        chord
+    }
+
+    fn assert_score(score : &F1Score, precision : f64, recall : f64, f1 : f64) {
+        assert!((score.precision - precision).abs() < 1e-12);
+        assert!((score.recall - recall).abs() < 1e-12);
+        assert!((score.f1 - f1).abs() < 1e-12);
+    }
+
+    #[test]
+    fn frame_score_counts_the_ticks_notes() {
+        let predicted = vec![chord(&[0, 1]), chord(&[2]), chord(&[])];
+        let ideal = vec![chord(&[0]), chord(&[2, 3]), chord(&[])];
+        // 2 true positives, 1 false positive (key 1) and 1 false negative (key 3)
+        assert_score(&frame_score(&predicted, &ideal), 2.0 / 3.0, 2.0 / 3.0, 2.0 / 3.0);
+        assert_score(&frame_score(&ideal, &ideal), 1.0, 1.0, 1.0);
+    }
+
+    #[test]
+    fn onset_score_matches_within_the_tolerance() {
+        // The key 5 starts on ticks 0 and 4 and the key 9 on tick 3
+        let ideal = vec![chord(&[5]), chord(&[5]), chord(&[]), chord(&[9]), chord(&[5]), chord(&[5]), chord(&[])];
+        // The key 5 starts on ticks 1 and 6, one and two ticks late, and the key 9 on tick 2, one tick early
+        let predicted = vec![chord(&[]), chord(&[5]), chord(&[9]), chord(&[]), chord(&[]), chord(&[]), chord(&[5])];
+        assert_score(&onset_score(&predicted, &ideal, 0), 0.0, 0.0, 0.0);
+        assert_score(&onset_score(&predicted, &ideal, 1), 2.0 / 3.0, 2.0 / 3.0, 2.0 / 3.0);
+        assert_score(&onset_score(&predicted, &ideal, 2), 1.0, 1.0, 1.0);
+    }
+
+    #[test]
+    fn pitch_class_histogram_distance() {
+        // The first key and the key 12 are Cs, the key 7 is a G
+        let stats = chords_stats(&vec![chord(&[0, 7]), chord(&[12]), chord(&[])], 36);
+        let mut expected = [0.0; 12];
+        expected[0] = 2.0 / 3.0;
+        expected[7] = 1.0 / 3.0;
+        for i in 0..12 {
+            assert!((stats.pitch_class_histogram[i] - expected[i]).abs() < 1e-12);
+        }
+        assert!((stats.silence_ratio - 1.0 / 3.0).abs() < 1e-12);
+
+        let only_c = chords_stats(&vec![chord(&[0])], 36);
+        let e_and_g = chords_stats(&vec![chord(&[16, 19])], 36);
+        assert!((pitch_class_distance(&stats.pitch_class_histogram, &only_c.pitch_class_histogram) - 1.0 / 3.0).abs() < 1e-12);
+        assert!((pitch_class_distance(&only_c.pitch_class_histogram, &e_and_g.pitch_class_histogram) - 1.0).abs() < 1e-12);
+        assert_eq!(pitch_class_distance(&stats.pitch_class_histogram, &stats.pitch_class_histogram), 0.0);
+    }
+}
@@ -5,12 +5,24 @@ use utils::math::sigmoid;
 use utils::math::sigmoid_deriv;
 use utils::math::tanh;
 use utils::math::tanh_deriv;
+use utils::math;
 
 use utils::traits::Parse;
 
 
+// TYPE_SIGMOID and TYPE_TANH are the cheap x/(1+|x|) approximations, kept
+// under their original names so that saved networks compute the same outputs
 pub const TYPE_SIGMOID : u8 = 0;
 pub const TYPE_TANH : u8 = 1;
+pub const TYPE_LOGISTIC : u8 = 2;
+pub const TYPE_TANH_EXACT : u8 = 3;
+pub const TYPE_RELU : u8 = 4;
+pub const TYPE_LEAKY_RELU : u8 = 5;
+pub const TYPE_ELU : u8 = 6;
+pub const TYPE_GELU : u8 = 7;
+pub const TYPE_SOFTPLUS : u8 = 8;
+pub const TYPE_LINEAR : u8 = 9;
+pub const TYPE_HARD_SIGMOID : u8 = 10;
 
 
 pub enum ActivationType {
@@ -19,6 +31,8 @@ pub enum ActivationType {
 }
 
 
+type ActivationFn = fn(&Matrix) -> Matrix;
+
 pub struct Activation {
     pub act_type: u8,
     pub activate: fn(&Matrix) -> Matrix,
@@ -46,6 +60,15 @@ impl Parse for Activation {
         return match self.act_type {
             TYPE_SIGMOID => "sigmoid".to_string(),
             TYPE_TANH => "tanh".to_string(),
+            TYPE_LOGISTIC => "logistic".to_string(),
+            TYPE_TANH_EXACT => "tanh_exact".to_string(),
+            TYPE_RELU => "relu".to_string(),
+            TYPE_LEAKY_RELU => "leaky_relu".to_string(),
+            TYPE_ELU => "elu".to_string(),
+            TYPE_GELU => "gelu".to_string(),
+            TYPE_SOFTPLUS => "softplus".to_string(),
+            TYPE_LINEAR => "linear".to_string(),
+            TYPE_HARD_SIGMOID => "hard_sigmoid".to_string(),
             _ => panic!("Unknown activation type"),
         }
     }
@@ -53,6 +76,15 @@ impl Parse for Activation {
         match str {
             "sigmoid" => Activation::new(TYPE_SIGMOID),
             "tanh" => Activation::new(TYPE_TANH),
+            "logistic" => Activation::new(TYPE_LOGISTIC),
+            "tanh_exact" => Activation::new(TYPE_TANH_EXACT),
+            "relu" => Activation::new(TYPE_RELU),
+            "leaky_relu" => Activation::new(TYPE_LEAKY_RELU),
+            "elu" => Activation::new(TYPE_ELU),
+            "gelu" => Activation::new(TYPE_GELU),
+            "softplus" => Activation::new(TYPE_SOFTPLUS),
+            "linear" => Activation::new(TYPE_LINEAR),
+            "hard_sigmoid" => Activation::new(TYPE_HARD_SIGMOID),
             _ => panic!("Unknown activation type"),
         }
     }
@@ -63,22 +95,24 @@ impl Parse for Activation {
 impl Activation {
 
     pub fn new(activation_type : u8) -> Activation {
-        match activation_type {
-            TYPE_SIGMOID => {
-                return Activation {
-                    act_type : TYPE_SIGMOID,
-                    activate : matrix_sigmoid,
-                    derivate : matrix_sigmoid_deriv,
-                }
-            }
-            TYPE_TANH => {
-                return Activation {
-                    act_type : TYPE_TANH,
-                    activate : matrix_tanh,
-                    derivate : matrix_tanh_deriv,
-                }
-            }
+        let (activate, derivate) : (ActivationFn, ActivationFn) = match activation_type {
+            TYPE_SIGMOID => (matrix_sigmoid, matrix_sigmoid_deriv),
+            TYPE_TANH => (matrix_tanh, matrix_tanh_deriv),
+            TYPE_LOGISTIC => (matrix_logistic, matrix_logistic_deriv),
+            TYPE_TANH_EXACT => (matrix_tanh_exact, matrix_tanh_exact_deriv),
+            TYPE_RELU => (matrix_relu, matrix_relu_deriv),
+            TYPE_LEAKY_RELU => (matrix_leaky_relu, matrix_leaky_relu_deriv),
+            TYPE_ELU => (matrix_elu, matrix_elu_deriv),
+            TYPE_GELU => (matrix_gelu, matrix_gelu_deriv),
+            TYPE_SOFTPLUS => (matrix_softplus, matrix_softplus_deriv),
+            TYPE_LINEAR => (matrix_linear, matrix_linear_deriv),
+            TYPE_HARD_SIGMOID => (matrix_hard_sigmoid, matrix_hard_sigmoid_deriv),
             _ => panic!("Unknown activation type"),
+        };
+        return Activation {
+            act_type : activation_type,
+            activate : activate,
+            derivate : derivate,
         }
     }
 
@@ -117,4 +151,119 @@ fn matrix_tanh_deriv(matrix : & Matrix) -> Matrix {
         result.datas[index] = tanh_deriv(matrix.datas[index]);
     }
     return result
-}
\ No newline at end of file
+}
+
+
+#[inline]
+fn matrix_map(matrix : & Matrix, function : fn(f64) -> f64) -> Matrix {
+    let mut result = Matrix::new(matrix.rows, matrix.cols);
+    for index in 0..matrix.len {
+        result.datas[index] = function(matrix.datas[index]);
+    }
+    return result
+}
+#[inline]
+fn matrix_logistic(matrix : & Matrix) -> Matrix {
+    matrix_map(matrix, math::logistic)
+}
+#[inline]
+fn matrix_logistic_deriv(matrix : & Matrix) -> Matrix {
+    matrix_map(matrix, math::logistic_deriv)
+}
+#[inline]
+fn matrix_tanh_exact(matrix : & Matrix) -> Matrix {
+    matrix_map(matrix, math::tanh_exact)
+}
+#[inline]
+fn matrix_tanh_exact_deriv(matrix : & Matrix) -> Matrix {
+    matrix_map(matrix, math::tanh_exact_deriv)
+}
+#[inline]
+fn matrix_relu(matrix : & Matrix) -> Matrix {
+    matrix_map(matrix, math::relu)
+}
+#[inline]
+fn matrix_relu_deriv(matrix : & Matrix) -> Matrix {
+    matrix_map(matrix, math::relu_deriv)
+}
+#[inline]
+fn matrix_leaky_relu(matrix : & Matrix) -> Matrix {
+    matrix_map(matrix, math::leaky_relu)
+}
+#[inline]
+fn matrix_leaky_relu_deriv(matrix : & Matrix) -> Matrix {
+    matrix_map(matrix, math::leaky_relu_deriv)
+}
+#[inline]
+fn matrix_elu(matrix : & Matrix) -> Matrix {
+    matrix_map(matrix, math::elu)
+}
+#[inline]
+fn matrix_elu_deriv(matrix : & Matrix) -> Matrix {
+    matrix_map(matrix, math::elu_deriv)
+}
+#[inline]
+fn matrix_gelu(matrix : & Matrix) -> Matrix {
+    matrix_map(matrix, math::gelu)
+}
+#[inline]
+fn matrix_gelu_deriv(matrix : & Matrix) -> Matrix {
+    matrix_map(matrix, math::gelu_deriv)
+}
+#[inline]
+fn matrix_softplus(matrix : & Matrix) -> Matrix {
+    matrix_map(matrix, math::softplus)
+}
+#[inline]
+fn matrix_softplus_deriv(matrix : & Matrix) -> Matrix {
+    matrix_map(matrix, math::softplus_deriv)
+}
+#[inline]
+fn matrix_linear(matrix : & Matrix) -> Matrix {
+    matrix_map(matrix, math::linear)
+}
+#[inline]
+fn matrix_linear_deriv(matrix : & Matrix) -> Matrix {
+    matrix_map(matrix, math::linear_deriv)
+}
+#[inline]
+fn matrix_hard_sigmoid(matrix : & Matrix) -> Matrix {
+    matrix_map(matrix, math::hard_sigmoid)
+}
+#[inline]
+fn matrix_hard_sigmoid_deriv(matrix : & Matrix) -> Matrix {
+    matrix_map(matrix, math::hard_sigmoid_deriv)
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_round_trip() {
+        for activation_type in TYPE_SIGMOID..(TYPE_HARD_SIGMOID+1) {
+            let activation = Activation::new(activation_type);
+            assert_eq!(Activation::from_string(&activation.to_string()).act_type, activation_type);
+        }
+    }
+
+    #[test]
+    fn derivate_matches_activate() {
+        let delta = 1e-6;
+        let input = Matrix::new_row_from_datas(vec![-3.3, -1.6, -0.7, 0.2, 0.9, 2.7]);
+        for activation_type in TYPE_SIGMOID..(TYPE_HARD_SIGMOID+1) {
+            let activation = Activation::new(activation_type);
+            let derivative = (activation.derivate)(&input);
+            for i in 0..input.len {
+                let mut plus = input.clone();
+                plus.datas[i] += delta;
+                let mut minus = input.clone();
+                minus.datas[i] -= delta;
+                let numeric = ((activation.activate)(&plus).datas[i] - (activation.activate)(&minus).datas[i]) / (2.0 * delta);
+                assert!((derivative.datas[i] - numeric).abs() < 1e-6, "{} at {}", activation.to_string(), input.datas[i]);
+            }
+        }
+    }
+}
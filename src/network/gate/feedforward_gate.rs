@@ -4,7 +4,13 @@ extern crate rand;
 
 use std::ops::Add;
 
+use rand::Rng;
+
 use network::gate::activation::Activation;
+use network::gate::traits::Gate;
+use network::gate::traits::GateOutput;
+use network::gate::traits::WeightsChanges;
+use network::gate::weight_init::WeightInit;
 
 use utils::matrix::Matrix;
 use utils::matrix_math::m_dot;
@@ -21,7 +27,7 @@ pub struct FeedforwardGate {
     pub output_dimension : usize,
     pub nb_layers : usize,
     pub layers : Vec<Matrix>,
-    pub activation : Activation,
+    pub activations : Vec<Activation>,
 }
 
 pub struct VerboseOutput {
@@ -31,7 +37,6 @@ pub struct VerboseOutput {
     pub output : Matrix,
 }
 
-type WeightsChanges = Vec<Matrix>;
 
 
 
@@ -42,7 +47,7 @@ impl Clone for FeedforwardGate {
             output_dimension : self.output_dimension,
             nb_layers : self.nb_layers,
             layers : Vec::with_capacity(self.nb_layers),
-            activation : self.activation.clone(),
+            activations : self.activations.clone(),
         };
         for i in 0..self.nb_layers {
             output.layers.push(self.layers[i].clone());
@@ -57,12 +62,21 @@ impl Clone for FeedforwardGate {
         for i in 0..self.nb_layers {
             self.layers.push(source.layers[i].clone());
         }
-        self.activation.clone_from(&source.activation);
+        self.activations.clone_from(&source.activations);
     }
 }
 impl Parse for FeedforwardGate {
     fn to_string(&self) -> String {
-        let mut output = format!("{} {} {} {}", self.input_dimension, self.output_dimension, self.nb_layers, self.activation.to_string());
+        let mut output = format!("{} {} {}", self.input_dimension, self.output_dimension, self.nb_layers);
+        // A single activation name is written when every layer shares it
+        let first_activation = self.activations[0].to_string();
+        if self.activations.iter().all(|activation| activation.to_string() == first_activation) {
+            output = output.add(&format!(" {}", first_activation));
+        } else {
+            for activation in &self.activations {
+                output = output.add(&format!(" {}", activation.to_string()));
+            }
+        }
         for i in 0..self.nb_layers {
             output = output.add(&format!("\n{}", &self.layers[i].to_string()));
         }
@@ -73,11 +87,17 @@ impl Parse for FeedforwardGate {
         assert!(lines.len() >= 2); // header + at least one layer
 
         let header : Vec<&str> = lines[0].split(" ").collect();
-        assert!(header.len() == 4);
+        assert!(header.len() >= 4);
         let input_dimension = header[0].parse().unwrap();
         let output_dimension = header[1].parse().unwrap();
         let nb_layers = header[2].parse().unwrap();
-        let activation = Activation::from_string(header[3]);
+        let activations = match header.len() == 4 {
+            true => vec![Activation::from_string(header[3]); nb_layers],
+            false => {
+                assert!(header.len() == 3 + nb_layers);
+                header[3..].iter().map(|name| Activation::from_string(name)).collect()
+            }
+        };
 
         assert!(lines.len() == nb_layers + 1); // header + layers
         let mut layers = Vec::with_capacity(nb_layers);
@@ -90,134 +110,22 @@ impl Parse for FeedforwardGate {
             output_dimension : output_dimension,
             nb_layers : nb_layers,
             layers : layers,
-            activation : activation,
+            activations : activations,
         }
     }
 }
 
 
 
-impl FeedforwardGate {
-
-    /**
-    * Create a new FeedforwardGate object
-    * @input input_dimension : The gate input dimension
-    * @input output_dimension : The gate output dimension
-    * @input hiddens_dimensions : The hidden layers' dimension (The number of layers
-    *                             will be hiddens_dimensions.len()+1)
-    * @input activation : The activation function
-    * @return The newly created FeedforwardGate object
-    */
-    pub fn new(input_dimension : usize, output_dimension : usize, hiddens_dimensions : Vec<usize>, activation : Activation) -> FeedforwardGate {
-        assert!(input_dimension > 0 && output_dimension > 0);
-        let nb_layers = hiddens_dimensions.len() + 1;
-        let mut output = FeedforwardGate {
-            input_dimension : input_dimension,
-            output_dimension : output_dimension,
-            nb_layers : nb_layers,
-            layers : Vec::with_capacity(nb_layers),
-            activation : activation,
-        };
-        if nb_layers == 1 {
-            output.layers.push(Matrix::new(input_dimension+1, output_dimension));
-        } else {
-            output.layers.push(Matrix::new(input_dimension+1, hiddens_dimensions[0]));
-            for i in 0..(nb_layers-2) {
-                output.layers.push(Matrix::new(hiddens_dimensions[i]+1, hiddens_dimensions[i+1]));
-            }
-            output.layers.push(Matrix::new(hiddens_dimensions[nb_layers-2]+1, output_dimension));
-        }
-        output.weight_init_xavier(&mut rand::thread_rng());
-        return output;
-    }
-
-    /**
-    * Create a new FeedforwardGate object with automatically determined number of  hidden layers' neurons
-    * @input input_dimension : The gate input dimension
-    * @input output_dimension : The gate output dimension
-    * @input nb_layers : The number of layers
-    * @input activation : The activation function
-    * @return The newly created FeedforwardGate object
-    */
-    pub fn new_auto(input_dimension : usize, output_dimension : usize, nb_layers : usize, activation : Activation) -> FeedforwardGate {
-        assert!(input_dimension > 0 && output_dimension > 0 && nb_layers > 0);
-        let mut output = FeedforwardGate {
-            input_dimension : input_dimension,
-            output_dimension : output_dimension,
-            nb_layers : nb_layers,
-            layers : Vec::with_capacity(nb_layers),
-            activation : activation,
-        };
-
-        let in_dim = input_dimension as f64;
-        let out_dim = output_dimension as f64;
-        let x_div = nb_layers as f64;
-        let mut last_out_dimension = input_dimension;
-
-        for i in 0..nb_layers {
-            let x : f64 = (i+1) as f64 / x_div;
-            let new_out_dimension = x * out_dim + (1.0-x) * in_dim;
-            let mut new_out_dimension = new_out_dimension.round() as usize;
-            if i < nb_layers-1 {
-                new_out_dimension += 1;
-            }
-            output.layers.push(Matrix::new(last_out_dimension+1, new_out_dimension));
-            last_out_dimension = new_out_dimension;
-        }
-        output.weight_init_xavier(&mut rand::thread_rng());
-        return output;
-    }
-
-    /**
-    * Initialize the weights using XAVIER initialization
-    * @input rand : The ThreadRng object to use for random
-    */
-    fn weight_init_xavier(&mut self, mut rand : &mut rand::ThreadRng) {
-        for i in 0..self.nb_layers {
-            self.layers[i].set_random(-1.0, 1.0, rand); //TODO
-        }
-    }
+impl Gate for FeedforwardGate {
+    type VerboseOutput = VerboseOutput;
 
-    /**
-    * @input magnitude : The amount of random
-    * @input rand : The ThreadRng object to use for random
-    * @return The cloned FeedforwardGate
-    */
-    pub fn clone_randomized(&self, magnitude : f64, rand : &mut rand::ThreadRng) -> FeedforwardGate {
-        let mut layers = Vec::with_capacity(self.nb_layers);
-        for i in 0..self.nb_layers {
-            layers.push( self.layers[i].clone_randomized(magnitude,rand) );
-        }
-        let activation = self.activation.clone();
-        return FeedforwardGate {
-            input_dimension : self.input_dimension,
-            output_dimension : self.output_dimension,
-            nb_layers : self.nb_layers,
-            layers : layers,
-            activation : activation,
-        }
-    }
-
-    /**
-    * Print all the layers
-    */
-    pub fn print(&self) {
-        for i in 0..self.nb_layers {
-            println!("Layer #{} :", i);
-            self.layers[i].print();
-        }
+    fn input_dimension(&self) -> usize {
+        self.input_dimension
     }
 
-    /**
-    * Return the number of neurons in this network
-    * @return The number of neurons
-    */
-    pub fn get_nb_neurons(&self) -> usize {
-        let mut nb_neurons = 0;
-        for layer in &self.layers {
-            nb_neurons += layer.len;
-        }
-        nb_neurons
+    fn output_dimension(&self) -> usize {
+        self.output_dimension
     }
 
     /**
@@ -225,12 +133,12 @@ impl FeedforwardGate {
     * @input input : The input to compute
     * @return The computed output
     */
-    pub fn compute(&self, input : &Matrix) -> Matrix {
+    fn compute(&self, input : &Matrix) -> Matrix {
         assert!(input.is_row() && input.len == self.input_dimension);
         let mut output = row_append(input, 1.0);
         for i in 0..self.nb_layers {
             output.m_dot(&self.layers[i]);
-            output = (self.activation.activate)(&output);
+            output = (self.activations[i].activate)(&output);
             if i < self.nb_layers-1 {
                 output.row_append(1.0);
             }
@@ -244,7 +152,7 @@ impl FeedforwardGate {
     * @input input : The input to compute
     * @return The VerboseOutput object
     */
-    pub fn compute_verbose(&self, input : &Matrix) -> VerboseOutput {
+    fn compute_verbose(&self, input : &Matrix) -> VerboseOutput {
         assert!(input.is_row() && input.len == self.input_dimension);
 
         let mut outputs_unact = Vec::with_capacity(self.nb_layers);
@@ -253,17 +161,17 @@ impl FeedforwardGate {
         let input_bias = row_append(&input, 1.0);
 
         outputs_unact.push( m_dot(&input_bias, &self.layers[0]) );
-        let mut output_act_bias : Matrix = (self.activation.activate)(&outputs_unact[0]);
+        let mut output_act_bias : Matrix = (self.activations[0].activate)(&outputs_unact[0]);
         output_act_bias.row_append(1.0);
         outputs_act_bias.push(output_act_bias);
         for i in 1..self.nb_layers {
             outputs_unact.push( m_dot(&outputs_act_bias[i-1], &self.layers[i]) );
-            let mut output_act_bias : Matrix = (self.activation.activate)(&outputs_unact[i]);
+            let mut output_act_bias : Matrix = (self.activations[i].activate)(&outputs_unact[i]);
             output_act_bias.row_append(1.0);
             outputs_act_bias.push(output_act_bias);
         }
 
-        let output = (self.activation.activate)(&outputs_unact[self.nb_layers-1]);
+        let output = (self.activations[self.nb_layers-1].activate)(&outputs_unact[self.nb_layers-1]);
 
         assert!(outputs_unact.len() == self.nb_layers);
         assert!(outputs_act_bias.len() == self.nb_layers);
@@ -278,8 +186,8 @@ impl FeedforwardGate {
     }
 
     /**
-    * Backpropagate an error signal to change the weights
-    * The final output error signal is = (output-ideal)
+    * Backpropagate an error signal to get the weights changes
+    * It won't apply the weights changes, use apply_changes() if you want to apply them
     * @input output_v : The la computation's VerboseOutput object
     * @input signal : The output error signal
     * @input last_changes : A LastChanges object with the last weights changes
@@ -287,8 +195,8 @@ impl FeedforwardGate {
     * @input momentum : The momentum
     * @return A tuple composed by the input error signal and the last changes
     */
-    pub fn backpropagate(&mut self, output_v : &VerboseOutput, signal : &Matrix, learning_rate : f64,
-                                                    last_changes : Option<WeightsChanges>, momentum : f64) -> (Matrix,WeightsChanges) {
+    fn backpropagate_no_change(&self, output_v : &VerboseOutput, signal : &Matrix, learning_rate : f64,
+                               last_changes : &Option<WeightsChanges>, momentum : f64) -> (Matrix,WeightsChanges) {
         assert!(signal.len == self.output_dimension);
 
         let mut hidden_signals = Vec::with_capacity(self.nb_layers);
@@ -300,12 +208,12 @@ impl FeedforwardGate {
         }
 
         if self.nb_layers == 1 {
-            hidden_signals[0] = p_mult( signal, &(self.activation.derivate)(&output_v.outputs_unact[0]) );
+            hidden_signals[0] = p_mult( signal, &(self.activations[0].derivate)(&output_v.outputs_unact[0]) );
             weights_deltas[0] = m_dot( &transpose(&output_v.input_bias), &hidden_signals[0] );
             weights_deltas[0].mult_scl(-1.0 * learning_rate);
 
         } else { // self.nb_layers >= 2
-            hidden_signals[self.nb_layers-1] = p_mult( signal, &(self.activation.derivate)(&output_v.outputs_unact[self.nb_layers-1]) );
+            hidden_signals[self.nb_layers-1] = p_mult( signal, &(self.activations[self.nb_layers-1].derivate)(&output_v.outputs_unact[self.nb_layers-1]) );
             weights_deltas[self.nb_layers-1] = m_dot( &transpose(&output_v.outputs_act_bias[self.nb_layers-2]), &hidden_signals[self.nb_layers-1] );
             weights_deltas[self.nb_layers-1].mult_scl(-1.0 * learning_rate);
 
@@ -313,7 +221,7 @@ impl FeedforwardGate {
             while layer_i >= 1 {
                 let mut hidden_signal = m_dot(&hidden_signals[layer_i+1], &transpose(&self.layers[layer_i+1]));
                 hidden_signal.delete_last_col();
-                hidden_signal.p_mult(&(self.activation.derivate)(&output_v.outputs_unact[layer_i]));
+                hidden_signal.p_mult(&(self.activations[layer_i].derivate)(&output_v.outputs_unact[layer_i]));
 
                 hidden_signals[layer_i] = hidden_signal;
                 weights_deltas[layer_i] = m_dot( &transpose(&output_v.outputs_act_bias[layer_i-1]), &hidden_signals[layer_i] );
@@ -323,7 +231,7 @@ impl FeedforwardGate {
 
             let mut hidden_signal = m_dot(&hidden_signals[1], &transpose(&self.layers[1]));
             hidden_signal.delete_last_col();
-            hidden_signal.p_mult( &(self.activation.derivate)(&output_v.outputs_unact[0]) );
+            hidden_signal.p_mult( &(self.activations[0].derivate)(&output_v.outputs_unact[0]) );
 
             hidden_signals[0] = hidden_signal;
             weights_deltas[0] = m_dot( &transpose(&output_v.input_bias), &hidden_signals[0] );
@@ -332,19 +240,15 @@ impl FeedforwardGate {
 
 
 
-        match last_changes {
-            Some(mut changes) => {
+        match *last_changes {
+            Some(ref last_changes) => {
                 for layer_i in 0..self.nb_layers {
-                    weights_deltas[layer_i].add( changes[layer_i].mult_scl(momentum) );
+                    weights_deltas[layer_i].add( &mult_scl(&last_changes[layer_i], momentum) );
                 }
             }
             None => {}
         }
 
-        for layer_i in 0..self.nb_layers {
-            self.layers[layer_i].add(&weights_deltas[layer_i]);
-        }
-
         let mut input_signal = m_dot(&hidden_signals[0], &transpose(&self.layers[0]));
         input_signal.delete_last_col();
 
@@ -352,9 +256,115 @@ impl FeedforwardGate {
         return (input_signal, weights_deltas);
     }
 
+    fn parameters(&self) -> Vec<&Matrix> {
+        self.layers.iter().collect()
+    }
+
+    fn parameters_mut(&mut self) -> Vec<&mut Matrix> {
+        self.layers.iter_mut().collect()
+    }
+}
+impl GateOutput for VerboseOutput {
+    fn output(&self) -> &Matrix {
+        &self.output
+    }
+}
+
+
+
+impl FeedforwardGate {
+
     /**
-    * Backpropagate an error signal to get the weights changes
-    * It won't apply the weights changes, use apply_changes() if you want to apply them
+    * Create a new FeedforwardGate object
+    * @input input_dimension : The gate input dimension
+    * @input output_dimension : The gate output dimension
+    * @input hiddens_dimensions : The hidden layers' dimension (The number of layers
+    *                             will be hiddens_dimensions.len()+1)
+    * @input activations : The activation function of each layer, the last one being the output layer's
+    * @input weight_init : The weights initialization
+    * @input rand : The random number generator used to initialize the weights
+    * @return The newly created FeedforwardGate object
+    */
+    pub fn new<R: Rng>(input_dimension : usize, output_dimension : usize, hiddens_dimensions : Vec<usize>, activations : Vec<Activation>,
+                       weight_init : &WeightInit, rand : &mut R) -> FeedforwardGate {
+        assert!(input_dimension > 0 && output_dimension > 0);
+        let nb_layers = hiddens_dimensions.len() + 1;
+        assert!(activations.len() == nb_layers);
+        let mut output = FeedforwardGate {
+            input_dimension : input_dimension,
+            output_dimension : output_dimension,
+            nb_layers : nb_layers,
+            layers : Vec::with_capacity(nb_layers),
+            activations : activations,
+        };
+        if nb_layers == 1 {
+            output.layers.push(Matrix::new(input_dimension+1, output_dimension));
+        } else {
+            output.layers.push(Matrix::new(input_dimension+1, hiddens_dimensions[0]));
+            for i in 0..(nb_layers-2) {
+                output.layers.push(Matrix::new(hiddens_dimensions[i]+1, hiddens_dimensions[i+1]));
+            }
+            output.layers.push(Matrix::new(hiddens_dimensions[nb_layers-2]+1, output_dimension));
+        }
+        output.weight_init(weight_init, rand);
+        return output;
+    }
+
+    /**
+    * Create a new FeedforwardGate object with automatically determined number of  hidden layers' neurons
+    * @input input_dimension : The gate input dimension
+    * @input output_dimension : The gate output dimension
+    * @input nb_layers : The number of layers
+    * @input activations : The activation function of each layer, the last one being the output layer's
+    * @input weight_init : The weights initialization
+    * @input rand : The random number generator used to initialize the weights
+    * @return The newly created FeedforwardGate object
+    */
+    pub fn new_auto<R: Rng>(input_dimension : usize, output_dimension : usize, nb_layers : usize, activations : Vec<Activation>,
+                            weight_init : &WeightInit, rand : &mut R) -> FeedforwardGate {
+        assert!(input_dimension > 0 && output_dimension > 0 && nb_layers > 0);
+        assert!(activations.len() == nb_layers);
+        let mut output = FeedforwardGate {
+            input_dimension : input_dimension,
+            output_dimension : output_dimension,
+            nb_layers : nb_layers,
+            layers : Vec::with_capacity(nb_layers),
+            activations : activations,
+        };
+
+        let in_dim = input_dimension as f64;
+        let out_dim = output_dimension as f64;
+        let x_div = nb_layers as f64;
+        let mut last_out_dimension = input_dimension;
+
+        for i in 0..nb_layers {
+            let x : f64 = (i+1) as f64 / x_div;
+            let new_out_dimension = x * out_dim + (1.0-x) * in_dim;
+            let mut new_out_dimension = new_out_dimension.round() as usize;
+            if i < nb_layers-1 {
+                new_out_dimension += 1;
+            }
+            output.layers.push(Matrix::new(last_out_dimension+1, new_out_dimension));
+            last_out_dimension = new_out_dimension;
+        }
+        output.weight_init(weight_init, rand);
+        return output;
+    }
+
+    /**
+    * Initialize the weights of every layer
+    * @input weight_init : The weights initialization
+    * @input rand : The random number generator
+    */
+    fn weight_init<R: Rng>(&mut self, weight_init : &WeightInit, rand : &mut R) {
+        for i in 0..self.nb_layers {
+            weight_init.init_layer(&mut self.layers[i], rand);
+        }
+    }
+
+    /**
+    * Backpropagate an error signal to change the weights
+    * The final output error signal is = (output-ideal)
     * @input output_v : The la computation's VerboseOutput object
     * @input signal : The output error signal
     * @input last_changes : A LastChanges object with the last weights changes
@@ -362,8 +372,8 @@ impl FeedforwardGate {
     * @input momentum : The momentum
     * @return A tuple composed by the input error signal and the last changes
     */
-    pub fn backpropagate_no_change(&mut self, output_v : &VerboseOutput, signal : &Matrix, learning_rate : f64,
-                                                    last_changes : &Option<WeightsChanges>, momentum : f64) -> (Matrix,WeightsChanges) {
+    pub fn backpropagate(&mut self, output_v : &VerboseOutput, signal : &Matrix, learning_rate : f64,
+                                                    last_changes : Option<WeightsChanges>, momentum : f64) -> (Matrix,WeightsChanges) {
         assert!(signal.len == self.output_dimension);
 
         let mut hidden_signals = Vec::with_capacity(self.nb_layers);
@@ -375,12 +385,12 @@ impl FeedforwardGate {
         }
 
         if self.nb_layers == 1 {
-            hidden_signals[0] = p_mult( signal, &(self.activation.derivate)(&output_v.outputs_unact[0]) );
+            hidden_signals[0] = p_mult( signal, &(self.activations[0].derivate)(&output_v.outputs_unact[0]) );
             weights_deltas[0] = m_dot( &transpose(&output_v.input_bias), &hidden_signals[0] );
             weights_deltas[0].mult_scl(-1.0 * learning_rate);
 
         } else { // self.nb_layers >= 2
-            hidden_signals[self.nb_layers-1] = p_mult( signal, &(self.activation.derivate)(&output_v.outputs_unact[self.nb_layers-1]) );
+            hidden_signals[self.nb_layers-1] = p_mult( signal, &(self.activations[self.nb_layers-1].derivate)(&output_v.outputs_unact[self.nb_layers-1]) );
             weights_deltas[self.nb_layers-1] = m_dot( &transpose(&output_v.outputs_act_bias[self.nb_layers-2]), &hidden_signals[self.nb_layers-1] );
             weights_deltas[self.nb_layers-1].mult_scl(-1.0 * learning_rate);
 
@@ -388,7 +398,7 @@ impl FeedforwardGate {
             while layer_i >= 1 {
                 let mut hidden_signal = m_dot(&hidden_signals[layer_i+1], &transpose(&self.layers[layer_i+1]));
                 hidden_signal.delete_last_col();
-                hidden_signal.p_mult(&(self.activation.derivate)(&output_v.outputs_unact[layer_i]));
+                hidden_signal.p_mult(&(self.activations[layer_i].derivate)(&output_v.outputs_unact[layer_i]));
 
                 hidden_signals[layer_i] = hidden_signal;
                 weights_deltas[layer_i] = m_dot( &transpose(&output_v.outputs_act_bias[layer_i-1]), &hidden_signals[layer_i] );
@@ -398,7 +408,7 @@ impl FeedforwardGate {
 
             let mut hidden_signal = m_dot(&hidden_signals[1], &transpose(&self.layers[1]));
             hidden_signal.delete_last_col();
-            hidden_signal.p_mult( &(self.activation.derivate)(&output_v.outputs_unact[0]) );
+            hidden_signal.p_mult( &(self.activations[0].derivate)(&output_v.outputs_unact[0]) );
 
             hidden_signals[0] = hidden_signal;
             weights_deltas[0] = m_dot( &transpose(&output_v.input_bias), &hidden_signals[0] );
@@ -408,12 +418,16 @@ impl FeedforwardGate {
 
 
         match last_changes {
-            &Some(ref last_changes) => {
+            Some(mut changes) => {
                 for layer_i in 0..self.nb_layers {
-                    weights_deltas[layer_i].add( &mult_scl(&last_changes[layer_i], momentum) );
+                    weights_deltas[layer_i].add( changes[layer_i].mult_scl(momentum) );
                 }
             }
-            &None => {}
+            None => {}
+        }
+
+        for layer_i in 0..self.nb_layers {
+            self.layers[layer_i].add(&weights_deltas[layer_i]);
         }
 
         let mut input_signal = m_dot(&hidden_signals[0], &transpose(&self.layers[0]));
@@ -423,17 +437,6 @@ impl FeedforwardGate {
         return (input_signal, weights_deltas);
     }
 
-    /**
-    * Apply the weights changes to the layers
-    * @input weights_changes : The weights changes to apply
-    */
-    pub fn apply_changes(&mut self, weights_changes : &WeightsChanges) {
-        assert!(weights_changes.len() == self.layers.len());
-        for i in 0..self.layers.len() {
-            self.layers[i].add(&weights_changes[i]);
-        }
-    }
-
 }
 
 
@@ -528,4 +531,46 @@ pub fn backpropagate_error_signal(&mut self, output_v : &VerboseOutput, signal :
     let mut hidden_signal = m_dot(&hidden_signals[0], &transpose(&self.layers[0]));
     hidden_signal.delete_last_col();
     return hidden_signal;
-}*/
\ No newline at end of file
+}*/
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use network::gate::activation::{TYPE_TANH, TYPE_GELU, TYPE_LOGISTIC};
+    use network::gate::traits::gradient_check::check_gradients;
+    use utils::random::new_rng;
+
+    fn mixed_gate() -> FeedforwardGate {
+        let activations = vec![Activation::new(TYPE_GELU), Activation::new(TYPE_TANH), Activation::new(TYPE_LOGISTIC)];
+        FeedforwardGate::new(4, 2, vec![3, 3], activations, &WeightInit::default(), &mut new_rng(Some(31)))
+    }
+
+    #[test]
+    fn per_layer_activations_round_trip() {
+        let gate = mixed_gate();
+        let str = gate.to_string();
+        assert!(str.starts_with("4 2 3 gelu tanh logistic\n"));
+        let parsed = FeedforwardGate::from_string(&str);
+        assert_eq!(parsed.activations.iter().map(|activation| activation.act_type).collect::<Vec<u8>>(),
+                   vec![TYPE_GELU, TYPE_TANH, TYPE_LOGISTIC]);
+        assert_eq!(parsed.to_string(), str);
+    }
+
+    #[test]
+    fn shared_activation_keeps_the_legacy_header() {
+        let gate = FeedforwardGate::new(4, 2, vec![3], vec![Activation::new(TYPE_TANH); 2], &WeightInit::default(), &mut new_rng(Some(31)));
+        let str = gate.to_string();
+        assert!(str.starts_with("4 2 2 tanh\n"));
+        let parsed = FeedforwardGate::from_string(&str);
+        assert!(parsed.activations.iter().all(|activation| activation.act_type == TYPE_TANH));
+        assert_eq!(parsed.to_string(), str);
+    }
+
+    #[test]
+    fn per_layer_gradients_match_finite_differences() {
+        let input = Matrix::new_row_from_datas(vec![0.4, -0.7, 0.2, -0.5]);
+        check_gradients(&mixed_gate(), &input);
+    }
+}
@@ -0,0 +1,284 @@
+extern crate rand;
+
+use std::ops::Add;
+
+use rand::Rng;
+
+use network::gate::traits::Gate;
+use network::gate::traits::GateOutput;
+use network::gate::traits::WeightsChanges;
+use network::gate::weight_init::WeightInit;
+
+use utils::math;
+use utils::matrix::Matrix;
+use utils::matrix_math::m_dot;
+use utils::matrix_math::mult_scl;
+use utils::matrix_math::transpose;
+use utils::matrix_math::row_append;
+use utils::traits::Parse;
+
+
+
+/**
+* A gated recurrent unit used as a memory gate
+* Its output is the new hidden state h, the previous one is read back from the input at state_offset
+* z = σ([x,1].Wz), r = σ([x,1].Wr), n = tanh([x',1].Wn) where x' is the input whose previous
+* hidden state is multiplied by r, and h = (1-z)*n + z*h_prev
+* - input_dimension : The gate input dimension
+* - hidden_dimension : The hidden state's dimension
+* - state_offset : The position of the previous hidden state in the input
+* - weights : The (input_dimension+1, 3*hidden_dimension) weights of the update gate, the
+*   reset gate and the candidate state, the last row holding the bias
+*/
+pub struct GruGate {
+    pub input_dimension : usize,
+    pub hidden_dimension : usize,
+    pub state_offset : usize,
+    pub weights : Matrix,
+}
+
+pub struct VerboseOutput {
+    pub input_bias : Matrix,
+    pub reset_input_bias : Matrix,
+    pub update_gate : Matrix,
+    pub reset_gate : Matrix,
+    pub candidate : Matrix,
+    pub hidden_prev : Matrix,
+    pub output : Matrix,
+}
+
+
+
+impl Clone for GruGate {
+    fn clone(&self) -> GruGate {
+        return GruGate {
+            input_dimension : self.input_dimension,
+            hidden_dimension : self.hidden_dimension,
+            state_offset : self.state_offset,
+            weights : self.weights.clone(),
+        }
+    }
+    fn clone_from(&mut self, source: &GruGate) {
+        self.input_dimension = source.input_dimension;
+        self.hidden_dimension = source.hidden_dimension;
+        self.state_offset = source.state_offset;
+        self.weights.clone_from(&source.weights);
+    }
+}
+impl Parse for GruGate {
+    fn to_string(&self) -> String {
+        let output = format!("{} {} {}", self.input_dimension, self.hidden_dimension, self.state_offset);
+        return output.add(&format!("\n{}", self.weights.to_string()));
+    }
+    fn from_string(str : &str) -> GruGate {
+        let lines : Vec<&str> = str.split("\n").collect();
+        assert!(lines.len() == 2); // header + weights
+
+        let header : Vec<&str> = lines[0].split(" ").collect();
+        assert!(header.len() == 3);
+        let input_dimension = header[0].parse().unwrap();
+        let hidden_dimension = header[1].parse().unwrap();
+        let state_offset = header[2].parse().unwrap();
+        let weights = Matrix::from_string(lines[1]);
+        assert!(weights.rows == input_dimension+1 && weights.cols == 3*hidden_dimension);
+
+        return GruGate {
+            input_dimension : input_dimension,
+            hidden_dimension : hidden_dimension,
+            state_offset : state_offset,
+            weights : weights,
+        }
+    }
+}
+
+
+
+impl Gate for GruGate {
+    type VerboseOutput = VerboseOutput;
+
+    fn input_dimension(&self) -> usize {
+        self.input_dimension
+    }
+
+    fn output_dimension(&self) -> usize {
+        self.hidden_dimension
+    }
+
+    /**
+    * Compute an input and return the computed output
+    * @input input : The input to compute
+    * @return The new hidden state
+    */
+    fn compute(&self, input : &Matrix) -> Matrix {
+        self.compute_verbose(input).output
+    }
+
+    /**
+    * Compute an input and return a VerboseOutput object
+    * containing the gates' activations
+    * @input input : The input to compute
+    * @return The VerboseOutput object
+    */
+    fn compute_verbose(&self, input : &Matrix) -> VerboseOutput {
+        assert!(input.is_row() && input.len == self.input_dimension);
+        let h = self.hidden_dimension;
+
+        let input_bias = row_append(input, 1.0);
+        let gates = m_dot(&input_bias, &self.weights);
+
+        let mut update_gate = Matrix::new_row(h);
+        let mut reset_gate = Matrix::new_row(h);
+        let mut hidden_prev = Matrix::new_row(h);
+        let mut reset_input_bias = input_bias.clone();
+        for k in 0..h {
+            update_gate.datas[k] = math::logistic(gates.datas[k]);
+            reset_gate.datas[k] = math::logistic(gates.datas[h+k]);
+            hidden_prev.datas[k] = input.datas[self.state_offset+k];
+            reset_input_bias.datas[self.state_offset+k] *= reset_gate.datas[k];
+        }
+
+        let reset_gates = m_dot(&reset_input_bias, &self.weights);
+        let mut candidate = Matrix::new_row(h);
+        let mut output = Matrix::new_row(h);
+        for k in 0..h {
+            candidate.datas[k] = math::tanh_exact(reset_gates.datas[2*h+k]);
+            output.datas[k] = (1.0 - update_gate.datas[k]) * candidate.datas[k] + update_gate.datas[k] * hidden_prev.datas[k];
+        }
+
+        return VerboseOutput {
+            input_bias : input_bias,
+            reset_input_bias : reset_input_bias,
+            update_gate : update_gate,
+            reset_gate : reset_gate,
+            candidate : candidate,
+            hidden_prev : hidden_prev,
+            output : output,
+        }
+    }
+
+    /**
+    * Backpropagate an error signal to get the weights changes
+    * It won't apply the weights changes, use apply_changes() if you want to apply them
+    * @input output_v : The last computation's VerboseOutput object
+    * @input signal : The output error signal
+    * @input last_changes : The last weights changes, None if it's the first iteration
+    * @input momentum : The momentum
+    * @return A tuple composed by the input error signal and the weights changes
+    */
+    fn backpropagate_no_change(&self, output_v : &VerboseOutput, signal : &Matrix, learning_rate : f64,
+                               last_changes : &Option<WeightsChanges>, momentum : f64) -> (Matrix,WeightsChanges) {
+        assert!(signal.len == self.hidden_dimension);
+        let h = self.hidden_dimension;
+
+        // The candidate's signal, the only one computed from the reset input
+        let mut candidate_signal = Matrix::new_row(3*h);
+        for k in 0..h {
+            let z = output_v.update_gate.datas[k];
+            let n = output_v.candidate.datas[k];
+            candidate_signal.datas[2*h+k] = signal.datas[k] * (1.0 - z) * (1.0 - n*n);
+        }
+        let mut reset_input_signal = m_dot(&candidate_signal, &transpose(&self.weights));
+        reset_input_signal.delete_last_col();
+
+        let mut gates_signal = Matrix::new_row(3*h);
+        for k in 0..h {
+            let z = output_v.update_gate.datas[k];
+            let r = output_v.reset_gate.datas[k];
+            let reset_hidden_signal = reset_input_signal.datas[self.state_offset+k];
+            gates_signal.datas[k] = signal.datas[k] * (output_v.hidden_prev.datas[k] - output_v.candidate.datas[k]) * z * (1.0 - z);
+            gates_signal.datas[h+k] = reset_hidden_signal * output_v.hidden_prev.datas[k] * r * (1.0 - r);
+        }
+
+        let mut weights_delta = m_dot(&transpose(&output_v.input_bias), &gates_signal);
+        weights_delta.add(&m_dot(&transpose(&output_v.reset_input_bias), &candidate_signal));
+        weights_delta.mult_scl(-learning_rate);
+        if let Some(ref last_changes) = *last_changes {
+            weights_delta.add(&mult_scl(&last_changes[0], momentum));
+        }
+
+        let mut input_signal = m_dot(&gates_signal, &transpose(&self.weights));
+        input_signal.delete_last_col();
+        for i in 0..self.input_dimension {
+            input_signal.datas[i] += reset_input_signal.datas[i];
+        }
+        for k in 0..h {
+            // The previous hidden state went through the reset gate and the update gate
+            let reset_hidden_signal = reset_input_signal.datas[self.state_offset+k];
+            input_signal.datas[self.state_offset+k] += reset_hidden_signal * (output_v.reset_gate.datas[k] - 1.0)
+                                                       + signal.datas[k] * output_v.update_gate.datas[k];
+        }
+
+        assert!(input_signal.is_finite());
+        return (input_signal, vec![weights_delta]);
+    }
+
+    fn parameters(&self) -> Vec<&Matrix> {
+        vec![&self.weights]
+    }
+
+    fn parameters_mut(&mut self) -> Vec<&mut Matrix> {
+        vec![&mut self.weights]
+    }
+}
+impl GateOutput for VerboseOutput {
+    fn output(&self) -> &Matrix {
+        &self.output
+    }
+}
+
+
+
+impl GruGate {
+
+    /**
+    * Create a new GruGate object
+    * @input input_dimension : The gate input dimension
+    * @input hidden_dimension : The hidden state's dimension
+    * @input state_offset : The position of the previous hidden state in the input
+    * @input weight_init : The weights initialization
+    * @input rand : The random number generator used to initialize the weights
+    * @return The newly created GruGate object
+    */
+    pub fn new<R: Rng>(input_dimension : usize, hidden_dimension : usize, state_offset : usize,
+                       weight_init : &WeightInit, rand : &mut R) -> GruGate {
+        assert!(input_dimension > 0 && hidden_dimension > 0);
+        assert!(state_offset + hidden_dimension <= input_dimension);
+        let mut weights = Matrix::new(input_dimension+1, 3*hidden_dimension);
+        weight_init.init_layer(&mut weights, rand);
+        return GruGate {
+            input_dimension : input_dimension,
+            hidden_dimension : hidden_dimension,
+            state_offset : state_offset,
+            weights : weights,
+        }
+    }
+
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use network::gate::traits::gradient_check::check_gradients;
+    use utils::random::new_rng;
+
+    fn tiny_gate() -> GruGate {
+        // 2 values of input followed by the previous hidden state
+        GruGate::new(4, 2, 2, &WeightInit::default(), &mut new_rng(Some(46)))
+    }
+
+    #[test]
+    fn gradients_match_finite_differences() {
+        let input = Matrix::new_row_from_datas(vec![0.4, -0.7, 0.2, -0.5]);
+        check_gradients(&tiny_gate(), &input);
+    }
+
+    #[test]
+    fn parse_round_trip() {
+        let gate = tiny_gate();
+        let parsed = GruGate::from_string(&gate.to_string());
+        assert_eq!(parsed.to_string(), gate.to_string());
+        assert_eq!(parsed.state_offset, gate.state_offset);
+    }
+}
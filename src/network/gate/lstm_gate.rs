@@ -0,0 +1,286 @@
+extern crate rand;
+
+use std::ops::Add;
+
+use rand::Rng;
+
+use network::gate::traits::Gate;
+use network::gate::traits::GateOutput;
+use network::gate::traits::WeightsChanges;
+use network::gate::weight_init::WeightInit;
+
+use utils::math;
+use utils::matrix::Matrix;
+use utils::matrix_math::m_dot;
+use utils::matrix_math::mult_scl;
+use utils::matrix_math::transpose;
+use utils::matrix_math::row_append;
+use utils::traits::Parse;
+
+
+
+/**
+* A LSTM cell used as a memory gate
+* Its output is the hidden state followed by the cell state, [h, c], so the cell state
+* is carried in Cesure's context like the hidden state and the previous [h, c] is read
+* back from the input at state_offset
+* - input_dimension : The gate input dimension
+* - hidden_dimension : The hidden and cell states' dimension, the output is twice as long
+* - state_offset : The position of the previous [h, c] in the input
+* - weights : The (input_dimension+1, 4*hidden_dimension) weights of the input, forget,
+*   cell and output gates, the last row holding the bias
+*/
+pub struct LstmGate {
+    pub input_dimension : usize,
+    pub hidden_dimension : usize,
+    pub state_offset : usize,
+    pub weights : Matrix,
+}
+
+pub struct VerboseOutput {
+    pub input_bias : Matrix,
+    pub input_gate : Matrix,
+    pub forget_gate : Matrix,
+    pub cell_gate : Matrix,
+    pub output_gate : Matrix,
+    pub cell_prev : Matrix,
+    pub cell_tanh : Matrix,
+    pub output : Matrix,
+}
+
+
+
+impl Clone for LstmGate {
+    fn clone(&self) -> LstmGate {
+        return LstmGate {
+            input_dimension : self.input_dimension,
+            hidden_dimension : self.hidden_dimension,
+            state_offset : self.state_offset,
+            weights : self.weights.clone(),
+        }
+    }
+    fn clone_from(&mut self, source: &LstmGate) {
+        self.input_dimension = source.input_dimension;
+        self.hidden_dimension = source.hidden_dimension;
+        self.state_offset = source.state_offset;
+        self.weights.clone_from(&source.weights);
+    }
+}
+impl Parse for LstmGate {
+    fn to_string(&self) -> String {
+        let output = format!("{} {} {}", self.input_dimension, self.hidden_dimension, self.state_offset);
+        return output.add(&format!("\n{}", self.weights.to_string()));
+    }
+    fn from_string(str : &str) -> LstmGate {
+        let lines : Vec<&str> = str.split("\n").collect();
+        assert!(lines.len() == 2); // header + weights
+
+        let header : Vec<&str> = lines[0].split(" ").collect();
+        assert!(header.len() == 3);
+        let input_dimension = header[0].parse().unwrap();
+        let hidden_dimension = header[1].parse().unwrap();
+        let state_offset = header[2].parse().unwrap();
+        let weights = Matrix::from_string(lines[1]);
+        assert!(weights.rows == input_dimension+1 && weights.cols == 4*hidden_dimension);
+
+        return LstmGate {
+            input_dimension : input_dimension,
+            hidden_dimension : hidden_dimension,
+            state_offset : state_offset,
+            weights : weights,
+        }
+    }
+}
+
+
+
+impl Gate for LstmGate {
+    type VerboseOutput = VerboseOutput;
+
+    fn input_dimension(&self) -> usize {
+        self.input_dimension
+    }
+
+    fn output_dimension(&self) -> usize {
+        2 * self.hidden_dimension
+    }
+
+    /**
+    * Compute an input and return the computed output
+    * @input input : The input to compute
+    * @return The new [h, c]
+    */
+    fn compute(&self, input : &Matrix) -> Matrix {
+        self.compute_verbose(input).output
+    }
+
+    /**
+    * Compute an input and return a VerboseOutput object
+    * containing the gates' activations
+    * @input input : The input to compute
+    * @return The VerboseOutput object
+    */
+    fn compute_verbose(&self, input : &Matrix) -> VerboseOutput {
+        assert!(input.is_row() && input.len == self.input_dimension);
+        let h = self.hidden_dimension;
+
+        let input_bias = row_append(input, 1.0);
+        let z = m_dot(&input_bias, &self.weights);
+
+        let mut input_gate = Matrix::new_row(h);
+        let mut forget_gate = Matrix::new_row(h);
+        let mut cell_gate = Matrix::new_row(h);
+        let mut output_gate = Matrix::new_row(h);
+        let mut cell_prev = Matrix::new_row(h);
+        let mut cell_tanh = Matrix::new_row(h);
+        let mut output = Matrix::new_row(2*h);
+        for k in 0..h {
+            input_gate.datas[k] = math::logistic(z.datas[k]);
+            forget_gate.datas[k] = math::logistic(z.datas[h+k]);
+            cell_gate.datas[k] = math::tanh_exact(z.datas[2*h+k]);
+            output_gate.datas[k] = math::logistic(z.datas[3*h+k]);
+            cell_prev.datas[k] = input.datas[self.state_offset+h+k];
+
+            let cell = forget_gate.datas[k] * cell_prev.datas[k] + input_gate.datas[k] * cell_gate.datas[k];
+            cell_tanh.datas[k] = math::tanh_exact(cell);
+            output.datas[k] = output_gate.datas[k] * cell_tanh.datas[k];
+            output.datas[h+k] = cell;
+        }
+
+        return VerboseOutput {
+            input_bias : input_bias,
+            input_gate : input_gate,
+            forget_gate : forget_gate,
+            cell_gate : cell_gate,
+            output_gate : output_gate,
+            cell_prev : cell_prev,
+            cell_tanh : cell_tanh,
+            output : output,
+        }
+    }
+
+    /**
+    * Backpropagate an error signal to get the weights changes
+    * It won't apply the weights changes, use apply_changes() if you want to apply them
+    * The cell state's signal flows back to the previous cell state through the forget gate,
+    * it is added to the input signal at the previous [h, c]'s position
+    * @input output_v : The last computation's VerboseOutput object
+    * @input signal : The output error signal, on the hidden state then the cell state
+    * @input last_changes : The last weights changes, None if it's the first iteration
+    * @input momentum : The momentum
+    * @return A tuple composed by the input error signal and the weights changes
+    */
+    fn backpropagate_no_change(&self, output_v : &VerboseOutput, signal : &Matrix, learning_rate : f64,
+                               last_changes : &Option<WeightsChanges>, momentum : f64) -> (Matrix,WeightsChanges) {
+        assert!(signal.len == Gate::output_dimension(self));
+        let h = self.hidden_dimension;
+
+        let mut z_signal = Matrix::new_row(4*h);
+        let mut cell_prev_signal = Matrix::new_row(h);
+        for k in 0..h {
+            let i = output_v.input_gate.datas[k];
+            let f = output_v.forget_gate.datas[k];
+            let g = output_v.cell_gate.datas[k];
+            let o = output_v.output_gate.datas[k];
+            let tanh_c = output_v.cell_tanh.datas[k];
+
+            let hidden_signal = signal.datas[k];
+            let cell_signal = signal.datas[h+k] + hidden_signal * o * (1.0 - tanh_c*tanh_c);
+
+            z_signal.datas[k] = cell_signal * g * i * (1.0 - i);
+            z_signal.datas[h+k] = cell_signal * output_v.cell_prev.datas[k] * f * (1.0 - f);
+            z_signal.datas[2*h+k] = cell_signal * i * (1.0 - g*g);
+            z_signal.datas[3*h+k] = hidden_signal * tanh_c * o * (1.0 - o);
+            cell_prev_signal.datas[k] = cell_signal * f;
+        }
+
+        let mut weights_delta = m_dot(&transpose(&output_v.input_bias), &z_signal);
+        weights_delta.mult_scl(-learning_rate);
+        if let Some(ref last_changes) = *last_changes {
+            weights_delta.add(&mult_scl(&last_changes[0], momentum));
+        }
+
+        let mut input_signal = m_dot(&z_signal, &transpose(&self.weights));
+        input_signal.delete_last_col();
+        for k in 0..h {
+            input_signal.datas[self.state_offset+h+k] += cell_prev_signal.datas[k];
+        }
+
+        assert!(input_signal.is_finite());
+        return (input_signal, vec![weights_delta]);
+    }
+
+    fn parameters(&self) -> Vec<&Matrix> {
+        vec![&self.weights]
+    }
+
+    fn parameters_mut(&mut self) -> Vec<&mut Matrix> {
+        vec![&mut self.weights]
+    }
+}
+impl GateOutput for VerboseOutput {
+    fn output(&self) -> &Matrix {
+        &self.output
+    }
+}
+
+
+
+impl LstmGate {
+
+    /**
+    * Create a new LstmGate object
+    * @input input_dimension : The gate input dimension
+    * @input hidden_dimension : The hidden and cell states' dimension
+    * @input state_offset : The position of the previous [h, c] in the input
+    * @input weight_init : The weights initialization, the forget gate's bias is then set to 1
+    * @input rand : The random number generator used to initialize the weights
+    * @return The newly created LstmGate object
+    */
+    pub fn new<R: Rng>(input_dimension : usize, hidden_dimension : usize, state_offset : usize,
+                       weight_init : &WeightInit, rand : &mut R) -> LstmGate {
+        assert!(input_dimension > 0 && hidden_dimension > 0);
+        assert!(state_offset + 2*hidden_dimension <= input_dimension);
+        let mut weights = Matrix::new(input_dimension+1, 4*hidden_dimension);
+        weight_init.init_layer(&mut weights, rand);
+        // Remember by default, the usual LSTM forget bias
+        for j in hidden_dimension..(2*hidden_dimension) {
+            weights[(input_dimension, j)] = 1.0;
+        }
+        return LstmGate {
+            input_dimension : input_dimension,
+            hidden_dimension : hidden_dimension,
+            state_offset : state_offset,
+            weights : weights,
+        }
+    }
+
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use network::gate::traits::gradient_check::check_gradients;
+    use utils::random::new_rng;
+
+    fn tiny_gate() -> LstmGate {
+        // 2 values of input followed by the previous [h, c]
+        LstmGate::new(6, 2, 2, &WeightInit::default(), &mut new_rng(Some(45)))
+    }
+
+    #[test]
+    fn gradients_match_finite_differences() {
+        let input = Matrix::new_row_from_datas(vec![0.4, -0.7, 0.2, -0.1, 0.5, -0.3]);
+        check_gradients(&tiny_gate(), &input);
+    }
+
+    #[test]
+    fn parse_round_trip() {
+        let gate = tiny_gate();
+        let parsed = LstmGate::from_string(&gate.to_string());
+        assert_eq!(parsed.to_string(), gate.to_string());
+        assert_eq!(parsed.state_offset, gate.state_offset);
+    }
+}
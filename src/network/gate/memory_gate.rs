@@ -0,0 +1,235 @@
+use std::ops::Add;
+
+use network::gate::feedforward_gate;
+use network::gate::feedforward_gate::FeedforwardGate;
+use network::gate::lstm_gate;
+use network::gate::lstm_gate::LstmGate;
+use network::gate::gru_gate;
+use network::gate::gru_gate::GruGate;
+use network::gate::traits::Gate;
+use network::gate::traits::GateOutput;
+use network::gate::traits::WeightsChanges;
+
+use utils::matrix::Matrix;
+use utils::traits::Parse;
+
+
+// The context is overwritten by a feedforward network every tick
+pub const MEMORY_TYPE_FEEDFORWARD : u8 = 0;
+// The context is the hidden and cell states of a LSTM cell
+pub const MEMORY_TYPE_LSTM : u8 = 1;
+// The context is the hidden state of a gated recurrent unit
+pub const MEMORY_TYPE_GRU : u8 = 2;
+
+
+/**
+* The gate computing Cesure's next context
+*/
+pub enum MemoryGate {
+    Feedforward(FeedforwardGate),
+    Lstm(LstmGate),
+    Gru(GruGate),
+}
+
+pub enum VerboseOutput {
+    Feedforward(feedforward_gate::VerboseOutput),
+    Lstm(lstm_gate::VerboseOutput),
+    Gru(gru_gate::VerboseOutput),
+}
+
+
+
+impl Clone for MemoryGate {
+    fn clone(&self) -> MemoryGate {
+        match *self {
+            MemoryGate::Feedforward(ref gate) => MemoryGate::Feedforward(gate.clone()),
+            MemoryGate::Lstm(ref gate) => MemoryGate::Lstm(gate.clone()),
+            MemoryGate::Gru(ref gate) => MemoryGate::Gru(gate.clone()),
+        }
+    }
+    fn clone_from(&mut self, source: &MemoryGate) {
+        *self = source.clone();
+    }
+}
+impl Parse for MemoryGate {
+    fn to_string(&self) -> String {
+        match *self {
+            MemoryGate::Feedforward(ref gate) => gate.to_string(),
+            MemoryGate::Lstm(ref gate) => String::from("LSTM\n").add(gate.to_string().as_str()),
+            MemoryGate::Gru(ref gate) => String::from("GRU\n").add(gate.to_string().as_str()),
+        }
+    }
+    fn from_string(str : &str) -> MemoryGate {
+        // The feedforward gate has no type line, as in the files saved before the LSTM
+        if let Some(lstm) = str.strip_prefix("LSTM\n") {
+            MemoryGate::Lstm(LstmGate::from_string(lstm))
+        } else if let Some(gru) = str.strip_prefix("GRU\n") {
+            MemoryGate::Gru(GruGate::from_string(gru))
+        } else {
+            MemoryGate::Feedforward(FeedforwardGate::from_string(str))
+        }
+    }
+}
+
+
+
+impl GateOutput for VerboseOutput {
+    fn output(&self) -> &Matrix {
+        match *self {
+            VerboseOutput::Feedforward(ref output_v) => output_v.output(),
+            VerboseOutput::Lstm(ref output_v) => output_v.output(),
+            VerboseOutput::Gru(ref output_v) => output_v.output(),
+        }
+    }
+}
+impl Gate for MemoryGate {
+    type VerboseOutput = VerboseOutput;
+
+    fn input_dimension(&self) -> usize {
+        match *self {
+            MemoryGate::Feedforward(ref gate) => gate.input_dimension(),
+            MemoryGate::Lstm(ref gate) => gate.input_dimension(),
+            MemoryGate::Gru(ref gate) => gate.input_dimension(),
+        }
+    }
+
+    fn output_dimension(&self) -> usize {
+        match *self {
+            MemoryGate::Feedforward(ref gate) => gate.output_dimension(),
+            MemoryGate::Lstm(ref gate) => gate.output_dimension(),
+            MemoryGate::Gru(ref gate) => gate.output_dimension(),
+        }
+    }
+
+    fn compute(&self, input : &Matrix) -> Matrix {
+        match *self {
+            MemoryGate::Feedforward(ref gate) => gate.compute(input),
+            MemoryGate::Lstm(ref gate) => gate.compute(input),
+            MemoryGate::Gru(ref gate) => gate.compute(input),
+        }
+    }
+
+    fn compute_verbose(&self, input : &Matrix) -> VerboseOutput {
+        match *self {
+            MemoryGate::Feedforward(ref gate) => VerboseOutput::Feedforward(gate.compute_verbose(input)),
+            MemoryGate::Lstm(ref gate) => VerboseOutput::Lstm(gate.compute_verbose(input)),
+            MemoryGate::Gru(ref gate) => VerboseOutput::Gru(gate.compute_verbose(input)),
+        }
+    }
+
+    fn backpropagate_no_change(&self, output_v : &VerboseOutput, signal : &Matrix, learning_rate : f64,
+                               last_changes : &Option<WeightsChanges>, momentum : f64) -> (Matrix,WeightsChanges) {
+        match (self, output_v) {
+            (MemoryGate::Feedforward(gate), VerboseOutput::Feedforward(output_v)) =>
+                gate.backpropagate_no_change(output_v, signal, learning_rate, last_changes, momentum),
+            (MemoryGate::Lstm(gate), VerboseOutput::Lstm(output_v)) =>
+                gate.backpropagate_no_change(output_v, signal, learning_rate, last_changes, momentum),
+            (MemoryGate::Gru(gate), VerboseOutput::Gru(output_v)) =>
+                gate.backpropagate_no_change(output_v, signal, learning_rate, last_changes, momentum),
+            _ => panic!("The VerboseOutput wasn't computed by this memory gate"),
+        }
+    }
+
+    fn parameters(&self) -> Vec<&Matrix> {
+        match *self {
+            MemoryGate::Feedforward(ref gate) => gate.parameters(),
+            MemoryGate::Lstm(ref gate) => gate.parameters(),
+            MemoryGate::Gru(ref gate) => gate.parameters(),
+        }
+    }
+
+    fn parameters_mut(&mut self) -> Vec<&mut Matrix> {
+        match *self {
+            MemoryGate::Feedforward(ref mut gate) => gate.parameters_mut(),
+            MemoryGate::Lstm(ref mut gate) => gate.parameters_mut(),
+            MemoryGate::Gru(ref mut gate) => gate.parameters_mut(),
+        }
+    }
+}
+
+
+
+impl MemoryGate {
+
+    /**
+    * @return MEMORY_TYPE_FEEDFORWARD, MEMORY_TYPE_LSTM or MEMORY_TYPE_GRU
+    */
+    pub fn memory_type(&self) -> u8 {
+        match *self {
+            MemoryGate::Feedforward(_) => MEMORY_TYPE_FEEDFORWARD,
+            MemoryGate::Lstm(_) => MEMORY_TYPE_LSTM,
+            MemoryGate::Gru(_) => MEMORY_TYPE_GRU,
+        }
+    }
+
+}
+
+
+
+/**
+* Convert a memory type to its name
+* @input memory_type : MEMORY_TYPE_FEEDFORWARD, MEMORY_TYPE_LSTM or MEMORY_TYPE_GRU
+* @return The name
+*/
+pub fn memory_type_to_string(memory_type : u8) -> &'static str {
+    match memory_type {
+        MEMORY_TYPE_FEEDFORWARD => "feedforward",
+        MEMORY_TYPE_LSTM => "lstm",
+        MEMORY_TYPE_GRU => "gru",
+        _ => panic!("Unknown memory type"),
+    }
+}
+
+/**
+* Convert a memory type name to the memory type
+* @input str : "feedforward", "lstm" or "gru"
+* @return The memory type
+*/
+pub fn memory_type_from_string(str : &str) -> u8 {
+    match str {
+        "feedforward" => MEMORY_TYPE_FEEDFORWARD,
+        "lstm" => MEMORY_TYPE_LSTM,
+        "gru" => MEMORY_TYPE_GRU,
+        _ => panic!("Unknown memory type {}", str),
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use network::gate::weight_init::WeightInit;
+    use utils::random::new_rng;
+
+    #[test]
+    fn lstm_round_trip() {
+        let gate = MemoryGate::Lstm(LstmGate::new(6, 2, 2, &WeightInit::default(), &mut new_rng(Some(45))));
+        let str = gate.to_string();
+        assert!(str.starts_with("LSTM\n"));
+        let parsed = MemoryGate::from_string(&str);
+        assert_eq!(parsed.memory_type(), MEMORY_TYPE_LSTM);
+        assert_eq!(parsed.to_string(), str);
+    }
+
+    #[test]
+    fn gru_round_trip() {
+        let gate = MemoryGate::Gru(GruGate::new(4, 2, 2, &WeightInit::default(), &mut new_rng(Some(46))));
+        let str = gate.to_string();
+        assert!(str.starts_with("GRU\n"));
+        let parsed = MemoryGate::from_string(&str);
+        assert_eq!(parsed.memory_type(), MEMORY_TYPE_GRU);
+        assert_eq!(parsed.to_string(), str);
+    }
+
+    #[test]
+    fn legacy_section_loads_as_feedforward() {
+        // A MEMORY_GATE section saved before the memory types, a feedforward gate without type line
+        let str = "3 2 1 tanh\n4 2 0.1 -0.2 0.3 -0.4 0.5 -0.6 0.7 -0.8";
+        let gate = MemoryGate::from_string(str);
+        assert_eq!(gate.memory_type(), MEMORY_TYPE_FEEDFORWARD);
+        assert_eq!(gate.input_dimension(), 3);
+        assert_eq!(gate.output_dimension(), 2);
+        assert_eq!(gate.to_string(), str);
+    }
+}
@@ -1,3 +1,8 @@
 
 pub mod activation;
-pub mod feedforward_gate;
\ No newline at end of file
+pub mod feedforward_gate;
+pub mod gru_gate;
+pub mod lstm_gate;
+pub mod memory_gate;
+pub mod traits;
+pub mod weight_init;
\ No newline at end of file
@@ -0,0 +1,172 @@
+extern crate rand;
+
+use rand::Rng;
+
+use utils::matrix::Matrix;
+use utils::traits::Parse;
+
+
+pub type WeightsChanges = Vec<Matrix>;
+
+
+/**
+* The computation details a gate keeps to be backpropagated
+*/
+pub trait GateOutput : Send {
+    /**
+    * @return The gate's computed output
+    */
+    fn output(&self) -> &Matrix;
+}
+
+
+/**
+* A layer Cesure and its trainers can use as output or memory gate
+* The weights are exposed as parameters, a list of matrices in a fixed order,
+* which the weights changes returned by the backpropagation follow
+*/
+pub trait Gate : Parse + Clone + Send + Sync {
+    type VerboseOutput : GateOutput;
+
+    fn input_dimension(&self) -> usize;
+    fn output_dimension(&self) -> usize;
+
+    /**
+    * Compute an input and return the computed output
+    * @input input : The input to compute
+    * @return The computed output
+    */
+    fn compute(&self, input : &Matrix) -> Matrix;
+
+    /**
+    * Compute an input and return a VerboseOutput object to backpropagate
+    * @input input : The input to compute
+    * @return The VerboseOutput object
+    */
+    fn compute_verbose(&self, input : &Matrix) -> Self::VerboseOutput;
+
+    /**
+    * Backpropagate an error signal to get the weights changes
+    * It won't apply the weights changes, use apply_changes() if you want to apply them
+    * @input output_v : The last computation's VerboseOutput object
+    * @input signal : The output error signal
+    * @input last_changes : The last weights changes, None if it's the first iteration
+    * @input momentum : The momentum
+    * @return A tuple composed by the input error signal and the weights changes
+    */
+    fn backpropagate_no_change(&self, output_v : &Self::VerboseOutput, signal : &Matrix, learning_rate : f64,
+                               last_changes : &Option<WeightsChanges>, momentum : f64) -> (Matrix,WeightsChanges);
+
+    /**
+    * @return The weights matrices
+    */
+    fn parameters(&self) -> Vec<&Matrix>;
+
+    /**
+    * @return The weights matrices, to be changed
+    */
+    fn parameters_mut(&mut self) -> Vec<&mut Matrix>;
+
+    /**
+    * Apply the weights changes
+    * @input weights_changes : The weights changes to apply, in the parameters' order
+    */
+    fn apply_changes(&mut self, weights_changes : &WeightsChanges) {
+        let mut parameters = self.parameters_mut();
+        assert!(weights_changes.len() == parameters.len());
+        for i in 0..parameters.len() {
+            parameters[i].add(&weights_changes[i]);
+        }
+    }
+
+    /**
+    * @input magnitude : The amount of random
+    * @input rand : The random number generator
+    * @return The cloned gate, with some random changes
+    */
+    fn clone_randomized<R: Rng>(&self, magnitude : f64, rand : &mut R) -> Self {
+        let mut output = self.clone();
+        for parameter in output.parameters_mut() {
+            *parameter = parameter.clone_randomized(magnitude, rand);
+        }
+        output
+    }
+
+    /**
+    * Return the number of neurons in this network
+    * @return The number of neurons
+    */
+    fn get_nb_neurons(&self) -> usize {
+        self.parameters().iter().map(|parameter| parameter.len).sum()
+    }
+
+    /**
+    * Print all the weights
+    */
+    fn print(&self) {
+        for (i, parameter) in self.parameters().iter().enumerate() {
+            println!("Layer #{} :", i);
+            parameter.print();
+        }
+    }
+}
+
+
+
+/**
+* Gradient checks shared by the gates' tests
+*/
+#[cfg(test)]
+pub mod gradient_check {
+    use super::*;
+
+    const EPSILON : f64 = 1e-6;
+    const TOLERANCE : f64 = 1e-6;
+
+    /**
+    * The error checked, the dot product of the output with a fixed signal,
+    * so its gradient with respect to the output is the signal itself
+    */
+    fn error<G : Gate>(gate : &G, input : &Matrix, signal : &Matrix) -> f64 {
+        let output = gate.compute(input);
+        (0..output.len).map(|k| output.datas[k] * signal.datas[k]).sum()
+    }
+
+    fn assert_close(analytic : f64, numeric : f64, what : &str) {
+        assert!((analytic - numeric).abs() < TOLERANCE * (1.0 + numeric.abs()),
+                "{} : backpropagated {} but the finite difference gives {}", what, analytic, numeric);
+    }
+
+    /**
+    * Check backpropagate_no_change against (E(w+ε)-E(w-ε))/2ε for every weight and input value
+    * @input gate : The gate to check
+    * @input input : The input it computes
+    */
+    pub fn check_gradients<G : Gate>(gate : &G, input : &Matrix) {
+        let signal = Matrix::new_row_from_datas((0..gate.output_dimension())
+            .map(|k| if k % 2 == 0 { 0.5 + 0.1 * k as f64 } else { -0.3 - 0.1 * k as f64 }).collect());
+        let output_v = gate.compute_verbose(input);
+        // With a learning rate of 1 and no momentum, the changes are the opposite of the gradient
+        let (input_signal, changes) = gate.backpropagate_no_change(&output_v, &signal, 1.0, &None, 0.0);
+
+        for p in 0..changes.len() {
+            for i in 0..changes[p].len {
+                let mut plus = gate.clone();
+                plus.parameters_mut()[p].datas[i] += EPSILON;
+                let mut minus = gate.clone();
+                minus.parameters_mut()[p].datas[i] -= EPSILON;
+                let numeric = (error(&plus, input, &signal) - error(&minus, input, &signal)) / (2.0 * EPSILON);
+                assert_close(-changes[p].datas[i], numeric, &format!("Parameter {} weight {}", p, i));
+            }
+        }
+
+        for i in 0..input.len {
+            let mut plus = input.clone();
+            plus.datas[i] += EPSILON;
+            let mut minus = input.clone();
+            minus.datas[i] -= EPSILON;
+            let numeric = (error(gate, &plus, &signal) - error(gate, &minus, &signal)) / (2.0 * EPSILON);
+            assert_close(input_signal.datas[i], numeric, &format!("Input {}", i));
+        }
+    }
+}
@@ -0,0 +1,293 @@
+extern crate rand;
+
+use rand::Rng;
+use rand::distributions::{Normal, IndependentSample};
+
+use utils::matrix::Matrix;
+
+
+pub const TYPE_UNIFORM : u8 = 0;
+pub const TYPE_XAVIER_UNIFORM : u8 = 1;
+pub const TYPE_XAVIER_NORMAL : u8 = 2;
+pub const TYPE_HE : u8 = 3;
+pub const TYPE_LECUN : u8 = 4;
+pub const TYPE_ORTHOGONAL : u8 = 5;
+
+pub const BIAS_TYPE_WEIGHTS : u8 = 0;
+pub const BIAS_TYPE_ZERO : u8 = 1;
+pub const BIAS_TYPE_CONSTANT : u8 = 2;
+
+
+/**
+* Describe how a gate's layers are initialized
+* A layer's last row holds the bias weights, the other rows the inputs' weights
+* - init_type : The inputs' weights initialization
+* - bias_type : BIAS_TYPE_WEIGHTS initializes the bias row like the other rows,
+*   BIAS_TYPE_ZERO sets it to 0 and BIAS_TYPE_CONSTANT to bias_value
+*/
+pub struct WeightInit {
+    pub init_type : u8,
+    pub bias_type : u8,
+    pub bias_value : f64,
+}
+
+
+
+impl Clone for WeightInit {
+    fn clone(&self) -> WeightInit {
+        WeightInit {
+            init_type : self.init_type,
+            bias_type : self.bias_type,
+            bias_value : self.bias_value,
+        }
+    }
+    fn clone_from(&mut self, source: &WeightInit) {
+        self.init_type = source.init_type;
+        self.bias_type = source.bias_type;
+        self.bias_value = source.bias_value;
+    }
+}
+
+impl Default for WeightInit {
+    /**
+    * The initialization of a new Cesure's gates when none is chosen : Xavier uniform, zero biases
+    */
+    fn default() -> WeightInit {
+        WeightInit::new(TYPE_XAVIER_UNIFORM, BIAS_TYPE_ZERO, 0.0)
+    }
+}
+
+
+
+impl WeightInit {
+
+    pub fn new(init_type : u8, bias_type : u8, bias_value : f64) -> WeightInit {
+        assert!(init_type <= TYPE_ORTHOGONAL, "Unknown weight initialization type");
+        assert!(bias_type <= BIAS_TYPE_CONSTANT, "Unknown bias initialization type");
+        return WeightInit {
+            init_type : init_type,
+            bias_type : bias_type,
+            bias_value : bias_value,
+        }
+    }
+
+    /**
+    * The initialization used before the schemes existed : uniform in [-1, 1] everywhere
+    */
+    pub fn legacy() -> WeightInit {
+        WeightInit::new(TYPE_UNIFORM, BIAS_TYPE_WEIGHTS, 0.0)
+    }
+
+    /**
+    * Initialize a layer's weights
+    * @input layer : The layer, of dimension (fan_in+1, fan_out)
+    * @input rand : The random number generator
+    */
+    pub fn init_layer<R: Rng>(&self, layer : &mut Matrix, rand : &mut R) {
+        assert!(layer.rows >= 2);
+        let fan_in = match self.bias_type {
+            BIAS_TYPE_WEIGHTS => layer.rows,
+            _ => layer.rows - 1,
+        };
+        let fan_out = layer.cols;
+
+        let mut weights = Matrix::new(fan_in, fan_out);
+        match self.init_type {
+            TYPE_UNIFORM => {
+                weights.set_random(-1.0, 1.0, rand);
+            }
+            TYPE_XAVIER_UNIFORM => {
+                let limit = (6.0 / (fan_in + fan_out) as f64).sqrt();
+                weights.set_random(-limit, limit, rand);
+            }
+            TYPE_XAVIER_NORMAL => {
+                set_random_normal(&mut weights, (2.0 / (fan_in + fan_out) as f64).sqrt(), rand);
+            }
+            TYPE_HE => {
+                set_random_normal(&mut weights, (2.0 / fan_in as f64).sqrt(), rand);
+            }
+            TYPE_LECUN => {
+                set_random_normal(&mut weights, (1.0 / fan_in as f64).sqrt(), rand);
+            }
+            TYPE_ORTHOGONAL => {
+                set_random_orthogonal(&mut weights, rand);
+            }
+            _ => panic!("Unknown weight initialization type"),
+        }
+
+        for i in 0..weights.len {
+            layer.datas[i] = weights.datas[i];
+        }
+        let bias_row = layer.rows - 1;
+        match self.bias_type {
+            BIAS_TYPE_WEIGHTS => {},
+            BIAS_TYPE_ZERO => {
+                for col in 0..layer.cols {
+                    layer[(bias_row,col)] = 0.0;
+                }
+            }
+            BIAS_TYPE_CONSTANT => {
+                for col in 0..layer.cols {
+                    layer[(bias_row,col)] = self.bias_value;
+                }
+            }
+            _ => panic!("Unknown bias initialization type"),
+        }
+    }
+
+}
+
+
+
+/**
+* Parse a weight initialization
+* It's written <scheme>[:<bias>], the scheme being "uniform", "xavier-uniform", "xavier-normal",
+* "he", "lecun" or "orthogonal" and the bias "weights", "zero" (the default) or a constant
+* @input str : The initialization, like "orthogonal" or "he:0.1"
+* @return The WeightInit
+*/
+pub fn parse_weight_init(str : &str) -> WeightInit {
+    let parsed : Vec<&str> = str.split(":").collect();
+    assert!(parsed.len() <= 2, "A weight initialization is written <scheme>[:<bias>]");
+    let init_type = match parsed[0] {
+        "uniform" => TYPE_UNIFORM,
+        "xavier-uniform" => TYPE_XAVIER_UNIFORM,
+        "xavier-normal" => TYPE_XAVIER_NORMAL,
+        "he" => TYPE_HE,
+        "lecun" => TYPE_LECUN,
+        "orthogonal" => TYPE_ORTHOGONAL,
+        _ => panic!("Unknown weight initialization {}", parsed[0]),
+    };
+    match parsed.get(1) {
+        None | Some(&"zero") => WeightInit::new(init_type, BIAS_TYPE_ZERO, 0.0),
+        Some(&"weights") => WeightInit::new(init_type, BIAS_TYPE_WEIGHTS, 0.0),
+        Some(bias) => WeightInit::new(init_type, BIAS_TYPE_CONSTANT, bias.parse().expect("Invalid bias initialization")),
+    }
+}
+
+fn set_random_normal<R: Rng>(matrix : &mut Matrix, std_dev : f64, rand : &mut R) {
+    let normal = Normal::new(0.0, std_dev);
+    for i in 0..matrix.len {
+        matrix.datas[i] = normal.ind_sample(rand);
+    }
+}
+
+/**
+* Fill a matrix with a random (semi-)orthogonal matrix : its rows, or its columns
+* if it has more rows than columns, are orthonormal
+* The vectors are drawn from a normal distribution then orthonormalized with
+* the modified Gram-Schmidt process
+*/
+fn set_random_orthogonal<R: Rng>(matrix : &mut Matrix, rand : &mut R) {
+    set_random_normal(matrix, 1.0, rand);
+    let by_cols = matrix.rows >= matrix.cols;
+    let (nb_vectors, vector_len) = match by_cols {
+        true => (matrix.cols, matrix.rows),
+        false => (matrix.rows, matrix.cols),
+    };
+    let index = |vector : usize, i : usize| -> (usize,usize) {
+        match by_cols {
+            true => (i, vector),
+            false => (vector, i),
+        }
+    };
+
+    for vector in 0..nb_vectors {
+        for previous in 0..vector {
+            let mut dot = 0.0;
+            for i in 0..vector_len {
+                dot += matrix[index(vector,i)] * matrix[index(previous,i)];
+            }
+            for i in 0..vector_len {
+                let delta = dot * matrix[index(previous,i)];
+                matrix[index(vector,i)] -= delta;
+            }
+        }
+        let mut norm = 0.0;
+        for i in 0..vector_len {
+            norm += matrix[index(vector,i)] * matrix[index(vector,i)];
+        }
+        let norm = norm.sqrt();
+        if norm > 1e-12 {
+            for i in 0..vector_len {
+                matrix[index(vector,i)] /= norm;
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use utils::random::new_rng;
+
+    /**
+    * Return the dot products of a layer's input rows or columns, as a matrix of dimension (n, n)
+    */
+    fn gram(layer : &Matrix, by_cols : bool) -> Vec<Vec<f64>> {
+        let nb_rows = layer.rows - 1;
+        let (nb_vectors, vector_len) = if by_cols { (layer.cols, nb_rows) } else { (nb_rows, layer.cols) };
+        let at = |vector : usize, i : usize| if by_cols { layer[(i,vector)] } else { layer[(vector,i)] };
+        (0..nb_vectors).map(|a| (0..nb_vectors).map(|b| (0..vector_len).map(|i| at(a,i) * at(b,i)).sum()).collect()).collect()
+    }
+
+    fn assert_orthonormal(gram : Vec<Vec<f64>>) {
+        for a in 0..gram.len() {
+            for b in 0..gram.len() {
+                let expected = if a == b { 1.0 } else { 0.0 };
+                assert!((gram[a][b] - expected).abs() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn orthogonal_vectors_are_orthonormal() {
+        let init = parse_weight_init("orthogonal");
+        let mut rand = new_rng(Some(29));
+        // More inputs than outputs : the columns are orthonormal
+        let mut tall = Matrix::new(7, 4);
+        init.init_layer(&mut tall, &mut rand);
+        assert_orthonormal(gram(&tall, true));
+        // More outputs than inputs : the rows are
+        let mut wide = Matrix::new(4, 7);
+        init.init_layer(&mut wide, &mut rand);
+        assert_orthonormal(gram(&wide, false));
+    }
+
+    #[test]
+    fn biases_only_set_the_last_row() {
+        let mut zero = Matrix::new(5, 3);
+        WeightInit::new(TYPE_HE, BIAS_TYPE_ZERO, 0.0).init_layer(&mut zero, &mut new_rng(Some(29)));
+        let mut constant = Matrix::new(5, 3);
+        WeightInit::new(TYPE_HE, BIAS_TYPE_CONSTANT, 0.5).init_layer(&mut constant, &mut new_rng(Some(29)));
+        for row in 0..4 {
+            for col in 0..3 {
+                assert_eq!(zero[(row,col)], constant[(row,col)]);
+                assert!(zero[(row,col)] != 0.0);
+            }
+        }
+        for col in 0..3 {
+            assert_eq!(zero[(4,col)], 0.0);
+            assert_eq!(constant[(4,col)], 0.5);
+        }
+    }
+
+    #[test]
+    fn weight_inits_parse() {
+        let he = parse_weight_init("he:0.1");
+        assert_eq!((he.init_type, he.bias_type, he.bias_value), (TYPE_HE, BIAS_TYPE_CONSTANT, 0.1));
+        let orthogonal = parse_weight_init("orthogonal");
+        assert_eq!((orthogonal.init_type, orthogonal.bias_type, orthogonal.bias_value), (TYPE_ORTHOGONAL, BIAS_TYPE_ZERO, 0.0));
+        let xavier = parse_weight_init("xavier-normal:weights");
+        assert_eq!((xavier.init_type, xavier.bias_type), (TYPE_XAVIER_NORMAL, BIAS_TYPE_WEIGHTS));
+        let zero = parse_weight_init("lecun:zero");
+        assert_eq!((zero.init_type, zero.bias_type), (TYPE_LECUN, BIAS_TYPE_ZERO));
+    }
+
+    #[test]
+    #[should_panic]
+    fn unknown_weight_inits_panic() {
+        parse_weight_init("glorot");
+    }
+}
@@ -1,4 +1,12 @@
 pub mod cesure;
 pub mod music;
 pub mod gate;
-pub mod training;
\ No newline at end of file
+pub mod training;
+pub mod evaluation;
+pub mod sampler;
+pub mod beam_search;
+pub mod constraints;
+pub mod conditioning;
+pub mod recurrent_level;
+pub mod attention;
+pub mod positional_encoding;
\ No newline at end of file
@@ -1,7 +1,8 @@
 
 use std::path::Path;
 
-use rimd::{SMF,SMFError,Event,TrackEvent,Track,SMFBuilder,MidiMessage, MetaEvent,SMFWriter};
+use rand::Rng;
+use rimd::{SMF,SMFError,Event,TrackEvent,Track,SMFBuilder,MidiMessage, MetaEvent,MetaCommand,SMFWriter};
 
 use network::training::training_set::TrainingSet;
 
@@ -11,11 +12,62 @@ use utils::matrix::Matrix;
 
 pub const INFOS_DIMENSION : usize = 3;
 pub const CHORD_DIMENSION : usize = 12*4; // nb_octaves * octave_range
+pub const CATEGORICAL_DIMENSION : usize = CHORD_DIMENSION + 1; // keys + rest
+pub const REST_INDEX : usize = CHORD_DIMENSION;
 
 pub const DIVISION_RANGE : f64 = 1000.0;
 pub const NB_TICKS_RANGE : f64 = 100000.0;
 pub const MIN_KEY_RANGE : f64 = 100.0;
 
+pub const TICKS_PER_QUARTER : usize = 4; // A tick is a sixteenth note
+
+
+
+/**
+* The tempo and time signature used to convert musical lengths to ticks
+* - bpm : The number of quarter notes per minute
+* - numerator, denominator : The time signature, such as 3/4
+*/
+pub struct Meter {
+    pub bpm : f64,
+    pub numerator : usize,
+    pub denominator : usize,
+}
+
+impl Default for Meter {
+    /**
+    * The meter written by CesureMusic::save : 120 bpm in 2/4
+    */
+    fn default() -> Meter {
+        Meter::new(120.0, 2, 4)
+    }
+}
+
+impl Meter {
+
+    pub fn new(bpm : f64, numerator : usize, denominator : usize) -> Meter {
+        assert!(bpm > 0.0 && numerator > 0);
+        assert!(denominator.is_power_of_two() && denominator <= TICKS_PER_QUARTER * 4);
+        return Meter {
+            bpm : bpm,
+            numerator : numerator,
+            denominator : denominator,
+        }
+    }
+
+    pub fn ticks_per_bar(&self) -> usize {
+        self.numerator * TICKS_PER_QUARTER * 4 / self.denominator
+    }
+
+    pub fn bars_to_ticks(&self, nb_bars : f64) -> usize {
+        (nb_bars * self.ticks_per_bar() as f64).round() as usize
+    }
+
+    pub fn seconds_to_ticks(&self, seconds : f64) -> usize {
+        (seconds * self.bpm / 60.0 * TICKS_PER_QUARTER as f64).round() as usize
+    }
+
+}
 
 
 pub struct CesureMusic {
@@ -29,9 +81,17 @@ impl CesureMusic {
         return CesureMusic::from_path(&Path::new(file_path));
     }
     pub fn from_path(file_path : &Path) -> CesureMusic {
+        CesureMusic::from_path_with_meter(file_path).0
+    }
+    /**
+    * Read a midi file
+    * @input file_path : The midi file's path
+    * @return The music, and the tempo and time signature of the file
+    */
+    pub fn from_path_with_meter(file_path : &Path) -> (CesureMusic, Meter) {
         match SMF::from_file(file_path) {
             Ok(smf) => {
-                return CesureMusic::from_smf(&smf);
+                return (CesureMusic::from_smf(&smf), meter_from_smf(&smf));
             }
             Err(e) => {
                 println!("Error reading {}", file_path.display());
@@ -49,7 +109,7 @@ impl CesureMusic {
         assert!(smf.tracks.len() == 2);
         let division = smf.division;
 
-        let tick_div = (division / 4) as u64;
+        let tick_div = (division as usize / TICKS_PER_QUARTER) as u64;
         let track = &smf.tracks[1];
         let parsed_notes = track_to_parsed_notes(track, tick_div);
 
@@ -63,8 +123,8 @@ impl CesureMusic {
             }
         }
 
-        let infos = Matrix::new_row_from_datas(vec![(division as f64)/DIVISION_RANGE, (nb_ticks as f64)/NB_TICKS_RANGE, (min_key as f64)/MIN_KEY_RANGE]);
-        let mut chords = Vec::with_capacity(nb_ticks as usize);
+        let infos = new_infos(division as f64, nb_ticks, min_key);
+        let mut chords = Vec::with_capacity(nb_ticks);
         for _ in 0..nb_ticks {
             chords.push(Matrix::new_row(CHORD_DIMENSION));
         }
@@ -96,15 +156,24 @@ impl CesureMusic {
     }
 
     pub fn save(&self, file_path : &str) {
+        self.save_with_meter(file_path, &Meter::default());
+    }
+
+    /**
+    * Save the music as a midi file, with a given tempo and time signature
+    * @input file_path : The midi file's path
+    * @input meter : The tempo and time signature
+    */
+    pub fn save_with_meter(&self, file_path : &str, meter : &Meter) {
 
         let mut builder = SMFBuilder::new();
 
         let division = self.infos.datas[0] * DIVISION_RANGE;
-        let tick_mult = division as u64 / 4;
+        let tick_mult = (division as usize / TICKS_PER_QUARTER) as u64;
 
         builder.add_track();
-        let tempo = MetaEvent::tempo_setting(500000);
-        let tsign = MetaEvent::time_signature(2, 2, 7, 160);
+        let tempo = MetaEvent::tempo_setting((60000000.0 / meter.bpm).round() as u32);
+        let tsign = MetaEvent::time_signature(meter.numerator as u8, meter.denominator.trailing_zeros() as u8, 7, 160);
         let eot = MetaEvent::end_of_track();
         builder.add_event(0, TrackEvent{vtime: 0, event: Event::Meta(tempo)});
         builder.add_event(0, TrackEvent{vtime: 0, event: Event::Meta(tsign)});
@@ -152,6 +221,7 @@ impl CesureMusic {
             infos : self.infos.clone(),
             inject_sequence : Vec::new(),
             compute_sequence : Vec::new(),
+            weight : 1.0,
         };
         for i in 0..nb_first_note_to_inject {
             training_set.inject_sequence.push(self.chords[i].clone());
@@ -173,6 +243,66 @@ impl CesureMusic {
         }
     }
 
+    /**
+    * Convert a chord to the categorical representation : a one-hot row over the
+    * keys and the rest token
+    * The highest active key is kept, as the melody is usually the top voice
+    * @input chord : The chord to convert
+    * @return The one-hot row, of dimension CATEGORICAL_DIMENSION
+    */
+    pub fn chord_to_categorical(chord : &Matrix) -> Matrix {
+        assert!(chord.is_row() && chord.len == CHORD_DIMENSION);
+        let mut output = Matrix::new_row(CATEGORICAL_DIMENSION);
+        let mut index = REST_INDEX;
+        for i in 0..CHORD_DIMENSION {
+            if chord.datas[i] > 0.5 {
+                index = i;
+            }
+        }
+        output.datas[index] = 1.0;
+        output
+    }
+
+    /**
+    * Convert a categorical index to a chord
+    * @input index : A key index, or REST_INDEX
+    * @return The chord, containing at most one note
+    */
+    pub fn categorical_to_chord(index : usize) -> Matrix {
+        assert!(index < CATEGORICAL_DIMENSION);
+        let mut chord = Matrix::new_row(CHORD_DIMENSION);
+        if index != REST_INDEX {
+            chord.datas[index] = 1.0;
+        }
+        chord
+    }
+
+    /**
+    * Sample a chord from a categorical distribution
+    * @input probabilities : The keys and rest probabilities
+    * @input rand : The random number generator
+    * @return The sampled chord, containing at most one note
+    */
+    pub fn sample_categorical<R: Rng>(probabilities : &Matrix, rand : &mut R) -> Matrix {
+        assert!(probabilities.is_row() && probabilities.len == CATEGORICAL_DIMENSION);
+        let x = rand.gen::<f64>();
+        let mut cumulated = 0.0;
+        for i in 0..CATEGORICAL_DIMENSION {
+            cumulated += probabilities.datas[i];
+            if x < cumulated {
+                return CesureMusic::categorical_to_chord(i);
+            }
+        }
+        CesureMusic::categorical_to_chord(REST_INDEX)
+    }
+
+    /**
+    * Return the key of the chords' first note
+    */
+    pub fn min_key(&self) -> usize {
+        infos_min_key(&self.infos)
+    }
+
     pub fn print(&self) {
         self.infos.print_title("Infos :");
         println!("Chords :");
@@ -188,6 +318,78 @@ struct ParsedNote {
     tick : usize,
     key : usize,
 }
+/**
+* Return the first tempo and time signature of a midi file, the default meter's if missing
+*/
+pub fn meter_from_smf(smf : &SMF) -> Meter {
+    let default = Meter::default();
+    let mut tempo = None;
+    let mut signature = None;
+    for track in &smf.tracks {
+        for event in &track.events {
+            if let Event::Meta(ref meta) = event.event {
+                if meta.command == MetaCommand::TempoSetting && meta.data.len() == 3 && tempo.is_none() {
+                    let micros = ((meta.data[0] as u32) << 16) | ((meta.data[1] as u32) << 8) | (meta.data[2] as u32);
+                    if micros > 0 {
+                        tempo = Some(60000000.0 / micros as f64);
+                    }
+                } else if meta.command == MetaCommand::TimeSignature && signature.is_none() {
+                    signature = time_signature_from_meta(&meta.data);
+                }
+            }
+        }
+    }
+    let (numerator, denominator) = signature.unwrap_or((default.numerator, default.denominator));
+    Meter::new(tempo.unwrap_or(default.bpm), numerator, denominator)
+}
+
+/**
+* Parse the data of a time signature meta event
+* @input data : The numerator, the denominator's power of two, then the clocks data
+* @return The numerator and denominator, None if they can't be used by a Meter
+*/
+fn time_signature_from_meta(data : &[u8]) -> Option<(usize, usize)> {
+    if data.len() < 2 || data[0] == 0 {
+        return None;
+    }
+    match 1usize.checked_shl(data[1] as u32) {
+        Some(denominator) if denominator <= TICKS_PER_QUARTER * 4 => Some((data[0] as usize, denominator)),
+        _ => None,
+    }
+}
+
+/**
+* Create the infos Matrix of a music
+* @input division : The midi division, in ticks per quarter note
+* @input nb_ticks : The music's number of ticks
+* @input min_key : The key of the chords' first note
+* @return The infos Matrix
+*/
+pub fn new_infos(division : f64, nb_ticks : usize, min_key : usize) -> Matrix {
+    Matrix::new_row_from_datas(vec![division/DIVISION_RANGE, (nb_ticks as f64)/NB_TICKS_RANGE, (min_key as f64)/MIN_KEY_RANGE])
+}
+
+/**
+* Return the key of the chords' first note encoded in an infos Matrix
+*/
+pub fn infos_min_key(infos : &Matrix) -> usize {
+    (infos.datas[2] * MIN_KEY_RANGE).round() as usize
+}
+
+/**
+* Set the key of the chords' first note encoded in an infos Matrix
+*/
+pub fn set_infos_min_key(infos : &mut Matrix, min_key : usize) {
+    infos.datas[2] = (min_key as f64) / MIN_KEY_RANGE;
+}
+
+/**
+* Set the number of ticks encoded in an infos Matrix
+*/
+pub fn set_infos_nb_ticks(infos : &mut Matrix, nb_ticks : usize) {
+    infos.datas[1] = (nb_ticks as f64) / NB_TICKS_RANGE;
+}
+
 fn track_to_parsed_notes(track : &Track, tick_div : u64) -> Vec<ParsedNote> {
     let mut parsed_notes = Vec::new();
     let mut tick = 0;
@@ -215,4 +417,39 @@ fn track_to_parsed_notes(track : &Track, tick_div : u64) -> Vec<ParsedNote> {
         }
     }
     parsed_notes
-}
\ No newline at end of file
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn meter_lengths() {
+        let two_four = Meter::default();
+        assert_eq!(two_four.ticks_per_bar(), 8);
+        assert_eq!(two_four.bars_to_ticks(3.0), 24);
+        assert_eq!(two_four.bars_to_ticks(1.5), 12);
+        // 120 quarter notes per minute are 8 sixteenth notes per second
+        assert_eq!(two_four.seconds_to_ticks(2.0), 16);
+
+        let three_four = Meter::new(90.0, 3, 4);
+        assert_eq!(three_four.ticks_per_bar(), 12);
+        assert_eq!(three_four.seconds_to_ticks(10.0), 60);
+
+        let six_eight = Meter::new(60.0, 6, 8);
+        assert_eq!(six_eight.ticks_per_bar(), 12);
+        assert_eq!(six_eight.bars_to_ticks(2.0), 24);
+        assert_eq!(six_eight.seconds_to_ticks(1.0), 4);
+    }
+
+    #[test]
+    fn time_signatures_are_checked() {
+        assert_eq!(time_signature_from_meta(&[6, 3, 24, 8]), Some((6, 8)));
+        assert_eq!(time_signature_from_meta(&[3, 2]), Some((3, 4)));
+        assert_eq!(time_signature_from_meta(&[4, 5, 24, 8]), None);
+        assert_eq!(time_signature_from_meta(&[4, 200, 24, 8]), None);
+        assert_eq!(time_signature_from_meta(&[0, 2, 24, 8]), None);
+        assert_eq!(time_signature_from_meta(&[4]), None);
+    }
+}
@@ -0,0 +1,202 @@
+use std::f64::consts::PI;
+
+use network::music::Meter;
+
+use utils::matrix::Matrix;
+use utils::traits::Parse;
+
+
+// The wavelengths of the sinusoidal encoding go from 2π to 2π*SINUSOIDAL_BASE ticks
+pub const SINUSOIDAL_BASE : f64 = 10000.0;
+
+
+/**
+* The position of a tick, given to the output gate
+* The beat and bar phases are encoded as the sine and cosine of the tick's position in its
+* beat or bar, so the last tick of a bar is close to the first one, and the sinusoidal
+* encoding is the sine and cosine of the tick at nb_frequencies geometrically spaced frequencies
+* - ticks_per_beat : The beat length, following the time signature
+* - ticks_per_bar : The bar length, following the time signature
+* - beat_phase : If the beat phase is encoded
+* - bar_phase : If the bar phase is encoded
+* - nb_frequencies : The number of frequencies of the sinusoidal encoding, 0 for none
+*/
+pub struct PositionalEncoding {
+    pub ticks_per_beat : usize,
+    pub ticks_per_bar : usize,
+    pub beat_phase : bool,
+    pub bar_phase : bool,
+    pub nb_frequencies : usize,
+}
+
+
+
+impl Clone for PositionalEncoding {
+    fn clone(&self) -> PositionalEncoding {
+        return PositionalEncoding {
+            ticks_per_beat : self.ticks_per_beat,
+            ticks_per_bar : self.ticks_per_bar,
+            beat_phase : self.beat_phase,
+            bar_phase : self.bar_phase,
+            nb_frequencies : self.nb_frequencies,
+        }
+    }
+    fn clone_from(&mut self, source: &PositionalEncoding) {
+        self.ticks_per_beat = source.ticks_per_beat;
+        self.ticks_per_bar = source.ticks_per_bar;
+        self.beat_phase = source.beat_phase;
+        self.bar_phase = source.bar_phase;
+        self.nb_frequencies = source.nb_frequencies;
+    }
+}
+impl Parse for PositionalEncoding {
+    fn to_string(&self) -> String {
+        format!("{} {} {} {} {}", self.ticks_per_beat, self.ticks_per_bar, self.beat_phase, self.bar_phase, self.nb_frequencies)
+    }
+    fn from_string(str : &str) -> PositionalEncoding {
+        let parsed : Vec<&str> = str.split(" ").collect();
+        assert!(parsed.len() == 5);
+        return PositionalEncoding::new(parsed[0].parse().unwrap(), parsed[1].parse().unwrap(), parsed[2].parse().unwrap(),
+                                       parsed[3].parse().unwrap(), parsed[4].parse().unwrap());
+    }
+}
+
+
+
+impl PositionalEncoding {
+
+    /**
+    * Constructor
+    * @input ticks_per_beat : The beat length
+    * @input ticks_per_bar : The bar length
+    * @input beat_phase : If the beat phase is encoded
+    * @input bar_phase : If the bar phase is encoded
+    * @input nb_frequencies : The number of frequencies of the sinusoidal encoding, 0 for none
+    */
+    pub fn new(ticks_per_beat : usize, ticks_per_bar : usize, beat_phase : bool, bar_phase : bool, nb_frequencies : usize) -> PositionalEncoding {
+        assert!(ticks_per_beat > 0 && ticks_per_bar > 0);
+        assert!(beat_phase || bar_phase || nb_frequencies > 0, "A positional encoding encodes nothing");
+        return PositionalEncoding {
+            ticks_per_beat : ticks_per_beat,
+            ticks_per_bar : ticks_per_bar,
+            beat_phase : beat_phase,
+            bar_phase : bar_phase,
+            nb_frequencies : nb_frequencies,
+        }
+    }
+
+    /**
+    * @return The dimension of an encoded position
+    */
+    pub fn dimension(&self) -> usize {
+        let mut dimension = 2 * self.nb_frequencies;
+        if self.beat_phase {
+            dimension += 2;
+        }
+        if self.bar_phase {
+            dimension += 2;
+        }
+        dimension
+    }
+
+    /**
+    * Encode a tick's position
+    * @input tick : The tick, counted from the beginning of the sequence
+    * @return The encoded position
+    */
+    pub fn encode(&self, tick : usize) -> Matrix {
+        let mut datas = Vec::with_capacity(self.dimension());
+        if self.beat_phase {
+            let angle = 2.0 * PI * (tick % self.ticks_per_beat) as f64 / self.ticks_per_beat as f64;
+            datas.push(angle.sin());
+            datas.push(angle.cos());
+        }
+        if self.bar_phase {
+            let angle = 2.0 * PI * (tick % self.ticks_per_bar) as f64 / self.ticks_per_bar as f64;
+            datas.push(angle.sin());
+            datas.push(angle.cos());
+        }
+        for k in 0..self.nb_frequencies {
+            let angle = tick as f64 / SINUSOIDAL_BASE.powf(k as f64 / self.nb_frequencies as f64);
+            datas.push(angle.sin());
+            datas.push(angle.cos());
+        }
+        Matrix::new_row_from_datas(datas)
+    }
+
+}
+
+
+
+/**
+* Parse the positional features' description
+* @input str : The features separated by spaces, "beat", "bar" and "sin:<number of frequencies>",
+*              like "beat bar sin:8"
+* @input meter : The Meter giving the beat and bar lengths
+* @return The PositionalEncoding
+*/
+pub fn parse_positional_encoding(str : &str, meter : &Meter) -> PositionalEncoding {
+    let mut beat_phase = false;
+    let mut bar_phase = false;
+    let mut nb_frequencies = 0;
+    for feature in str.split_whitespace() {
+        match feature {
+            "beat" => { beat_phase = true; },
+            "bar" => { bar_phase = true; },
+            _ if feature.starts_with("sin:") => {
+                nb_frequencies = feature["sin:".len()..].parse().expect("Invalid number of frequencies");
+            }
+            _ => panic!("Unknown positional feature {}", feature),
+        }
+    }
+    let ticks_per_bar = meter.ticks_per_bar();
+    PositionalEncoding::new(ticks_per_bar / meter.numerator, ticks_per_bar, beat_phase, bar_phase, nb_frequencies)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodings_have_the_dimension() {
+        for &(beat_phase, bar_phase, nb_frequencies) in &[(true, false, 0), (false, true, 3), (true, true, 4), (false, false, 1)] {
+            let encoding = PositionalEncoding::new(4, 12, beat_phase, bar_phase, nb_frequencies);
+            for tick in 0..30 {
+                assert_eq!(encoding.encode(tick).len, encoding.dimension());
+            }
+        }
+        assert_eq!(PositionalEncoding::new(4, 12, true, true, 4).dimension(), 12);
+    }
+
+    #[test]
+    fn phases_repeat_with_their_period() {
+        // In 3/4, a beat is 4 ticks and a bar 12
+        let encoding = parse_positional_encoding("beat bar", &Meter::new(120.0, 3, 4));
+        assert_eq!((encoding.ticks_per_beat, encoding.ticks_per_bar), (4, 12));
+        for tick in 0..24 {
+            let position = encoding.encode(tick);
+            let next_beat = encoding.encode(tick + 4);
+            let next_bar = encoding.encode(tick + 12);
+            assert_eq!(position.datas[..2], next_beat.datas[..2]);
+            assert_eq!(position.datas, next_bar.datas);
+            if tick % 4 != 0 {
+                assert!(position.datas[..2] != encoding.encode(tick - tick % 4).datas[..2]);
+            }
+        }
+        // The phases start at sin 0, cos 1
+        assert_eq!(encoding.encode(0).datas, vec![0.0, 1.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn encoding_string_round_trip() {
+        let encoding = parse_positional_encoding("bar sin:8", &Meter::new(90.0, 6, 8));
+        let parsed = PositionalEncoding::from_string(encoding.to_string().as_str());
+        assert_eq!(parsed.to_string(), encoding.to_string());
+        assert_eq!((parsed.ticks_per_beat, parsed.ticks_per_bar, parsed.beat_phase, parsed.bar_phase, parsed.nb_frequencies),
+                   (2, 12, false, true, 8));
+        for tick in 0..20 {
+            assert_eq!(parsed.encode(tick).datas, encoding.encode(tick).datas);
+        }
+    }
+}
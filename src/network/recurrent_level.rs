@@ -0,0 +1,109 @@
+use std::ops::Add;
+
+use network::gate::traits::Gate;
+use network::music::Meter;
+
+use utils::traits::Parse;
+
+
+/**
+* A recurrence level above Cesure's first context
+* Its context follows the levels below it in Cesure's context, and is only computed
+* once every period ticks, from the infos and the whole context, the levels below
+* being already updated
+* - context_dimension : The level's context dimension
+* - period : The number of ticks between two updates
+* - memory_gate : The gate computing the level's next context
+*/
+pub struct RecurrentLevel<M : Gate> {
+    pub context_dimension : usize,
+    pub period : usize,
+    pub memory_gate : M,
+}
+
+
+
+impl<M : Gate> Clone for RecurrentLevel<M> {
+    fn clone(&self) -> RecurrentLevel<M> {
+        return RecurrentLevel {
+            context_dimension : self.context_dimension,
+            period : self.period,
+            memory_gate : self.memory_gate.clone(),
+        }
+    }
+    fn clone_from(&mut self, source: &RecurrentLevel<M>) {
+        self.context_dimension = source.context_dimension;
+        self.period = source.period;
+        self.memory_gate.clone_from(&source.memory_gate);
+    }
+}
+impl<M : Gate> Parse for RecurrentLevel<M> {
+    fn to_string(&self) -> String {
+        let output = format!("{} {}\n", self.context_dimension, self.period);
+        return output.add(self.memory_gate.to_string().as_str());
+    }
+    fn from_string(str : &str) -> RecurrentLevel<M> {
+        let header_end = str.find("\n").expect("A recurrent level has no memory gate");
+        let header : Vec<&str> = str[..header_end].split(" ").collect();
+        assert!(header.len() == 2);
+        let memory_gate = M::from_string(&str[header_end+1..]);
+        return RecurrentLevel::new(header[0].parse().unwrap(), header[1].parse().unwrap(), memory_gate);
+    }
+}
+
+
+
+impl<M : Gate> RecurrentLevel<M> {
+
+    /**
+    * Constructor
+    * @input context_dimension : The level's context dimension
+    * @input period : The number of ticks between two updates
+    * @input memory_gate : The gate computing the level's next context
+    */
+    pub fn new(context_dimension : usize, period : usize, memory_gate : M) -> RecurrentLevel<M> {
+        assert!(period > 0);
+        assert!(memory_gate.output_dimension() == context_dimension);
+        return RecurrentLevel {
+            context_dimension : context_dimension,
+            period : period,
+            memory_gate : memory_gate,
+        }
+    }
+
+    /**
+    * Return if the level is updated at the end of a tick
+    * @input tick : The tick, counted from the beginning of the sequence
+    * @return true on the period's last tick
+    */
+    pub fn is_updated(&self, tick : usize) -> bool {
+        (tick + 1).is_multiple_of(self.period)
+    }
+
+}
+
+
+
+/**
+* Parse the upper levels' description
+* Each level is written <context dimension>:<period>, the period being a number of ticks,
+* "beat" or "bar"
+* @input str : The levels separated by spaces, like "32:beat 16:bar"
+* @input meter : The Meter giving the beat and bar lengths
+* @return The (context dimension, period) of each level
+*/
+pub fn parse_levels(str : &str, meter : &Meter) -> Vec<(usize, usize)> {
+    let mut levels = Vec::new();
+    for level in str.split_whitespace() {
+        let parsed : Vec<&str> = level.split(":").collect();
+        assert!(parsed.len() == 2, "A level is written <context dimension>:<period>");
+        let context_dimension = parsed[0].parse().expect("Invalid level context dimension");
+        let period = match parsed[1] {
+            "beat" => meter.ticks_per_bar() / meter.numerator,
+            "bar" => meter.ticks_per_bar(),
+            ticks => ticks.parse().expect("Invalid level period"),
+        };
+        levels.push((context_dimension, period));
+    }
+    levels
+}
@@ -0,0 +1,291 @@
+extern crate rand;
+
+use std::cmp::Ordering;
+
+use rand::Rng;
+
+use network::cesure::OUTPUT_MODE_CATEGORICAL;
+use network::music::CesureMusic;
+use network::training::error_calculation::EPSILON;
+
+use utils::matrix::Matrix;
+use utils::matrix_math::row_argmax;
+use utils::string::parse_option;
+
+
+// The legacy decoding : keys above 0.9 are played, the most likely note in categorical mode
+pub const SAMPLING_TYPE_THRESHOLD : u8 = 0;
+// Every key is drawn from its own probability, the note is drawn from the softmax in categorical mode
+pub const SAMPLING_TYPE_BERNOULLI : u8 = 1;
+// Like bernoulli, but only the top_k most likely keys (or notes) can be drawn
+pub const SAMPLING_TYPE_TOP_K : u8 = 2;
+
+// The memory gate is fed the decoded chord, as the ideal chords are injected at training time
+// This is the default, the raw output used to be fed before the samplers
+pub const FEEDBACK_CHORD : u8 = 0;
+// The memory gate is fed the raw output, as during the computed part of the training
+pub const FEEDBACK_OUTPUT : u8 = 1;
+
+
+/**
+* The way a cesure's outputs are decoded into chords during generation
+* - sampling_type : The SAMPLING_TYPE_ constant
+* - temperature : Below 1.0 sharpens the probabilities, above 1.0 flattens them
+* - top_k : The number of keys that can be drawn with SAMPLING_TYPE_TOP_K
+* - max_polyphony : The maximum number of notes in a chord, 0 for no limit
+* - feedback : The FEEDBACK_ constant, what the memory gate is fed during generation
+*/
+pub struct Sampler {
+    pub sampling_type : u8,
+    pub temperature : f64,
+    pub top_k : usize,
+    pub max_polyphony : usize,
+    pub feedback : u8,
+}
+
+impl Clone for Sampler {
+    fn clone(&self) -> Sampler {
+        return Sampler {
+            sampling_type : self.sampling_type,
+            temperature : self.temperature,
+            top_k : self.top_k,
+            max_polyphony : self.max_polyphony,
+            feedback : self.feedback,
+        }
+    }
+}
+
+impl Sampler {
+
+    pub fn new(sampling_type : u8, temperature : f64, top_k : usize, max_polyphony : usize) -> Sampler {
+        assert!(sampling_type <= SAMPLING_TYPE_TOP_K);
+        assert!(temperature > 0.0);
+        assert!(sampling_type != SAMPLING_TYPE_TOP_K || top_k > 0);
+        return Sampler {
+            sampling_type : sampling_type,
+            temperature : temperature,
+            top_k : top_k,
+            max_polyphony : max_polyphony,
+            feedback : FEEDBACK_CHORD,
+        }
+    }
+
+    /**
+    * Return the deterministic sampler, matching the original thresholding
+    */
+    pub fn threshold() -> Sampler {
+        Sampler::new(SAMPLING_TYPE_THRESHOLD, 1.0, 0, 0)
+    }
+
+    pub fn bernoulli(temperature : f64) -> Sampler {
+        Sampler::new(SAMPLING_TYPE_BERNOULLI, temperature, 0, 0)
+    }
+
+    pub fn top_k(top_k : usize, temperature : f64) -> Sampler {
+        Sampler::new(SAMPLING_TYPE_TOP_K, temperature, top_k, 0)
+    }
+
+    /**
+    * Return the same sampler, keeping at most max_polyphony notes per chord
+    */
+    pub fn with_max_polyphony(mut self, max_polyphony : usize) -> Sampler {
+        self.max_polyphony = max_polyphony;
+        self
+    }
+
+    /**
+    * Return the same sampler, with another feedback
+    */
+    pub fn with_feedback(mut self, feedback : u8) -> Sampler {
+        assert!(feedback <= FEEDBACK_OUTPUT);
+        self.feedback = feedback;
+        self
+    }
+
+    /**
+    * Parse the sampling options from the command line arguments :
+    * --temperature <t>, --top-k <k>, --max-polyphony <n> and --feed-output
+    * Without --temperature nor --top-k, the threshold sampler is used
+    * With --feed-output, the memory gate is fed the raw outputs
+    * @input args : The command line arguments
+    * @return The Sampler object
+    */
+    pub fn from_args(args : &Vec<String>) -> Sampler {
+        let temperature : Option<f64> = parse_option(args, "--temperature");
+        let top_k : Option<usize> = parse_option(args, "--top-k");
+        let max_polyphony : Option<usize> = parse_option(args, "--max-polyphony");
+        let sampler = match (temperature, top_k) {
+            (None, None) => Sampler::threshold(),
+            (Some(temperature), None) => Sampler::bernoulli(temperature),
+            (temperature, Some(top_k)) => Sampler::top_k(top_k, temperature.unwrap_or(1.0)),
+        };
+        let feedback = match args.iter().any(|arg| arg == "--feed-output") {
+            true => FEEDBACK_OUTPUT,
+            false => FEEDBACK_CHORD,
+        };
+        sampler.with_max_polyphony(max_polyphony.unwrap_or(0)).with_feedback(feedback)
+    }
+
+    /**
+    * Decode a cesure's output into a chord
+    * @input output : The cesure's output
+    * @input output_mode : The cesure's OUTPUT_MODE_ constant
+    * @input rand : The random number generator
+    * @return The decoded chord
+    */
+    pub fn sample<R: Rng>(&self, output : &Matrix, output_mode : u8, rand : &mut R) -> Matrix {
+        match output_mode {
+            OUTPUT_MODE_CATEGORICAL => self.sample_categorical(output, rand),
+            _ => self.sample_independent(output, rand),
+        }
+    }
+
+    fn sample_independent<R: Rng>(&self, output : &Matrix, rand : &mut R) -> Matrix {
+        let mut chord = output.clone();
+        match self.sampling_type {
+            SAMPLING_TYPE_THRESHOLD => {
+                CesureMusic::normalize_chord(&mut chord);
+            }
+            _ => {
+                let probabilities : Vec<f64> = output.datas.iter().map(|&p| self.apply_temperature(p)).collect();
+                // The keys are ranked on the output, as a low temperature rounds their probabilities to 1.0
+                let candidates = match self.sampling_type {
+                    SAMPLING_TYPE_TOP_K => top_indexes(&output.datas, self.top_k),
+                    _ => (0..probabilities.len()).collect(),
+                };
+                chord.set_zero();
+                for i in candidates {
+                    if rand.gen::<f64>() < probabilities[i] {
+                        chord.datas[i] = 1.0;
+                    }
+                }
+            }
+        }
+        if self.max_polyphony > 0 {
+            let played : Vec<f64> = (0..chord.len).map(|i| chord.datas[i] * output.datas[i]).collect();
+            chord.set_zero();
+            for i in top_indexes(&played, self.max_polyphony) {
+                if played[i] > 0.0 {
+                    chord.datas[i] = 1.0;
+                }
+            }
+        }
+        chord
+    }
+
+    fn sample_categorical<R: Rng>(&self, probabilities : &Matrix, rand : &mut R) -> Matrix {
+        if self.sampling_type == SAMPLING_TYPE_THRESHOLD {
+            return CesureMusic::categorical_to_chord(row_argmax(probabilities));
+        }
+        let mut tempered = probabilities.clone();
+        tempered.set_zero();
+        let candidates = match self.sampling_type {
+            SAMPLING_TYPE_TOP_K => top_indexes(&probabilities.datas, self.top_k),
+            _ => (0..probabilities.len).collect(),
+        };
+        let mut sum = 0.0;
+        for i in candidates {
+            tempered.datas[i] = probabilities.datas[i].max(EPSILON).powf(1.0 / self.temperature);
+            sum += tempered.datas[i];
+        }
+        for i in 0..tempered.len {
+            tempered.datas[i] /= sum;
+        }
+        CesureMusic::sample_categorical(&tempered, rand)
+    }
+
+    /**
+    * Apply the temperature to a key's probability, by dividing its logit
+    */
+    fn apply_temperature(&self, probability : f64) -> f64 {
+        if self.temperature == 1.0 {
+            return probability;
+        }
+        let p = probability.clamp(EPSILON, 1.0 - EPSILON);
+        let logit = (p / (1.0 - p)).ln() / self.temperature;
+        1.0 / (1.0 + (-logit).exp())
+    }
+
+}
+
+
+
+/**
+* Return the indexes of the k greatest values, greatest first
+*/
+fn top_indexes(values : &Vec<f64>, k : usize) -> Vec<usize> {
+    let mut indexes : Vec<usize> = (0..values.len()).collect();
+    indexes.sort_by(|&a, &b| values[b].partial_cmp(&values[a]).unwrap_or(Ordering::Equal));
+    indexes.truncate(k);
+    indexes
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use network::cesure::OUTPUT_MODE_INDEPENDENT;
+    use network::music::CHORD_DIMENSION;
+    use utils::random::new_rng;
+
+    /**
+    * An output whose keys all have a different probability, the last key being the most likely
+    */
+    fn increasing_output(first : f64, step : f64) -> Matrix {
+        Matrix::new_row_from_datas((0..CHORD_DIMENSION).map(|i| first + step * i as f64).collect())
+    }
+
+    fn played(chord : &Matrix) -> Vec<usize> {
+        (0..chord.len).filter(|&i| chord.datas[i] > 0.5).collect()
+    }
+
+    #[test]
+    fn bernoulli_temperature_limits() {
+        let cold = Sampler::bernoulli(0.001);
+        assert!(cold.apply_temperature(0.6) > 1.0 - 1e-6);
+        assert!(cold.apply_temperature(0.4) < 1e-6);
+        let hot = Sampler::bernoulli(1000.0);
+        assert!((hot.apply_temperature(0.99) - 0.5).abs() < 0.01);
+        assert!((hot.apply_temperature(0.01) - 0.5).abs() < 0.01);
+        assert_eq!(Sampler::bernoulli(1.0).apply_temperature(0.3), 0.3);
+
+        // Near a zero temperature, sampling plays the keys above 0.5
+        let output = increasing_output(0.205, 0.01);
+        let mut rand = new_rng(Some(36));
+        let chord = cold.sample(&output, OUTPUT_MODE_INDEPENDENT, &mut rand);
+        assert_eq!(played(&chord), (30..CHORD_DIMENSION).collect::<Vec<usize>>());
+    }
+
+    #[test]
+    fn top_k_keeps_k_keys() {
+        let output = increasing_output(0.6, 0.005);
+        let mut rand = new_rng(Some(36));
+        for &k in &[1, 5, 12] {
+            let chord = Sampler::top_k(k, 0.001).sample(&output, OUTPUT_MODE_INDEPENDENT, &mut rand);
+            assert_eq!(played(&chord), (CHORD_DIMENSION - k..CHORD_DIMENSION).collect::<Vec<usize>>());
+        }
+    }
+
+    #[test]
+    fn max_polyphony_caps_the_notes() {
+        let output = increasing_output(0.91, 0.001);
+        let mut rand = new_rng(Some(36));
+        let chord = Sampler::threshold().with_max_polyphony(3).sample(&output, OUTPUT_MODE_INDEPENDENT, &mut rand);
+        assert_eq!(played(&chord), vec![CHORD_DIMENSION - 3, CHORD_DIMENSION - 2, CHORD_DIMENSION - 1]);
+
+        let chord = Sampler::bernoulli(0.5).with_max_polyphony(3).sample(&output, OUTPUT_MODE_INDEPENDENT, &mut rand);
+        assert!(played(&chord).len() <= 3);
+    }
+
+    #[test]
+    fn threshold_matches_normalize_chord() {
+        let mut rand = new_rng(Some(36));
+        for _ in 0..10 {
+            let output = Matrix::new_row_from_datas((0..CHORD_DIMENSION).map(|_| rand.gen::<f64>()).collect());
+            let mut normalized = output.clone();
+            CesureMusic::normalize_chord(&mut normalized);
+            let chord = Sampler::threshold().sample(&output, OUTPUT_MODE_INDEPENDENT, &mut rand);
+            assert_eq!(chord.datas, normalized.datas);
+        }
+    }
+}
@@ -1,161 +1,352 @@
 
+extern crate rand;
+extern crate crossbeam;
+
 use std::f64;
+use std::cmp::min;
+use std::collections::HashMap;
+
+use rand::{Rng, StdRng};
 
 use network::cesure::Cesure;
+use network::cesure::CesureState;
+use network::cesure::VerboseOutput;
+use network::gate::traits::Gate;
+use network::attention;
+use network::sampler::Sampler;
 use network::training::training_set::TrainingSet;
+use network::training::training_set::SetWeighting;
 use network::training::error_calculation::ErrorCalculation;
 
 use network::music::INFOS_DIMENSION;
 use network::music::CHORD_DIMENSION;
 
 use utils::matrix::Matrix;
+use utils::matrix_math::mult_scl;
+use utils::matrix_math::row_slice;
 use utils::traits::Parse;
 use utils::io::{AsyncStdinReader, AsyncStdinRead};
+use utils::random::derive_rng;
+
+
+pub const UPDATE_MODE_PER_STEP : u8 = 0;
+pub const UPDATE_MODE_PER_BATCH : u8 = 1;
+pub const UPDATE_MODE_FULL_BATCH : u8 = 2;
+
+pub const BATCH_UNIT_SEQUENCE : u8 = 0;
+pub const BATCH_UNIT_WINDOW : u8 = 1;
+
+pub const DEFAULT_LEARNING_RATE : f64 = 0.1;
+pub const DEFAULT_MOMENTUM : f64 = 0.9;
+
+
+/**
+* Describe how the training sets are split and when the weights are updated
+* - update_mode : UPDATE_MODE_PER_STEP applies the changes after every unit,
+*   UPDATE_MODE_PER_BATCH after every batch_size units,
+*   UPDATE_MODE_FULL_BATCH once per epoch
+* - batch_unit : BATCH_UNIT_SEQUENCE uses whole training sets as units,
+*   BATCH_UNIT_WINDOW cuts them in windows of window_len ticks
+* - shuffle : Shuffle the units between epochs
+* - nb_threads : The number of threads computing a batch's units in parallel
+* - patience : Stop when the epoch's loss hasn't improved for this number of epochs,
+*   restoring the best weights, 0 to never stop early
+* - scheduled_sampling : Feed the memory gate with decoded chords instead of the raw
+*   outputs, None to keep the raw outputs
+* - weighting : How the training sets are weighted and drawn
+* - learning_rate : The learning rate, DEFAULT_LEARNING_RATE by default
+* - momentum : The momentum, applied between two updates, DEFAULT_MOMENTUM by default
+* - tick_momentum : Apply the momentum between the ticks of a unit instead of between two updates,
+*   as train() does
+*/
+pub struct BatchConfig {
+    pub update_mode : u8,
+    pub batch_unit : u8,
+    pub batch_size : usize,
+    pub window_len : usize,
+    pub shuffle : bool,
+    pub nb_threads : usize,
+    pub patience : usize,
+    pub scheduled_sampling : Option<ScheduledSampling>,
+    pub weighting : SetWeighting,
+    pub learning_rate : f64,
+    pub momentum : f64,
+    pub tick_momentum : bool,
+}
 
+/**
+* Scheduled sampling : on every computed tick, the memory gate is fed the ideal chord
+* with the teacher forcing probability, and the chord decoded from the output otherwise,
+* so the network learns to follow its own generated music
+* The probability linearly decays from 1.0 to min_teacher_forcing over decay_epochs epochs
+* - sampler : The Sampler decoding the outputs
+*/
+pub struct ScheduledSampling {
+    pub sampler : Sampler,
+    pub min_teacher_forcing : f64,
+    pub decay_epochs : usize,
+}
 
-pub fn train(cesure: &mut Cesure, training_sets: &Vec<TrainingSet>, error_calculation: &ErrorCalculation, learning_rate: f64, momentum: f64, iterations: usize) {
+impl BatchConfig {
+
+    pub fn new(update_mode : u8, batch_unit : u8, batch_size : usize, window_len : usize, shuffle : bool) -> BatchConfig {
+        assert!(update_mode <= UPDATE_MODE_FULL_BATCH && batch_unit <= BATCH_UNIT_WINDOW);
+        assert!(batch_size > 0 && window_len > 0);
+        return BatchConfig {
+            update_mode : update_mode,
+            batch_unit : batch_unit,
+            batch_size : batch_size,
+            window_len : window_len,
+            shuffle : shuffle,
+            nb_threads : 1,
+            patience : 0,
+            scheduled_sampling : None,
+            weighting : SetWeighting::default(),
+            learning_rate : DEFAULT_LEARNING_RATE,
+            momentum : DEFAULT_MOMENTUM,
+            tick_momentum : false,
+        }
+    }
 
-    let mut stdin = AsyncStdinReader::new();
-    let mut learning_rate = learning_rate;
-    let mut momentum = momentum;
-    let mut iterations = iterations;
-    let mut show = true;
+    /**
+    * Set the learning rate and the momentum
+    * They can then be changed during the training with the setlr and setmom commands
+    */
+    pub fn with_learning_rate(mut self, learning_rate : f64, momentum : f64) -> BatchConfig {
+        assert!(learning_rate >= 0.0 && momentum >= 0.0);
+        self.learning_rate = learning_rate;
+        self.momentum = momentum;
+        self
+    }
 
-    for iteration in 0..iterations {
+    /**
+    * Set the number of threads used to compute the units' changes
+    * The result doesn't depend on it, the changes are always summed in the units' order
+    */
+    pub fn with_threads(mut self, nb_threads : usize) -> BatchConfig {
+        assert!(nb_threads > 0);
+        self.nb_threads = nb_threads;
+        self
+    }
 
-        if let Some(line) = stdin.read_line() {
-            let mut args = line.as_str().split_whitespace();
-            match args.next() {
-                Some(arg) => {
-                    match arg {
-                        "stop" => { break; },
-                        "show" => { show = true; },
-                        "hide" => { show = false; },
-                        "setlr" => {
-                            match args.next() {
-                                Some(arg) => {
-                                    match arg.parse() {
-                                        Ok(lr) => {
-                                            match lr < 0.0 {
-                                                true => { println!("Learning rate has to be positive"); },
-                                                false => { learning_rate = lr; },
-                                            }
-                                        }
-                                        Err(msg) => { println!("{}", msg); }
-                                    }
-                                },
-                                None => { println!("No argument on command setlr"); }
-                            }
-                        }
-                        "setmom" => {
-                            match args.next() {
-                                Some(arg) => {
-                                    match arg.parse() {
-                                        Ok(mom) => {
-                                            match mom < 0.0 {
-                                                true => { println!("Momentum has to be positive"); },
-                                                false => { momentum = mom; },
-                                            }
-                                        }
-                                        Err(msg) => { println!("{}", msg); }
-                                    }
-                                },
-                                None => { println!("No argument on command setmom"); }
-                            }
-                        }
-                        /*"setiters" => {
-                            match args.next() {
-                                Some(arg) => {
-                                    match arg.parse() {
-                                        Ok(iters) => {
-                                            iterations = iters;
-                                        }
-                                        Err(msg) => { println!("{}", msg); }
-                                    }
-                                },
-                                None => { println!("No argument on command setiters"); }
-                            }
-                        }*/
-                        _ => println!("Unknown command [{}]", line),
-                    }
-                },
-                None => {}
-            }
-        }
+    /**
+    * Enable early stopping
+    * @input patience : The number of epochs without improvement of the loss before stopping
+    */
+    pub fn with_early_stopping(mut self, patience : usize) -> BatchConfig {
+        self.patience = patience;
+        self
+    }
 
-        let mut error_sum = 0.0;
-        let mut output_gate_changes_sum = Vec::with_capacity(cesure.output_gate.nb_layers);
-        let mut output_gate_nb_changes = 0.0;
-        let mut memory_gate_changes_sum = Vec::with_capacity(cesure.memory_gate.nb_layers);
-        let mut memory_gate_nb_changes = 0.0;
+    /**
+    * Enable scheduled sampling
+    */
+    pub fn with_scheduled_sampling(mut self, scheduled_sampling : ScheduledSampling) -> BatchConfig {
+        self.scheduled_sampling = Some(scheduled_sampling);
+        self
+    }
 
-        for set_i in 0..training_sets.len() {
-            let training_set = &training_sets[set_i];
-            let sequence_len = training_set.compute_sequence.len();
+    /**
+    * Set the training sets' weighting
+    */
+    pub fn with_weighting(mut self, weighting : SetWeighting) -> BatchConfig {
+        self.weighting = weighting;
+        self
+    }
 
-            cesure.new_sequence(&training_set.infos);
+    /**
+    * The configuration used by train() : whole sequences, one update per epoch,
+    * the momentum being applied between the ticks of a sequence
+    */
+    pub fn full_batch() -> BatchConfig {
+        let mut batch_config = BatchConfig::new(UPDATE_MODE_FULL_BATCH, BATCH_UNIT_SEQUENCE, 1, 1, false);
+        batch_config.tick_momentum = true;
+        batch_config
+    }
 
-            for injection in &training_set.inject_sequence {
-                cesure.inject_next(injection);
-            }
+}
 
-            let mut errors = Vec::with_capacity(sequence_len);
-            let mut outputs = Vec::with_capacity(sequence_len);
 
-            for i in 0..sequence_len {
-                let output = cesure.compute_next_verbose();
-                let error = (error_calculation.calculate)(&output.output_out.output, &training_set.compute_sequence[i]); //sub(&output.output_out.output, &training_set.compute_sequence[i]);
-                error_sum += error.get_abs_avg();
-                outputs.push(output);
-                errors.push(error);
-            }
+impl ScheduledSampling {
 
-            let mut output_gate_last_changes = None;
-            let mut memory_gate_last_changes = None;
-            let mut memory_gate_signal : Option<Matrix> = None;
+    pub fn new(sampler : Sampler, min_teacher_forcing : f64, decay_epochs : usize) -> ScheduledSampling {
+        assert!((0.0..=1.0).contains(&min_teacher_forcing));
+        return ScheduledSampling {
+            sampler : sampler,
+            min_teacher_forcing : min_teacher_forcing,
+            decay_epochs : decay_epochs,
+        }
+    }
 
-            for i in 0..sequence_len {
-                let i = sequence_len-1 - i;
+    /**
+    * Return the probability of feeding the ideal chord during an epoch
+    */
+    pub fn teacher_forcing(&self, epoch : usize) -> f64 {
+        match epoch >= self.decay_epochs {
+            true => self.min_teacher_forcing,
+            false => 1.0 - (1.0 - self.min_teacher_forcing) * epoch as f64 / self.decay_epochs as f64,
+        }
+    }
 
-                let (signal, weights_changes) = cesure.output_gate.backpropagate_no_change(&outputs[i].output_out, &errors[i], learning_rate,
-                                                                     &output_gate_last_changes, momentum);
-                weights_changes_add_or_clone(&mut output_gate_changes_sum, &weights_changes);
-                output_gate_nb_changes += 1.0;
-                output_gate_last_changes = Some(weights_changes);
+}
 
-                if i > 1 {
-                    let output_gate_signal = infos_context_to_context(cesure, &signal);
 
-                    if let Some(ref mut signal) = memory_gate_signal {
-                        signal.add(&output_gate_signal);
-                    } else {
-                        memory_gate_signal = Some(output_gate_signal);
-                    }
+/**
+* A part of a TrainingSet's compute sequence, from begin (included) to end (excluded)
+*/
+struct BatchUnit {
+    set_i : usize,
+    begin : usize,
+    end : usize,
+}
+
+/**
+* What the units' changes are computed with during an epoch
+* - learning_rate : The learning rate, which can be changed between epochs
+* - tick_momentum : The momentum applied between the ticks of a unit, 0.0 when it's applied between updates
+* - feeding : The scheduled sampling's Sampler and teacher forcing probability, None to feed the raw outputs
+*/
+struct EpochSettings<'a> {
+    error_calculation : &'a ErrorCalculation,
+    weighting : &'a SetWeighting,
+    learning_rate : f64,
+    tick_momentum : f64,
+    feeding : Option<(&'a Sampler, f64)>,
+}
 
-                    let (signal, weights_changes) = cesure.memory_gate.backpropagate_no_change(&outputs[i-1].memory_out, &memory_gate_signal.unwrap(), learning_rate,
-                                                                         &memory_gate_last_changes, momentum);
-                    weights_changes_add_or_clone(&mut memory_gate_changes_sum, &weights_changes);
-                    memory_gate_nb_changes += 1.0;
-                    memory_gate_last_changes = Some(weights_changes);
+/**
+* The way a unit's memory gate is fed with scheduled sampling
+* Every unit has its own random number generator so the result doesn't depend on the threads
+*/
+struct UnitFeeding<'a> {
+    sampler : &'a Sampler,
+    teacher_forcing : f64,
+    rand : StdRng,
+}
 
-                    let signal = infos_context_output_to_context(cesure, &signal);
-                    memory_gate_signal = Some(signal);
+/**
+* The states the windows ended on during the current epoch, by training set and tick
+* following the window's last tick
+*/
+type WindowStates = HashMap<(usize, usize), CesureState>;
+
+/**
+* The last applied changes of the output gate, the memory gate, the upper levels and the attention
+*/
+type LastChanges = (Vec<Matrix>, Vec<Matrix>, Vec<Vec<Matrix>>, Vec<Matrix>);
+
+/**
+* The summed weights changes of one or more units
+*/
+struct Gradients {
+    error : f64,
+    output_gate : Vec<Matrix>,
+    output_gate_nb_changes : f64,
+    memory_gate : Vec<Matrix>,
+    memory_gate_nb_changes : f64,
+    levels : Vec<Vec<Matrix>>,
+    levels_nb_changes : Vec<f64>,
+    attention : Vec<Matrix>,
+    attention_nb_changes : f64,
+}
 
-                }
-            }
+impl Gradients {
+
+    fn new() -> Gradients {
+        Gradients {
+            error : 0.0,
+            output_gate : Vec::new(),
+            output_gate_nb_changes : 0.0,
+            memory_gate : Vec::new(),
+            memory_gate_nb_changes : 0.0,
+            levels : Vec::new(),
+            levels_nb_changes : Vec::new(),
+            attention : Vec::new(),
+            attention_nb_changes : 0.0,
         }
+    }
 
-        weights_changes_div(&mut output_gate_changes_sum, output_gate_nb_changes);
-        weights_changes_div(&mut memory_gate_changes_sum, memory_gate_nb_changes);
+    fn add(&mut self, gradients : &Gradients) {
+        self.error += gradients.error;
+        if !gradients.output_gate.is_empty() {
+            weights_changes_add_or_clone(&mut self.output_gate, &gradients.output_gate);
+        }
+        self.output_gate_nb_changes += gradients.output_gate_nb_changes;
+        if !gradients.memory_gate.is_empty() {
+            weights_changes_add_or_clone(&mut self.memory_gate, &gradients.memory_gate);
+        }
+        self.memory_gate_nb_changes += gradients.memory_gate_nb_changes;
+        self.grow_levels(gradients.levels.len());
+        for i in 0..gradients.levels.len() {
+            if !gradients.levels[i].is_empty() {
+                weights_changes_add_or_clone(&mut self.levels[i], &gradients.levels[i]);
+            }
+            self.levels_nb_changes[i] += gradients.levels_nb_changes[i];
+        }
+        if !gradients.attention.is_empty() {
+            weights_changes_add_or_clone(&mut self.attention, &gradients.attention);
+        }
+        self.attention_nb_changes += gradients.attention_nb_changes;
+    }
 
-        cesure.output_gate.apply_changes(&output_gate_changes_sum);
-        cesure.memory_gate.apply_changes(&memory_gate_changes_sum);
+    /**
+    * Add the upper levels' changes of a tick
+    * @input levels_changes : The changes of each level, None for the levels not updated at the tick
+    * @input factor : The tick's weight
+    */
+    fn add_levels(&mut self, levels_changes : &Vec<Option<Vec<Matrix>>>, factor : f64) {
+        self.grow_levels(levels_changes.len());
+        for i in 0..levels_changes.len() {
+            if let Some(ref changes) = levels_changes[i] {
+                weights_changes_add_or_clone(&mut self.levels[i], changes);
+                self.levels_nb_changes[i] += factor;
+            }
+        }
+    }
 
-        if show {
-            println!("Epoch #{}, Error = {}, LearningRate = {}, Momentum = {}", iteration, error_sum, learning_rate, momentum);
+    fn grow_levels(&mut self, nb_levels : usize) {
+        while self.levels.len() < nb_levels {
+            self.levels.push(Vec::new());
+            self.levels_nb_changes.push(0.0);
         }
     }
 
+}
+
+
+/**
+* Train a Cesure object on whole sequences, with one update per epoch
+* @input cesure : The cesure object to train
+* @input training_sets : The TrainingSet objects to train on
+* @input error_calculation : The error calculation
+* @input learning_rate : The learning rate
+* @input momentum : The momentum, applied between the ticks of a sequence
+* @input iterations : The number of epochs
+* @input rand : The random number generator used by train_batched
+*/
+pub fn train<O: Gate, M: Gate, R: Rng>(cesure: &mut Cesure<O, M>, training_sets: &Vec<TrainingSet>, error_calculation: &ErrorCalculation, learning_rate: f64, momentum: f64, iterations: usize,
+                                       rand: &mut R) {
+    let batch_config = BatchConfig::full_batch().with_learning_rate(learning_rate, momentum);
+    train_batched(cesure, training_sets, error_calculation, &batch_config, iterations, rand);
+}
+
+/**
+* Train a Cesure object using backpropagation through time
+* Every update mode goes through the same gradient computation, they only differ
+* by the number of units whose changes are averaged before being applied
+* @input cesure : The cesure object to train
+* @input training_sets : The TrainingSet objects to train on
+* @input error_calculation : The error calculation
+* @input batch_config : The batching configuration, with the learning rate and the momentum
+* @input iterations : The number of epochs
+* @input rand : The random number generator used to shuffle the units and by scheduled sampling
+*/
+pub fn train_batched<O: Gate, M: Gate, R: Rng>(cesure: &mut Cesure<O, M>, training_sets: &Vec<TrainingSet>, error_calculation: &ErrorCalculation,
+                                               batch_config: &BatchConfig, iterations: usize, rand: &mut R) {
+
+    let mut stdin = AsyncStdinReader::new();
+    train_epochs(cesure, training_sets, error_calculation, batch_config, iterations, &mut || stdin.read_line(), rand);
+
     stdin.read_line();
     println!("Training finished!");
     println!("Type the file name to save Cesure in (type nothing if you don't want to save) :");
@@ -169,21 +360,30 @@ pub fn train(cesure: &mut Cesure, training_sets: &Vec<TrainingSet>, error_calcul
     }
 }
 
-pub fn train_mod2(cesure: &mut Cesure, training_sets: &Vec<TrainingSet>, error_calculation: &ErrorCalculation, learning_rate: f64, momentum: f64, depth: usize, iterations: usize) {
-
-    let mut stdin = AsyncStdinReader::new();
-    let mut learning_rate = learning_rate;
-    let mut momentum = momentum;
-    let mut iterations = iterations;
+/**
+* Run the epochs of train_batched
+* @input read_command : Return the command typed since the last call, if any, like "setlr 0.01"
+*/
+fn train_epochs<O: Gate, M: Gate, R: Rng, C: FnMut() -> Option<String>>(cesure: &mut Cesure<O, M>, training_sets: &Vec<TrainingSet>, error_calculation: &ErrorCalculation,
+                                                                        batch_config: &BatchConfig, iterations: usize, read_command: &mut C, rand: &mut R) {
+    let mut learning_rate = batch_config.learning_rate;
+    let mut momentum = batch_config.momentum;
     let mut show = true;
 
-    let mut output_gate_last_changes = None;
-    let mut memory_gate_last_changes = None;
+    let all_sets : Vec<usize> = (0..training_sets.len()).collect();
+    let mut units = build_units(training_sets, &all_sets, batch_config);
+    assert!(!units.is_empty());
+
+    let mut last_changes : Option<LastChanges> = None;
+    let mut window_states = WindowStates::new();
 
+    let mut best_error = f64::INFINITY;
+    let mut best_cesure : Option<Cesure<O, M>> = None;
+    let mut nb_epochs_without_improvement = 0;
 
     for iteration in 0..iterations {
 
-        if let Some(line) = stdin.read_line() {
+        if let Some(line) = read_command() {
             let mut args = line.as_str().split_whitespace();
             match args.next() {
                 Some(arg) => {
@@ -223,19 +423,6 @@ pub fn train_mod2(cesure: &mut Cesure, training_sets: &Vec<TrainingSet>, error_c
                                 None => { println!("No argument on command setmom"); }
                             }
                         }
-                        /*"setiters" => {
-                            match args.next() {
-                                Some(arg) => {
-                                    match arg.parse() {
-                                        Ok(iters) => {
-                                            iterations = iters;
-                                        }
-                                        Err(msg) => { println!("{}", msg); }
-                                    }
-                                },
-                                None => { println!("No argument on command setiters"); }
-                            }
-                        }*/
                         _ => println!("Unknown command [{}]", line),
                     }
                 },
@@ -243,73 +430,349 @@ pub fn train_mod2(cesure: &mut Cesure, training_sets: &Vec<TrainingSet>, error_c
             }
         }
 
+        if batch_config.weighting.nb_sampled_sets > 0 {
+            let epoch_sets = batch_config.weighting.epoch_sets(training_sets, rand);
+            units = build_units(training_sets, &epoch_sets, batch_config);
+        }
+        if batch_config.shuffle {
+            rand.shuffle(&mut units);
+        }
+        // A state from an earlier epoch was computed with older weights
+        window_states.clear();
+        let batch_size = match batch_config.update_mode {
+            UPDATE_MODE_PER_STEP => 1,
+            UPDATE_MODE_PER_BATCH => batch_config.batch_size,
+            UPDATE_MODE_FULL_BATCH => units.len(),
+            _ => panic!("Unknown update mode"),
+        };
+
         let mut error_sum = 0.0;
+        let epoch_cesure = match batch_config.patience > 0 {
+            true => Some(cesure.clone()),
+            false => None,
+        };
+
+        let (tick_momentum, update_momentum) = match batch_config.tick_momentum {
+            true => (momentum, 0.0),
+            false => (0.0, momentum),
+        };
+        let settings = EpochSettings {
+            error_calculation : error_calculation,
+            weighting : &batch_config.weighting,
+            learning_rate : learning_rate,
+            tick_momentum : tick_momentum,
+            feeding : batch_config.scheduled_sampling.as_ref().map(|scheduled_sampling| {
+                (&scheduled_sampling.sampler, scheduled_sampling.teacher_forcing(iteration))
+            }),
+        };
+
+        for batch in units.chunks(batch_size) {
+            let mut batch_gradients = batch_gradients(cesure, batch, training_sets, &settings, batch_config.nb_threads, &mut window_states, rand);
+            error_sum += batch_gradients.error;
+            apply_gradients(cesure, &mut batch_gradients, update_momentum, &mut last_changes);
+        }
 
-        for set_i in 0..training_sets.len() {
-            let training_set = &training_sets[set_i];
-            let sequence_len = training_set.compute_sequence.len();
+        if show {
+            println!("Epoch #{}, Error = {}, LearningRate = {}, Momentum = {}", iteration, error_sum, learning_rate, momentum);
+        }
 
-            cesure.new_sequence(&training_set.infos);
-            for injection in &training_set.inject_sequence {
-                cesure.inject_next(injection);
+        if batch_config.patience > 0 {
+            if error_sum < best_error {
+                best_error = error_sum;
+                best_cesure = epoch_cesure;
+                nb_epochs_without_improvement = 0;
+            } else {
+                nb_epochs_without_improvement += 1;
+                if nb_epochs_without_improvement >= batch_config.patience {
+                    println!("No improvement for {} epochs, stopping", nb_epochs_without_improvement);
+                    break;
+                }
             }
-            let mut memory_outputs = Vec::with_capacity(sequence_len);
+        }
+    }
 
-            for tick_i in 0..sequence_len {
+    if let Some(ref best_cesure) = best_cesure {
+        println!("Restoring the best weights (Error = {})", best_error);
+        cesure.clone_from(best_cesure);
+    }
+}
 
-                let output = cesure.compute_next_verbose();
-                let output_output = output.output_out;
-                memory_outputs.push(cesure.compute_next_verbose().memory_out);
+/**
+* Cut the training sets in units, following the batching configuration
+* @input sets : The indexes of the sets to cut, a set can appear more than once
+*/
+fn build_units(training_sets: &Vec<TrainingSet>, sets: &Vec<usize>, batch_config: &BatchConfig) -> Vec<BatchUnit> {
+    let mut units = Vec::new();
+    for &set_i in sets {
+        let sequence_len = training_sets[set_i].compute_sequence.len();
+        if sequence_len == 0 {
+            continue;
+        }
+        match batch_config.batch_unit {
+            BATCH_UNIT_SEQUENCE => {
+                units.push(BatchUnit {set_i: set_i, begin: 0, end: sequence_len});
+            }
+            BATCH_UNIT_WINDOW => {
+                let mut begin = 0;
+                while begin < sequence_len {
+                    let end = min(begin + batch_config.window_len, sequence_len);
+                    units.push(BatchUnit {set_i: set_i, begin: begin, end: end});
+                    begin = end;
+                }
+            }
+            _ => panic!("Unknown batch unit"),
+        }
+    }
+    units
+}
 
-                let error = (error_calculation.calculate)(&output_output.output, &training_set.compute_sequence[tick_i]);
-                error_sum += error.get_abs_avg();
+/**
+* Compute the summed weights changes of a batch, splitting its units between threads
+* Every thread works on its own clone of the cesure, the changes are then summed
+* in the units' order so the result doesn't depend on the number of threads
+* A window starts from the state its previous window ended on when it was computed earlier
+* in the epoch, the other windows inject the sequence before them
+* The states are read before the batch and written after it, for the same reason
+* @input cesure : The cesure object
+* @input batch : The batch's units
+* @input training_sets : The TrainingSet objects the units refer to
+* @input settings : The epoch's EpochSettings
+* @input nb_threads : The maximum number of threads
+* @input window_states : The states the windows ended on, updated by this function
+* @input rand : The random number generator the units' generators are derived from
+* @return The batch's Gradients
+*/
+fn batch_gradients<O: Gate, M: Gate, R: Rng>(cesure: &Cesure<O, M>, batch: &[BatchUnit], training_sets: &Vec<TrainingSet>, settings: &EpochSettings,
+                                             nb_threads: usize, window_states: &mut WindowStates, rand: &mut R) -> Gradients {
+    let nb_threads = min(nb_threads, batch.len());
+    let chunk_size = batch.len().div_ceil(nb_threads);
+
+    let mut units_feeding : Vec<Option<UnitFeeding>> = batch.iter().map(|_| {
+        settings.feeding.map(|(sampler, teacher_forcing)| UnitFeeding {
+            sampler : sampler,
+            teacher_forcing : teacher_forcing,
+            rand : derive_rng(rand),
+        })
+    }).collect();
+
+    let previous_states : &WindowStates = window_states;
+    let threads_gradients : Vec<Vec<(Gradients, CesureState)>> = crossbeam::scope(|scope| {
+        let mut threads = Vec::with_capacity(nb_threads);
+        for (chunk, chunk_feeding) in batch.chunks(chunk_size).zip(units_feeding.chunks_mut(chunk_size)) {
+            let thread = scope.spawn(move || {
+                let mut worker_cesure = cesure.clone();
+                let mut chunk_gradients = Vec::with_capacity(chunk.len());
+                for (unit, unit_feeding) in chunk.iter().zip(chunk_feeding.iter_mut()) {
+                    let training_set = &training_sets[unit.set_i];
+                    let start_state = previous_states.get(&(unit.set_i, unit.begin));
+                    chunk_gradients.push(unit_gradients(&mut worker_cesure, training_set, unit.begin, unit.end, start_state, settings, unit_feeding));
+                }
+                chunk_gradients
+            });
+            threads.push(thread);
+        }
+        threads.into_iter().map(|thread| thread.join()).collect()
+    });
+
+    let mut gradients = Gradients::new();
+    for (unit, (unit_gradients, end_state)) in batch.iter().zip(threads_gradients.iter().flat_map(|chunk_gradients| chunk_gradients.iter())) {
+        gradients.add(unit_gradients);
+        if unit.end < training_sets[unit.set_i].compute_sequence.len() {
+            window_states.insert((unit.set_i, unit.end), end_state.clone());
+        }
+    }
+    gradients
+}
 
-                let (output_gate_signal, changes) = cesure.output_gate.backpropagate(&output_output, &error, learning_rate, output_gate_last_changes, momentum);
-                output_gate_last_changes = Some(changes);
+/**
+* Compute the weights changes of a unit using backpropagation through time,
+* without applying them
+* The unit starts from the given state, or the ticks before it are injected so
+* that the context is the same as when computing the whole sequence
+* @input cesure : The cesure object
+* @input training_set : The unit's TrainingSet
+* @input begin : The unit's first tick in the compute sequence
+* @input end : The tick following the unit's last tick
+* @input start_state : The state at the unit's first tick, None to inject the ticks before it
+* @input settings : The epoch's EpochSettings, every tick's loss is weighted by the set's tick factor
+*                   and the changes are averaged using these weights
+*                   With a tick momentum, every tick's changes get the momentum of the changes of the
+*                   tick after it, the output gate's, the memory gate's and the attention's separately
+* @input feeding : The unit's scheduled sampling, None to feed the raw outputs
+* @return The unit's Gradients and the state at its end
+*/
+fn unit_gradients<O: Gate, M: Gate>(cesure: &mut Cesure<O, M>, training_set: &TrainingSet, begin: usize, end: usize, start_state: Option<&CesureState>,
+                                    settings: &EpochSettings, feeding: &mut Option<UnitFeeding>) -> (Gradients, CesureState) {
+    assert!(begin < end && end <= training_set.compute_sequence.len());
+    let sequence_len = end - begin;
+    let tick_factor = settings.weighting.tick_factor(training_set);
+    let learning_rate = settings.learning_rate;
+    let mut gradients = Gradients::new();
 
-                let mut memory_gate_signal = infos_context_to_context(cesure, &output_gate_signal);
-                let mut memory_gate_changes = Vec::new();
+    cesure.new_sequence(&training_set.infos);
+    match start_state {
+        Some(state) => cesure.set_state(state),
+        None => {
+            for injection in &training_set.inject_sequence {
+                cesure.inject_next(injection);
+            }
+            for i in 0..begin {
+                cesure.inject_next(&training_set.compute_sequence[i]);
+            }
+        }
+    }
+
+    let mut errors = Vec::with_capacity(sequence_len);
+    let mut outputs = Vec::with_capacity(sequence_len);
+
+    for i in begin..end {
+        let output = match *feeding {
+            Some(ref mut feeding) => {
+                let ideal_chord = match feeding.rand.gen::<f64>() < feeding.teacher_forcing {
+                    true => Some(&training_set.compute_sequence[i]),
+                    false => None,
+                };
+                cesure.compute_next_verbose_fed(ideal_chord, feeding.sampler, &mut feeding.rand)
+            }
+            None => cesure.compute_next_verbose(),
+        };
+        let error = cesure.calculate_output_error(&output.output, &training_set.compute_sequence[i], settings.error_calculation);
+        gradients.error += tick_factor * error.value;
+        outputs.push(output);
+        errors.push(mult_scl(&error.gradient, tick_factor));
+    }
+    let end_state = cesure.state();
 
-                for back_i in 0..tick_i {
-                    if back_i < depth {
-                        let back_i = tick_i - 1 - back_i;
+    let mut memory_gate_signal : Option<Matrix> = None;
+    let mut output_gate_last_changes = None;
+    let mut memory_gate_last_changes = None;
+    let mut attention_last_changes = None;
+    // The signals the attention sends to the context before each tick of the unit, from the ticks after it
+    let mut history_signals = vec![Matrix::new_row(cesure.context_dimension); sequence_len];
 
-                        let (signal, changes) = cesure.memory_gate.backpropagate_no_change(&memory_outputs[back_i], &memory_gate_signal, learning_rate, &memory_gate_last_changes, momentum);
-                        memory_gate_signal = infos_context_output_to_context(cesure, &signal);
-                        weights_changes_add_or_clone(&mut memory_gate_changes, &changes);
-                        memory_gate_last_changes = Some(changes);
+    for i in 0..sequence_len {
+        let i = sequence_len-1 - i;
+
+        let (signal, weights_changes) = cesure.output_gate.backpropagate_no_change(&outputs[i].output_out, &errors[i], learning_rate,
+                                                                                   &output_gate_last_changes, settings.tick_momentum);
+        weights_changes_add_or_clone(&mut gradients.output_gate, &weights_changes);
+        gradients.output_gate_nb_changes += tick_factor;
+        output_gate_last_changes = Some(weights_changes);
+
+        let (signal, items_signals, attention_changes) = backpropagate_attention(cesure, &outputs[i], &signal, learning_rate,
+                                                                                 &attention_last_changes, settings.tick_momentum);
+        if let Some(ref attention_changes) = attention_changes {
+            weights_changes_add_or_clone(&mut gradients.attention, attention_changes);
+            gradients.attention_nb_changes += tick_factor;
+            attention_last_changes = Some(attention_changes.clone());
+            if cesure.attention.as_ref().unwrap().attention_type == attention::ATTENTION_TYPE_CONTEXT {
+                // The items are the contexts before the previous ticks, the ones before the unit aren't backpropagated
+                let nb_items = items_signals.len();
+                for (j, item_signal) in items_signals.iter().enumerate() {
+                    if i + j >= nb_items {
+                        history_signals[i + j - nb_items].add(item_signal);
                     }
                 }
+            }
+        }
 
-                if memory_gate_changes.len() > 0 {
-                    cesure.memory_gate.apply_changes(&memory_gate_changes);
-                }
+        // The context of every tick but the unit's first one was computed by the memory gate at the tick before
+        if i > 0 {
+            let mut output_gate_signal = infos_context_to_context(cesure, &signal);
+            output_gate_signal.add(&history_signals[i]);
+
+            if let Some(ref mut signal) = memory_gate_signal {
+                signal.add(&output_gate_signal);
+            } else {
+                memory_gate_signal = Some(output_gate_signal);
             }
+
+            let (signal, weights_changes, levels_changes) = backpropagate_context(cesure, &outputs[i-1], &memory_gate_signal.unwrap(), learning_rate,
+                                                                                  &memory_gate_last_changes, settings.tick_momentum);
+            weights_changes_add_or_clone(&mut gradients.memory_gate, &weights_changes);
+            gradients.memory_gate_nb_changes += tick_factor;
+            memory_gate_last_changes = Some(weights_changes);
+            gradients.add_levels(&levels_changes, tick_factor);
+
+            memory_gate_signal = Some(signal);
         }
+    }
 
-        if show {
-            println!("Epoch #{}, Error = {}, LearningRate = {}, Momentum = {}", iteration, error_sum, learning_rate, momentum);
+    (gradients, end_state)
+}
+
+/**
+* Average the summed changes, add the momentum and apply them to the cesure
+* @input cesure : The cesure object to change
+* @input gradients : The summed changes
+* @input momentum : The momentum
+* @input last_changes : The last applied changes, updated by this function
+*/
+fn apply_gradients<O: Gate, M: Gate>(cesure: &mut Cesure<O, M>, gradients: &mut Gradients, momentum: f64, last_changes: &mut Option<LastChanges>) {
+    if !gradients.output_gate.is_empty() && gradients.output_gate_nb_changes > 0.0 {
+        weights_changes_div(&mut gradients.output_gate, gradients.output_gate_nb_changes);
+    }
+    if !gradients.memory_gate.is_empty() && gradients.memory_gate_nb_changes > 0.0 {
+        weights_changes_div(&mut gradients.memory_gate, gradients.memory_gate_nb_changes);
+    }
+    for i in 0..gradients.levels.len() {
+        if !gradients.levels[i].is_empty() && gradients.levels_nb_changes[i] > 0.0 {
+            weights_changes_div(&mut gradients.levels[i], gradients.levels_nb_changes[i]);
         }
+    }
+    if !gradients.attention.is_empty() && gradients.attention_nb_changes > 0.0 {
+        weights_changes_div(&mut gradients.attention, gradients.attention_nb_changes);
+    }
 
+    if let Some((ref last_output_gate, ref last_memory_gate, ref last_levels, ref last_attention)) = *last_changes {
+        if !gradients.output_gate.is_empty() && !last_output_gate.is_empty() {
+            for i in 0..gradients.output_gate.len() {
+                gradients.output_gate[i].add(&mult_scl(&last_output_gate[i], momentum));
+            }
+        }
+        if !gradients.memory_gate.is_empty() && !last_memory_gate.is_empty() {
+            for i in 0..gradients.memory_gate.len() {
+                gradients.memory_gate[i].add(&mult_scl(&last_memory_gate[i], momentum));
+            }
+        }
+        for level_i in 0..min(gradients.levels.len(), last_levels.len()) {
+            if !gradients.levels[level_i].is_empty() && !last_levels[level_i].is_empty() {
+                for i in 0..gradients.levels[level_i].len() {
+                    gradients.levels[level_i][i].add(&mult_scl(&last_levels[level_i][i], momentum));
+                }
+            }
+        }
+        if !gradients.attention.is_empty() && !last_attention.is_empty() {
+            for i in 0..gradients.attention.len() {
+                gradients.attention[i].add(&mult_scl(&last_attention[i], momentum));
+            }
+        }
     }
 
-    stdin.read_line();
-    println!("Training finished!");
-    println!("Type the file name to save Cesure in (type nothing if you don't want to save) :");
-    let answer = stdin.read_line_blocking();
-    match answer.as_str() {
-        "" => {},
-        _ => {
-            cesure.save(answer.as_str());
-            println!("Saved in {}", answer.as_str());
-        },
+    if !gradients.output_gate.is_empty() {
+        cesure.output_gate.apply_changes(&gradients.output_gate);
+    }
+    if !gradients.memory_gate.is_empty() {
+        cesure.memory_gate.apply_changes(&gradients.memory_gate);
+    }
+    for i in 0..gradients.levels.len() {
+        if !gradients.levels[i].is_empty() {
+            cesure.levels[i].memory_gate.apply_changes(&gradients.levels[i]);
+        }
+    }
+    if !gradients.attention.is_empty() {
+        if let Some(ref mut attention) = cesure.attention {
+            attention.apply_changes(&gradients.attention);
+        }
     }
+
+    *last_changes = Some((gradients.output_gate.clone(), gradients.memory_gate.clone(), gradients.levels.clone(), gradients.attention.clone()));
 }
 
-fn infos_context_to_context(cesure : &Cesure, infos_context : &Matrix) -> Matrix {
-    assert!(infos_context.is_row() && infos_context.len == INFOS_DIMENSION+cesure.context_dimension);
-    let begin = INFOS_DIMENSION;
-    let end = INFOS_DIMENSION+cesure.context_dimension;
+fn infos_context_to_context<O: Gate, M: Gate>(cesure : &Cesure<O, M>, infos_context : &Matrix) -> Matrix {
+    assert!(infos_context.is_row() && infos_context.len == cesure.infos_dimension+cesure.context_dimension);
+    let begin = cesure.infos_dimension;
+    let end = cesure.infos_dimension+cesure.context_dimension;
     let mut vec = Vec::with_capacity(cesure.context_dimension);
     for i in begin..end {
         vec.push(infos_context.datas[i]);
@@ -317,10 +780,10 @@ fn infos_context_to_context(cesure : &Cesure, infos_context : &Matrix) -> Matrix
     assert!(vec.len() == cesure.context_dimension);
     return Matrix::new_row_from_datas(vec);
 }
-fn infos_context_output_to_context(cesure : &Cesure, infos_context_output : &Matrix) -> Matrix {
-    assert!(infos_context_output.is_row() && infos_context_output.len == INFOS_DIMENSION+cesure.context_dimension+CHORD_DIMENSION);
-    let begin = INFOS_DIMENSION;
-    let end = INFOS_DIMENSION+cesure.context_dimension;
+fn infos_context_output_to_context<O: Gate, M: Gate>(cesure : &Cesure<O, M>, infos_context_output : &Matrix) -> Matrix {
+    assert!(infos_context_output.is_row() && infos_context_output.len == cesure.infos_dimension+cesure.context_dimension+cesure.output_dimension);
+    let begin = cesure.infos_dimension;
+    let end = cesure.infos_dimension+cesure.context_dimension;
     let mut vec = Vec::with_capacity(cesure.context_dimension);
     for i in begin..end {
         vec.push(infos_context_output.datas[i]);
@@ -329,6 +792,73 @@ fn infos_context_output_to_context(cesure : &Cesure, infos_context_output : &Mat
     return Matrix::new_row_from_datas(vec);
 }
 
+/**
+* Backpropagate the error signal of the context computed at a tick through the levels'
+* memory gates, from the highest level down to the memory gate
+* @input cesure : The cesure object
+* @input output_v : The tick's VerboseOutput object
+* @input signal : The error signal of the context computed at the tick
+* @input learning_rate : The learning rate
+* @input last_changes : The memory gate's last weights changes, None if it's the first iteration
+* @input momentum : The momentum of the memory gate's changes
+* @return A tuple composed by the error signal of the context before the tick, the memory gate's
+*         changes and the upper levels' changes, None for the levels not updated at the tick
+*/
+fn backpropagate_context<O: Gate, M: Gate>(cesure: &Cesure<O, M>, output_v: &VerboseOutput<O, M>, signal: &Matrix, learning_rate: f64,
+                                           last_changes: &Option<Vec<Matrix>>, momentum: f64) -> (Matrix, Vec<Matrix>, Vec<Option<Vec<Matrix>>>) {
+    let mut signal = signal.clone();
+    let mut levels_changes = vec![None; cesure.levels.len()];
+    for level_i in (0..cesure.levels.len()).rev() {
+        if let Some(ref level_out) = output_v.levels_out[level_i] {
+            let (begin, end) = cesure.level_range(level_i+1);
+            let level_signal = Matrix::new_row_from_datas(signal.datas[begin..end].to_vec());
+            let (input_signal, changes) = cesure.levels[level_i].memory_gate.backpropagate_no_change(level_out, &level_signal, learning_rate, &None, 0.0);
+            // The level's context before the tick only reaches the tick's context through its memory gate
+            for i in begin..end {
+                signal.datas[i] = 0.0;
+            }
+            signal.add(&infos_context_to_context(cesure, &input_signal));
+            levels_changes[level_i] = Some(changes);
+        }
+    }
+
+    let (begin, end) = cesure.level_range(0);
+    let memory_gate_signal = Matrix::new_row_from_datas(signal.datas[begin..end].to_vec());
+    let (input_signal, changes) = cesure.memory_gate.backpropagate_no_change(&output_v.memory_out, &memory_gate_signal, learning_rate, last_changes, momentum);
+    for i in begin..end {
+        signal.datas[i] = 0.0;
+    }
+    signal.add(&infos_context_output_to_context(cesure, &input_signal));
+    (signal, changes, levels_changes)
+}
+
+/**
+* Backpropagate the error signal of the output gate's input through the attention
+* The position's signal is dropped, the positions being constants
+* @input cesure : The cesure object
+* @input output_v : The tick's VerboseOutput object
+* @input signal : The error signal of the output gate's input
+* @input learning_rate : The learning rate
+* @input last_changes : The attention's last weights changes, None if it's the first iteration
+* @input momentum : The momentum of the attention's changes
+* @return A tuple composed by the error signal of the infos and the context, the error signals
+*         of the attended items and the attention's changes, None when there is no attention
+*/
+fn backpropagate_attention<O: Gate, M: Gate>(cesure: &Cesure<O, M>, output_v: &VerboseOutput<O, M>, signal: &Matrix, learning_rate: f64,
+                                             last_changes: &Option<Vec<Matrix>>, momentum: f64) -> (Matrix, Vec<Matrix>, Option<Vec<Matrix>>) {
+    let positions_dimension = cesure.positional_encoding.as_ref().map_or(0, |positional_encoding| positional_encoding.dimension());
+    let signal = Matrix::new_row_from_datas(signal.datas[..signal.len - positions_dimension].to_vec());
+    match (&cesure.attention, &output_v.attention_out) {
+        (Some(attention), Some(attention_out)) => {
+            let (mut infos_context_signal, attended_signal) = row_slice(&signal, cesure.infos_dimension + cesure.context_dimension);
+            let (query_signal, items_signals, changes) = attention.backpropagate_no_change(attention_out, &attended_signal, learning_rate, last_changes, momentum);
+            infos_context_signal.add(&query_signal);
+            (infos_context_signal, items_signals, Some(changes))
+        }
+        _ => (signal, Vec::new(), None),
+    }
+}
+
 fn weights_changes_div(weights_changes : &mut Vec<Matrix>, val : f64) {
     for i in 0..weights_changes.len() {
         weights_changes[i].div_scl(val);
@@ -362,7 +892,7 @@ fn weights_changes_add_or_clone(weights_changes: &mut Vec<Matrix>, to_add: &Vec<
 
         for i in 0..sequence_len {
             outputs.push( cesure.compute_next_verbose() );
-            errors.push( (error_calculation.calculate)(&outputs[i].output_out.output, &training_set.compute_sequence[i]) );
+            errors.push( error_calculation.calculate(&outputs[i].output_out.output, &training_set.compute_sequence[i]) );
         }
 
 
@@ -573,4 +1103,152 @@ pub fn iteration (cesure : &mut Cesure, training_set : &TrainingSet, learning_ra
     }
 
     error_sum
-}*/*/
\ No newline at end of file
+}*/*/
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use network::cesure;
+    use network::gate::memory_gate;
+    use network::music::new_infos;
+    use network::training::error_calculation::ERROR_CALCULATION_TYPE_BCE;
+    use network::training::error_calculation::ERROR_CALCULATION_TYPE_MSE;
+    use network::cesure::CesureConfig;
+    use utils::random::new_rng;
+
+    fn chord(keys : &[usize]) -> Matrix {
+        let mut chord = Matrix::new_row(CHORD_DIMENSION);
+        for &key in keys {
+            chord.datas[key] = 1.0;
+        }
+        chord
+    }
+
+    fn training_set(nb_ticks : usize) -> TrainingSet {
+        let compute_sequence = (0..nb_ticks).map(|tick| chord(&[tick % 12, 12 + (tick * 5) % 12])).collect();
+        TrainingSet::new(new_infos(480.0, nb_ticks, 36), vec![chord(&[0, 4, 7])], compute_sequence)
+    }
+
+    fn tiny_cesure() -> Cesure {
+        Cesure::new(4, cesure::OUTPUT_MODE_INDEPENDENT, memory_gate::MEMORY_TYPE_GRU, &mut new_rng(Some(26)))
+    }
+
+    fn trained(training_sets : &Vec<TrainingSet>, batch_config : &BatchConfig, iterations : usize) -> Cesure {
+        let mut cesure = tiny_cesure();
+        train_epochs(&mut cesure, training_sets, &ErrorCalculation::new(ERROR_CALCULATION_TYPE_BCE), batch_config, iterations, &mut || None, &mut new_rng(Some(26)));
+        cesure
+    }
+
+    #[test]
+    fn update_modes_agree_on_a_single_unit() {
+        let training_sets = vec![training_set(6)];
+        let per_step = trained(&training_sets, &BatchConfig::new(UPDATE_MODE_PER_STEP, BATCH_UNIT_SEQUENCE, 1, 1, false), 1);
+        let per_batch = trained(&training_sets, &BatchConfig::new(UPDATE_MODE_PER_BATCH, BATCH_UNIT_SEQUENCE, 4, 1, false), 1);
+        let full_batch = trained(&training_sets, &BatchConfig::new(UPDATE_MODE_FULL_BATCH, BATCH_UNIT_SEQUENCE, 1, 1, false), 1);
+        assert!(per_step.to_string() != tiny_cesure().to_string());
+        assert_eq!(per_step.to_string(), per_batch.to_string());
+        assert_eq!(per_step.to_string(), full_batch.to_string());
+    }
+
+    #[test]
+    fn units_cut_the_sets_in_windows() {
+        let training_sets = vec![training_set(7), training_set(0), training_set(3)];
+        let bounds = |units : Vec<BatchUnit>| -> Vec<(usize, usize, usize)> {
+            units.iter().map(|unit| (unit.set_i, unit.begin, unit.end)).collect()
+        };
+
+        let windows = BatchConfig::new(UPDATE_MODE_PER_STEP, BATCH_UNIT_WINDOW, 1, 3, false);
+        assert_eq!(bounds(build_units(&training_sets, &vec![0, 1, 2], &windows)),
+                   vec![(0, 0, 3), (0, 3, 6), (0, 6, 7), (2, 0, 3)]);
+
+        let sequences = BatchConfig::new(UPDATE_MODE_PER_STEP, BATCH_UNIT_SEQUENCE, 1, 3, false);
+        assert_eq!(bounds(build_units(&training_sets, &vec![2, 1, 0, 2], &sequences)),
+                   vec![(2, 0, 3), (0, 0, 7), (2, 0, 3)]);
+    }
+
+    #[test]
+    fn teacher_forcing_decays_linearly() {
+        let scheduled_sampling = ScheduledSampling {
+            sampler : Sampler::threshold(),
+            min_teacher_forcing : 0.2,
+            decay_epochs : 4,
+        };
+        let teacher_forcings : Vec<f64> = (0..7).map(|epoch| scheduled_sampling.teacher_forcing(epoch)).collect();
+        let expected = [1.0, 0.8, 0.6, 0.4, 0.2, 0.2, 0.2];
+        for (teacher_forcing, expected) in teacher_forcings.iter().zip(expected.iter()) {
+            assert!((teacher_forcing - expected).abs() < 1e-12);
+        }
+    }
+
+    /**
+    * Return the Gradients of a whole set computed as one unit, the ideal chords being fed
+    * to the memory gate so the loss only depends on the weights through the contexts
+    */
+    fn fed_gradients(cesure : &Cesure, training_set : &TrainingSet) -> Gradients {
+        let error_calculation = ErrorCalculation::new(ERROR_CALCULATION_TYPE_MSE);
+        let weighting = SetWeighting::default();
+        let sampler = Sampler::threshold();
+        let settings = EpochSettings {
+            error_calculation : &error_calculation,
+            weighting : &weighting,
+            learning_rate : 1.0,
+            tick_momentum : 0.0,
+            feeding : Some((&sampler, 1.0)),
+        };
+        let mut feeding = Some(UnitFeeding {
+            sampler : &sampler,
+            teacher_forcing : 1.0,
+            rand : new_rng(Some(48)),
+        });
+        unit_gradients(&mut cesure.clone(), training_set, 0, training_set.compute_sequence.len(), None, &settings, &mut feeding).0
+    }
+
+    /**
+    * Check a gate's summed changes against (E(w+ε)-E(w-ε))/2ε for about 20 weights of every parameter
+    * @input changes : The gate's changes, the opposite of the gradient with a learning rate of 1
+    * @input parameters : Return the gate's parameters in a cesure
+    */
+    fn check_gate<F: Fn(&mut Cesure) -> Vec<&mut Matrix>>(cesure : &Cesure, training_set : &TrainingSet, changes : &Vec<Matrix>, parameters : F, gate : &str) {
+        const EPSILON : f64 = 1e-6;
+        assert!(!changes.is_empty());
+        for p in 0..changes.len() {
+            for i in (0..changes[p].len).step_by(changes[p].len.div_ceil(20)) {
+                let mut plus = cesure.clone();
+                parameters(&mut plus)[p].datas[i] += EPSILON;
+                let mut minus = cesure.clone();
+                parameters(&mut minus)[p].datas[i] -= EPSILON;
+                // The losses are averaged over the keys while their gradients are the keys' ones
+                let numeric = CHORD_DIMENSION as f64 * (fed_gradients(&plus, training_set).error - fed_gradients(&minus, training_set).error) / (2.0 * EPSILON);
+                let analytic = -changes[p].datas[i];
+                assert!((analytic - numeric).abs() < 1e-6 * (1.0 + numeric.abs()),
+                        "{} parameter {} weight {} : backpropagated {} but the finite difference gives {}", gate, p, i, analytic, numeric);
+            }
+        }
+    }
+
+    #[test]
+    fn one_level_gradients_match_the_loss() {
+        let config = CesureConfig::new(3, cesure::OUTPUT_MODE_INDEPENDENT, memory_gate::MEMORY_TYPE_GRU)
+            .with_levels(vec![(2, 2)]);
+        let cesure = Cesure::from_config(&config, &mut new_rng(Some(48)));
+        let compute_sequence = (0..5).map(|tick| chord(&[tick % 12, 12 + (tick * 5) % 12])).collect();
+        let training_set = TrainingSet::new(new_infos(480.0, 5, 36), Vec::new(), compute_sequence);
+
+        let gradients = fed_gradients(&cesure, &training_set);
+        check_gate(&cesure, &training_set, &gradients.output_gate, |cesure| cesure.output_gate.parameters_mut(), "Output gate");
+        check_gate(&cesure, &training_set, &gradients.memory_gate, |cesure| cesure.memory_gate.parameters_mut(), "Memory gate");
+        check_gate(&cesure, &training_set, &gradients.levels[0], |cesure| cesure.levels[0].memory_gate.parameters_mut(), "Level");
+    }
+
+    #[test]
+    fn threads_dont_change_the_weights() {
+        let training_sets = vec![training_set(7), training_set(5), training_set(4)];
+        let batch_config = |nb_threads| BatchConfig::new(UPDATE_MODE_PER_BATCH, BATCH_UNIT_WINDOW, 3, 3, false).with_threads(nb_threads);
+        let one_thread = trained(&training_sets, &batch_config(1), 3);
+        let three_threads = trained(&training_sets, &batch_config(3), 3);
+        assert!(one_thread.to_string() != tiny_cesure().to_string());
+        assert_eq!(one_thread.to_string(), three_threads.to_string());
+    }
+}
@@ -1,14 +1,15 @@
 
 use network::cesure::Cesure;
+use network::gate::traits::Gate;
 
-pub struct CesureAndError {
-    pub cesure : Cesure,
+pub struct CesureAndError<O : Gate, M : Gate> {
+    pub cesure : Cesure<O, M>,
     pub error : f64,
 }
 
-impl CesureAndError {
+impl<O : Gate, M : Gate> CesureAndError<O, M> {
 
-    pub fn new(cesure : Cesure, error : f64) -> CesureAndError {
+    pub fn new(cesure : Cesure<O, M>, error : f64) -> CesureAndError<O, M> {
         return CesureAndError {
             cesure : cesure,
             error : error,
@@ -0,0 +1,309 @@
+use std::collections::HashMap;
+use std::fs;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+use network::cesure::Cesure;
+use network::gate::traits::Gate;
+use network::conditioning::Conditioning;
+use network::conditioning::ConditioningSchema;
+use network::conditioning::FIELD_TYPE_LABEL;
+use network::music::CesureMusic;
+use network::music::CHORD_DIMENSION;
+use network::music::set_infos_min_key;
+use network::music::infos_min_key;
+use network::training::training_set::TrainingSet;
+
+
+pub const SPLIT_TRAIN : u8 = 0;
+pub const SPLIT_VALIDATION : u8 = 1;
+pub const SPLIT_TEST : u8 = 2;
+
+pub const MANIFEST_FILE_NAME : &str = "manifest.csv";
+
+
+/**
+* A midi file of the corpus
+* - split : The SPLIT_ constant of the part of the corpus the file belongs to
+* - max_transpose : The training sets are also transposed by up to this number of
+*   semitones in both directions, 0 to forbid transposing the file
+* - weight : The file's weight in the training loss
+* - labels : The file's conditioning labels, by field name
+*/
+pub struct CorpusEntry {
+    pub path : PathBuf,
+    pub split : u8,
+    pub max_transpose : usize,
+    pub weight : f64,
+    pub labels : HashMap<String, String>,
+}
+
+/**
+* The midi files of a folder, described by the folder's optional manifest
+*/
+pub struct Corpus {
+    pub entries : Vec<CorpusEntry>,
+}
+
+
+
+impl CorpusEntry {
+
+    pub fn new(path : PathBuf) -> CorpusEntry {
+        return CorpusEntry {
+            path : path,
+            split : SPLIT_TRAIN,
+            max_transpose : 0,
+            weight : 1.0,
+            labels : HashMap::new(),
+        }
+    }
+
+}
+
+impl Corpus {
+
+    /**
+    * List the midi files of a folder, and read its manifest.csv if it exists
+    * The manifest's first line is a header naming the columns : "file" is the midi
+    * file's name, "split" is train, validation or test, "transpose" the maximum
+    * transposition in semitones, "weight" the loss weight, and any other column is
+    * a conditioning label field
+    * Every column but "file" is optional, and so is a file's line. The values can't
+    * contain commas
+    * @input folder : The folder's path
+    * @return The Corpus object
+    */
+    pub fn from_folder(folder : &str) -> Corpus {
+        let paths = fs::read_dir(folder).unwrap();
+
+        let mut entries = Vec::new();
+        for path in paths {
+            let path = path.unwrap().path();
+            print!("{}", path.display());
+            if path.display().to_string().ends_with(".mid") {
+                entries.push(CorpusEntry::new(path));
+                println!(" is midi");
+            } else {
+                println!(" is not midi");
+            }
+        }
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+
+        let mut corpus = Corpus {
+            entries : entries,
+        };
+        let manifest_path = Path::new(folder).join(MANIFEST_FILE_NAME);
+        if manifest_path.exists() {
+            corpus.read_manifest(&manifest_path);
+        }
+        corpus
+    }
+
+    /**
+    * Return the paths of the entries of a split
+    */
+    pub fn paths(&self, split : u8) -> Vec<PathBuf> {
+        self.entries.iter().filter(|entry| entry.split == split).map(|entry| entry.path.clone()).collect()
+    }
+
+    /**
+    * Return the sorted values of a label field, used to build a ConditioningSchema
+    * @input name : The label field's name
+    */
+    pub fn label_values(&self, name : &str) -> Vec<String> {
+        let mut values : Vec<String> = Vec::new();
+        for entry in &self.entries {
+            if let Some(value) = entry.labels.get(name) {
+                if !values.contains(value) {
+                    values.push(value.clone());
+                }
+            }
+        }
+        values.sort();
+        values
+    }
+
+    /**
+    * Fill the values of the schema's label fields that have none with the corpus' labels
+    */
+    pub fn fill_schema_labels(&self, schema : &mut ConditioningSchema) {
+        for field in &mut schema.fields {
+            if field.field_type == FIELD_TYPE_LABEL && field.labels.is_empty() {
+                field.labels = self.label_values(&field.name);
+            }
+        }
+    }
+
+    /**
+    * Read the entries of a split and convert them to TrainingSet objects whose infos
+    * follow the cesure's conditioning schema
+    * The conditioning values are derived from the files, then overridden by their sidecar
+    * files and by the manifest's labels. The train split's entries are also transposed
+    * @input cesure : The cesure the TrainingSet objects are for
+    * @input split : The SPLIT_ constant
+    * @input nb_first_note_to_inject : The number of ticks injected before computing
+    * @return The TrainingSet objects
+    */
+    pub fn training_sets<O: Gate, M: Gate>(&self, cesure : &Cesure<O, M>, split : u8, nb_first_note_to_inject : usize) -> Vec<TrainingSet> {
+        let mut training_sets = Vec::new();
+        for entry in self.entries.iter().filter(|entry| entry.split == split) {
+            let (music, meter) = CesureMusic::from_path_with_meter(&entry.path);
+            let mut conditioning = Conditioning::from_music(&music, &meter);
+            conditioning.read_sidecar(&entry.path);
+            for (name, value) in &entry.labels {
+                conditioning.set(name, value);
+            }
+
+            let mut training_set = music.to_training_set(nb_first_note_to_inject);
+            training_set.weight = entry.weight;
+            let max_transpose = match split {
+                SPLIT_TRAIN => entry.max_transpose as i32,
+                _ => 0,
+            };
+            let min_key = infos_min_key(&music.infos) as i32;
+            for transpose in -max_transpose..(max_transpose+1) {
+                let transposed_min_key = min_key + transpose;
+                if transposed_min_key < 0 || transposed_min_key as usize + CHORD_DIMENSION > 128 {
+                    continue;
+                }
+                let mut transposed_conditioning = conditioning.clone();
+                if let Some((root, is_major)) = conditioning.key {
+                    transposed_conditioning.key = Some((transposed_root(root, transpose), is_major));
+                }
+                let mut transposed_set = training_set.clone();
+                set_infos_min_key(&mut transposed_set.infos, transposed_min_key as usize);
+                transposed_set.infos = cesure.build_infos(&transposed_set.infos, &transposed_conditioning);
+                training_sets.push(transposed_set);
+            }
+        }
+        training_sets
+    }
+
+    fn read_manifest(&mut self, manifest_path : &Path) {
+        let file = File::open(manifest_path).expect("Couldn't open the manifest");
+        self.parse_manifest(BufReader::new(file).lines().map(|line| line.expect("Couldn't read the manifest")));
+    }
+
+    /**
+    * Apply a manifest's lines to the entries, see from_folder
+    * @input lines : The manifest's lines, the header first
+    */
+    fn parse_manifest<I: Iterator<Item = String>>(&mut self, mut lines : I) {
+        let header : Vec<String> = match lines.next() {
+            Some(header) => header.split(",").map(|x| x.trim().to_string()).collect(),
+            None => { return; },
+        };
+        let file_column = header.iter().position(|x| x == "file").expect("The manifest has no file column");
+
+        for line in lines {
+            let values : Vec<&str> = line.split(",").map(|x| x.trim()).collect();
+            if values.len() == 1 && values[0].is_empty() {
+                continue;
+            }
+            assert!(values.len() == header.len(), "Invalid manifest line : {}", line);
+            let entry = match self.entries.iter_mut().find(|entry| {
+                entry.path.file_name().map(|name| name.to_string_lossy() == values[file_column]).unwrap_or(false)
+            }) {
+                Some(entry) => entry,
+                None => {
+                    println!("{} is in the manifest but not in the folder", values[file_column]);
+                    continue;
+                }
+            };
+            for (column, value) in header.iter().zip(values.iter()) {
+                if value.is_empty() {
+                    continue;
+                }
+                match column.as_str() {
+                    "file" => {},
+                    "split" => {
+                        entry.split = match *value {
+                            "train" => SPLIT_TRAIN,
+                            "validation" => SPLIT_VALIDATION,
+                            "test" => SPLIT_TEST,
+                            _ => panic!("Unknown split {}", value),
+                        };
+                    }
+                    "transpose" => { entry.max_transpose = value.parse().expect("transpose expects an unsigned integer"); },
+                    "weight" => { entry.weight = value.parse().expect("weight expects a number"); },
+                    _ => { entry.labels.insert(column.clone(), value.to_string()); },
+                }
+            }
+        }
+    }
+
+}
+
+
+
+/**
+* Return the root pitch class of a key transposed by a number of semitones
+*/
+fn transposed_root(root : usize, transpose : i32) -> usize {
+    (((root as i32 + transpose) % 12 + 12) % 12) as usize
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn corpus(file_names : &[&str]) -> Corpus {
+        return Corpus {
+            entries : file_names.iter().map(|name| CorpusEntry::new(PathBuf::from(format!("./{}", name)))).collect(),
+        }
+    }
+
+    fn parsed(file_names : &[&str], manifest : &str) -> Corpus {
+        let mut corpus = corpus(file_names);
+        corpus.parse_manifest(manifest.lines().map(|line| line.to_string()));
+        corpus
+    }
+
+    #[test]
+    fn manifest_header_names_the_columns() {
+        let corpus = parsed(&["bach1.mid", "monk1.mid"], "weight, style ,file,split,transpose\n2.5,jazz,monk1.mid,validation,0\n1.0,baroque,bach1.mid,train,5\n");
+        let bach = &corpus.entries[0];
+        assert_eq!((bach.split, bach.max_transpose, bach.weight), (SPLIT_TRAIN, 5, 1.0));
+        assert_eq!(bach.labels.get("style"), Some(&"baroque".to_string()));
+        let monk = &corpus.entries[1];
+        assert_eq!((monk.split, monk.max_transpose, monk.weight), (SPLIT_VALIDATION, 0, 2.5));
+        assert_eq!(corpus.label_values("style"), vec!["baroque".to_string(), "jazz".to_string()]);
+        assert_eq!(corpus.paths(SPLIT_VALIDATION), vec![PathBuf::from("./monk1.mid")]);
+    }
+
+    #[test]
+    fn manifest_columns_and_lines_are_optional() {
+        let corpus = parsed(&["a.mid", "b.mid", "c.mid"], "file,split,weight,composer\na.mid,test,,\n\nb.mid,,3.0,satie\nmissing.mid,train,1.0,x\n");
+        let a = &corpus.entries[0];
+        assert_eq!((a.split, a.max_transpose, a.weight, a.labels.len()), (SPLIT_TEST, 0, 1.0, 0));
+        let b = &corpus.entries[1];
+        assert_eq!((b.split, b.weight), (SPLIT_TRAIN, 3.0));
+        assert_eq!(b.labels.get("composer"), Some(&"satie".to_string()));
+        // A file missing from the manifest keeps the defaults
+        let c = &corpus.entries[2];
+        assert_eq!((c.split, c.max_transpose, c.weight, c.labels.len()), (SPLIT_TRAIN, 0, 1.0, 0));
+
+        let header_only = parsed(&["a.mid"], "file");
+        assert_eq!(header_only.entries[0].weight, 1.0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn manifest_needs_a_file_column() {
+        parsed(&["a.mid"], "split,weight\ntrain,1.0");
+    }
+
+    #[test]
+    fn transposed_keys_wrap() {
+        assert_eq!(transposed_root(9, 5), 2);
+        assert_eq!(transposed_root(2, -5), 9);
+        assert_eq!(transposed_root(0, -1), 11);
+        assert_eq!(transposed_root(11, 1), 0);
+        assert_eq!(transposed_root(4, 0), 4);
+        assert_eq!(transposed_root(7, -12), 7);
+    }
+}
@@ -6,11 +6,48 @@ use network::music::CHORD_DIMENSION;
 pub const ERROR_CALCULATION_TYPE_BASIC : u8 = 0;
 pub const ERROR_CALCULATION_TYPE_ONLY_ON : u8 = 1;
 pub const ERROR_CALCULATION_TYPE_SMART : u8 = 2;
+pub const ERROR_CALCULATION_TYPE_BCE : u8 = 3;
+pub const ERROR_CALCULATION_TYPE_WEIGHTED_BCE : u8 = 4;
+pub const ERROR_CALCULATION_TYPE_FOCAL : u8 = 5;
+pub const ERROR_CALCULATION_TYPE_MSE : u8 = 6;
 
+// The outputs are clamped to [EPSILON, 1-EPSILON] before taking their log
+pub const EPSILON : f64 = 1e-7;
 
+
+/**
+* The result of an error calculation
+* - value : The loss, averaged over the keys, used to report and compare errors
+* - gradient : The error signal to backpropagate, the gradient of each key's loss
+*/
+pub struct Loss {
+    pub value : f64,
+    pub gradient : Matrix,
+}
+
+impl Loss {
+
+    pub fn new(value : f64, gradient : Matrix) -> Loss {
+        return Loss {
+            value : value,
+            gradient : gradient,
+        }
+    }
+
+}
+
+
+/**
+* The error calculation used to train a cesure
+* The BCE, weighted BCE, focal and MSE losses accept soft ideal values in [0, 1],
+* the other types expect ideal chords made of 0.0 and 1.0 only
+* - positive_weight : The weight of the ideal notes' term (weighted BCE and focal loss)
+* - focal_gamma : The focusing parameter of the focal loss
+*/
 pub struct ErrorCalculation {
     pub calculation_type: u8,
-    pub calculate: fn(output_chord: &Matrix, ideal_chord: &Matrix) -> Matrix,
+    pub positive_weight: f64,
+    pub focal_gamma: f64,
 }
 
 
@@ -18,12 +55,14 @@ impl Clone for ErrorCalculation {
     fn clone(&self) -> ErrorCalculation {
         ErrorCalculation {
             calculation_type : self.calculation_type,
-            calculate : self.calculate,
+            positive_weight : self.positive_weight,
+            focal_gamma : self.focal_gamma,
         }
     }
     fn clone_from(&mut self, source: &ErrorCalculation) {
         self.calculation_type = source.calculation_type;
-        self.calculate = source.calculate;
+        self.positive_weight = source.positive_weight;
+        self.focal_gamma = source.focal_gamma;
     }
 }
 
@@ -31,71 +70,296 @@ impl Clone for ErrorCalculation {
 impl ErrorCalculation {
 
     pub fn new(calculation_type: u8) -> ErrorCalculation {
-        let error_calculation = match calculation_type {
-            ERROR_CALCULATION_TYPE_BASIC => calculation_basic,
-            ERROR_CALCULATION_TYPE_ONLY_ON => calculation_only_on,
-            ERROR_CALCULATION_TYPE_SMART => calculation_smart,
-            _ => panic!("Unknown calculation type"),
-        };
+        assert!(calculation_type <= ERROR_CALCULATION_TYPE_MSE, "Unknown calculation type");
         return ErrorCalculation {
             calculation_type : calculation_type,
-            calculate : error_calculation,
+            positive_weight : 1.0,
+            focal_gamma : 2.0,
         }
     }
 
+    /**
+    * Create a weighted binary cross-entropy error calculation
+    * @input positive_weight : The weight of the ideal notes' term, > 1.0 to
+    *                          penalize missed notes more than extra notes
+    */
+    pub fn new_weighted_bce(positive_weight: f64) -> ErrorCalculation {
+        assert!(positive_weight > 0.0);
+        let mut error_calculation = ErrorCalculation::new(ERROR_CALCULATION_TYPE_WEIGHTED_BCE);
+        error_calculation.positive_weight = positive_weight;
+        error_calculation
+    }
+
+    /**
+    * Create a focal loss error calculation
+    * @input gamma : The focusing parameter, 0.0 gives the binary cross-entropy
+    * @input positive_weight : The weight of the ideal notes' term
+    */
+    pub fn new_focal(gamma: f64, positive_weight: f64) -> ErrorCalculation {
+        assert!(gamma >= 0.0 && positive_weight > 0.0);
+        let mut error_calculation = ErrorCalculation::new(ERROR_CALCULATION_TYPE_FOCAL);
+        error_calculation.focal_gamma = gamma;
+        error_calculation.positive_weight = positive_weight;
+        error_calculation
+    }
+
+    /**
+    * Calculate the loss of an output chord and its error signal
+    * @input output_chord : The computed chord
+    * @input ideal_chord : The ideal chord
+    * @return The Loss object
+    */
+    pub fn calculate(&self, output_chord: &Matrix, ideal_chord: &Matrix) -> Loss {
+        let (value, gradient) = match self.calculation_type {
+            ERROR_CALCULATION_TYPE_BASIC => calculation_basic(output_chord, ideal_chord),
+            ERROR_CALCULATION_TYPE_ONLY_ON => calculation_only_on(output_chord, ideal_chord),
+            ERROR_CALCULATION_TYPE_SMART => calculation_smart(output_chord, ideal_chord),
+            ERROR_CALCULATION_TYPE_BCE => loss_weighted_bce(output_chord, ideal_chord, 1.0),
+            ERROR_CALCULATION_TYPE_WEIGHTED_BCE => loss_weighted_bce(output_chord, ideal_chord, self.positive_weight),
+            ERROR_CALCULATION_TYPE_FOCAL => loss_focal(output_chord, ideal_chord, self.focal_gamma, self.positive_weight),
+            ERROR_CALCULATION_TYPE_MSE => loss_mse(output_chord, ideal_chord),
+            _ => panic!("Unknown calculation type"),
+        };
+        Loss::new(value, gradient)
+    }
+
 }
 
 
-fn calculation_basic(output_chord: &Matrix, ideal_chord: &Matrix) -> Matrix {
+/**
+* The original error : the signal is (output-ideal), the loss 0.5*(output-ideal)^2
+*/
+fn calculation_basic(output_chord: &Matrix, ideal_chord: &Matrix) -> (f64, Matrix) {
     assert!(output_chord.is_row() && output_chord.len == CHORD_DIMENSION);
     assert!(ideal_chord.is_row() && ideal_chord.len == CHORD_DIMENSION);
+    let mut loss = 0.0;
     let mut output = Vec::with_capacity(CHORD_DIMENSION);
     for i in 0..CHORD_DIMENSION {
-        output.push(match ideal_chord.datas[i] {
+        let error = match ideal_chord.datas[i] {
             0.0 => output_chord.datas[i],
             1.0 => output_chord.datas[i] - 1.0,
             _ => panic!("Ideal chord isn't normalized"),
-        });
+        };
+        loss += 0.5 * error * error;
+        output.push(error);
     }
-    Matrix::new_row_from_datas(output)
+    (loss / CHORD_DIMENSION as f64, Matrix::new_row_from_datas(output))
 }
 
-fn calculation_only_on(output_chord: &Matrix, ideal_chord: &Matrix) -> Matrix {
+/**
+* Like calculation_basic, but only the ideal notes count
+*/
+fn calculation_only_on(output_chord: &Matrix, ideal_chord: &Matrix) -> (f64, Matrix) {
     assert!(output_chord.is_row() && output_chord.len == CHORD_DIMENSION);
     assert!(ideal_chord.is_row() && ideal_chord.len == CHORD_DIMENSION);
+    let mut loss = 0.0;
     let mut output = Vec::with_capacity(CHORD_DIMENSION);
     for i in 0..CHORD_DIMENSION {
-        output.push(match ideal_chord.datas[i] {
+        let error = match ideal_chord.datas[i] {
             0.0 => 0.0,
             1.0 => output_chord.datas[i] - 1.0,
             _ => panic!("Ideal chord isn't normalized"),
-        });
+        };
+        loss += 0.5 * error * error;
+        output.push(error);
     }
-    Matrix::new_row_from_datas(output)
+    (loss / CHORD_DIMENSION as f64, Matrix::new_row_from_datas(output))
 }
 
-fn calculation_smart(output_chord: &Matrix, ideal_chord: &Matrix) -> Matrix {
+/**
+* The extra notes are barely penalized under 0.9, and strongly above it
+* The loss is the integral of the signal, so that it is 0.0 for a perfect output
+*/
+fn calculation_smart(output_chord: &Matrix, ideal_chord: &Matrix) -> (f64, Matrix) {
     assert!(output_chord.is_row() && output_chord.len == CHORD_DIMENSION);
     assert!(ideal_chord.is_row() && ideal_chord.len == CHORD_DIMENSION);
+    let mut loss = 0.0;
     let mut output = Vec::with_capacity(CHORD_DIMENSION);
     for i in 0..CHORD_DIMENSION {
         output.push(match ideal_chord.datas[i] {
             0.0 => {
-                let error = {
-                    let delta = output_chord.datas[i];
-                    match delta > 0.9 {
-                        true => 9.0*delta - 8.0,
-                        false => delta/9.0,
-                    }
+                let delta = output_chord.datas[i];
+                let error = match delta > 0.9 {
+                    true => {
+                        loss += 0.81/216.0 + (4.5*delta*delta - 8.0*delta + 3.555) / 12.0;
+                        9.0*delta - 8.0
+                    },
+                    false => {
+                        loss += delta*delta / 216.0;
+                        delta/9.0
+                    },
                 };
                 error / 12.0
             },
             1.0 => {
                 let error = output_chord.datas[i] - 1.0;
+                loss += 0.5 * error * error;
                 error
             },
             _ => panic!("Ideal chord isn't normalized"),
         });
     }
-    Matrix::new_row_from_datas(output)
-}
\ No newline at end of file
+    (loss / CHORD_DIMENSION as f64, Matrix::new_row_from_datas(output))
+}
+
+
+#[inline]
+fn clamp_probability(x : f64) -> f64 {
+    x.clamp(EPSILON, 1.0 - EPSILON)
+}
+
+/**
+* Weighted binary cross-entropy : -(w*y*ln(p) + (1-y)*ln(1-p)), with w = 1.0 for the plain BCE
+* @return A tuple composed by the loss averaged over the keys and the gradient
+*         of each key's loss with respect to the output
+*/
+fn loss_weighted_bce(output_chord: &Matrix, ideal_chord: &Matrix, positive_weight: f64) -> (f64, Matrix) {
+    assert!(output_chord.is_row() && output_chord.len == CHORD_DIMENSION);
+    assert!(ideal_chord.is_row() && ideal_chord.len == CHORD_DIMENSION);
+    let mut loss = 0.0;
+    let mut gradient = Vec::with_capacity(CHORD_DIMENSION);
+    for i in 0..CHORD_DIMENSION {
+        let p = clamp_probability(output_chord.datas[i]);
+        let y = ideal_chord.datas[i];
+        loss -= positive_weight * y * p.ln() + (1.0 - y) * (1.0 - p).ln();
+        gradient.push(-positive_weight * y / p + (1.0 - y) / (1.0 - p));
+    }
+    (loss / CHORD_DIMENSION as f64, Matrix::new_row_from_datas(gradient))
+}
+
+/**
+* Focal loss : -(w*y*(1-p)^g*ln(p) + (1-y)*p^g*ln(1-p))
+* The well predicted keys' terms are scaled down, so training focuses on the hard ones
+* @return A tuple composed by the loss averaged over the keys and the gradient
+*         of each key's loss with respect to the output
+*/
+fn loss_focal(output_chord: &Matrix, ideal_chord: &Matrix, gamma: f64, positive_weight: f64) -> (f64, Matrix) {
+    assert!(output_chord.is_row() && output_chord.len == CHORD_DIMENSION);
+    assert!(ideal_chord.is_row() && ideal_chord.len == CHORD_DIMENSION);
+    let mut loss = 0.0;
+    let mut gradient = Vec::with_capacity(CHORD_DIMENSION);
+    for i in 0..CHORD_DIMENSION {
+        let p = clamp_probability(output_chord.datas[i]);
+        let y = ideal_chord.datas[i];
+        let on_scale = (1.0 - p).powf(gamma);
+        let off_scale = p.powf(gamma);
+        loss -= positive_weight * y * on_scale * p.ln() + (1.0 - y) * off_scale * (1.0 - p).ln();
+        let on_gradient = gamma * (1.0 - p).powf(gamma - 1.0) * p.ln() - on_scale / p;
+        let off_gradient = -gamma * p.powf(gamma - 1.0) * (1.0 - p).ln() + off_scale / (1.0 - p);
+        gradient.push(positive_weight * y * on_gradient + (1.0 - y) * off_gradient);
+    }
+    (loss / CHORD_DIMENSION as f64, Matrix::new_row_from_datas(gradient))
+}
+
+/**
+* Mean squared error : (p-y)^2
+* @return A tuple composed by the loss averaged over the keys and the gradient
+*         of each key's loss with respect to the output
+*/
+fn loss_mse(output_chord: &Matrix, ideal_chord: &Matrix) -> (f64, Matrix) {
+    assert!(output_chord.is_row() && output_chord.len == CHORD_DIMENSION);
+    assert!(ideal_chord.is_row() && ideal_chord.len == CHORD_DIMENSION);
+    let mut loss = 0.0;
+    let mut gradient = Vec::with_capacity(CHORD_DIMENSION);
+    for i in 0..CHORD_DIMENSION {
+        let delta = output_chord.datas[i] - ideal_chord.datas[i];
+        loss += delta * delta;
+        gradient.push(2.0 * delta);
+    }
+    (loss / CHORD_DIMENSION as f64, Matrix::new_row_from_datas(gradient))
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DELTA : f64 = 1e-6;
+    const TOLERANCE : f64 = 1e-5;
+
+    /**
+    * An output chord spread over ]0, 1[, its first key being above the smart loss' 0.9 threshold
+    */
+    fn output_chord() -> Matrix {
+        let mut datas : Vec<f64> = (0..CHORD_DIMENSION).map(|i| 0.05 + 0.85 * ((i * 37) % 100) as f64 / 100.0).collect();
+        datas[0] = 0.95;
+        Matrix::new_row_from_datas(datas)
+    }
+
+    fn binary_ideal_chord() -> Matrix {
+        Matrix::new_row_from_datas((0..CHORD_DIMENSION).map(|i| if i % 3 == 1 { 1.0 } else { 0.0 }).collect())
+    }
+
+    fn soft_ideal_chord() -> Matrix {
+        Matrix::new_row_from_datas((0..CHORD_DIMENSION).map(|i| ((i * 53) % 100) as f64 / 100.0).collect())
+    }
+
+    /**
+    * Check the gradient of each key's loss against the finite difference of the loss,
+    * which is averaged over the keys
+    */
+    fn check_gradient(error_calculation : &ErrorCalculation, ideal_chord : &Matrix) {
+        let output_chord = output_chord();
+        let loss = error_calculation.calculate(&output_chord, ideal_chord);
+        for i in 0..CHORD_DIMENSION {
+            let mut plus = output_chord.clone();
+            plus.datas[i] += DELTA;
+            let mut minus = output_chord.clone();
+            minus.datas[i] -= DELTA;
+            let numeric = CHORD_DIMENSION as f64 * (error_calculation.calculate(&plus, ideal_chord).value
+                                                     - error_calculation.calculate(&minus, ideal_chord).value) / (2.0 * DELTA);
+            assert!((loss.gradient.datas[i] - numeric).abs() < TOLERANCE * (1.0 + numeric.abs()),
+                    "Key {} : gradient {} but the finite difference gives {}", i, loss.gradient.datas[i], numeric);
+        }
+    }
+
+    #[test]
+    fn basic_gradient() {
+        check_gradient(&ErrorCalculation::new(ERROR_CALCULATION_TYPE_BASIC), &binary_ideal_chord());
+    }
+
+    #[test]
+    fn only_on_gradient() {
+        check_gradient(&ErrorCalculation::new(ERROR_CALCULATION_TYPE_ONLY_ON), &binary_ideal_chord());
+    }
+
+    #[test]
+    fn smart_gradient() {
+        check_gradient(&ErrorCalculation::new(ERROR_CALCULATION_TYPE_SMART), &binary_ideal_chord());
+    }
+
+    #[test]
+    fn smart_loss_is_continuous() {
+        let error_calculation = ErrorCalculation::new(ERROR_CALCULATION_TYPE_SMART);
+        let ideal_chord = Matrix::new_row(CHORD_DIMENSION);
+        let mut below = Matrix::new_row(CHORD_DIMENSION);
+        below.datas[0] = 0.9 - DELTA;
+        let mut above = Matrix::new_row(CHORD_DIMENSION);
+        above.datas[0] = 0.9 + DELTA;
+        let gap = error_calculation.calculate(&above, &ideal_chord).value - error_calculation.calculate(&below, &ideal_chord).value;
+        assert!(gap.abs() < 1e-6);
+    }
+
+    #[test]
+    fn bce_gradient() {
+        check_gradient(&ErrorCalculation::new(ERROR_CALCULATION_TYPE_BCE), &soft_ideal_chord());
+    }
+
+    #[test]
+    fn weighted_bce_gradient() {
+        check_gradient(&ErrorCalculation::new_weighted_bce(3.0), &binary_ideal_chord());
+        check_gradient(&ErrorCalculation::new_weighted_bce(3.0), &soft_ideal_chord());
+    }
+
+    #[test]
+    fn focal_gradient() {
+        check_gradient(&ErrorCalculation::new_focal(2.0, 3.0), &binary_ideal_chord());
+        check_gradient(&ErrorCalculation::new_focal(0.5, 1.0), &soft_ideal_chord());
+    }
+
+    #[test]
+    fn mse_gradient() {
+        check_gradient(&ErrorCalculation::new(ERROR_CALCULATION_TYPE_MSE), &soft_ideal_chord());
+    }
+}
@@ -5,23 +5,28 @@ extern crate crossbeam;
 use std::f64;
 use std::sync::{Arc, Mutex};
 
+use rand::{Rng, StdRng};
+
 use network::cesure::Cesure;
+use network::gate::traits::Gate;
 use network::training::error_calculation::ErrorCalculation;
 use network::training::training_set::TrainingSet;
+use network::training::training_set::SetWeighting;
 
 use utils::traits::Parse;
 use utils::io::{AsyncStdinReader, AsyncStdinRead};
 use utils::io::stdin_readline;
+use utils::random::derive_rng;
 
 
 
-struct CesureAndErrors {
-    pub cesure : Cesure,
+struct CesureAndErrors<O: Gate, M: Gate> {
+    pub cesure : Cesure<O, M>,
     pub errors : Vec<f64>,
 }
 
-struct CesureAndError {
-    pub cesure: Cesure,
+struct CesureAndError<O: Gate, M: Gate> {
+    pub cesure: Cesure<O, M>,
     pub error: f64,
 }
 
@@ -29,16 +34,19 @@ struct CesureAndError {
 /**
 * Train a Cesure object using a simple Genetic algorithm
 * @input cesure : The cesure object to train
-* @input magnitude_0 : The amount of changes at iteration0
-* @input magnitude_1 : The amount of changes at iteration1
+* @input magnitudes : The amounts of changes at iteration0 and at iteration1
 * @input iterations : The number of iterations
 * @input training_sets : The TrainingSet objects to calculate the error on
+* @input weighting : The SetWeighting of the error, its sets drawing isn't used as the errors
+*                    of two iterations have to be comparable
+* @input rand : The random number generator, every thread gets its own generator seeded from it
 */
-pub fn train(cesure : &mut Cesure, training_sets: &Vec<TrainingSet>, error_calculation: &ErrorCalculation, magnitude_0: f64, magnitude_1: f64, iterations: usize) {
+pub fn train<O: Gate, M: Gate, R: Rng>(cesure : &mut Cesure<O, M>, training_sets: &Vec<TrainingSet>, error_calculation: &ErrorCalculation, weighting: &SetWeighting,
+                                       magnitudes: (f64, f64), iterations: usize, rand: &mut R) {
 
     let mut best_cesure = {
         let mut first_cesure = cesure.clone();
-        let first_error = first_cesure.calculate_error_sum_multi(&training_sets, &error_calculation);
+        let first_error = first_cesure.calculate_error_weighted(training_sets, error_calculation, weighting);
         CesureAndError {cesure: first_cesure, error: first_error}
     };
 
@@ -46,8 +54,7 @@ pub fn train(cesure : &mut Cesure, training_sets: &Vec<TrainingSet>, error_calcu
     let mut stdin = AsyncStdinReader::new();
     let mut show = true;
     let mut iterations = iterations;
-    let mut magnitude_0 = magnitude_0;
-    let mut magnitude_1 = magnitude_1;
+    let (mut magnitude_0, mut magnitude_1) = magnitudes;
 
     for iteration in 0..iterations {
 
@@ -120,19 +127,20 @@ pub fn train(cesure : &mut Cesure, training_sets: &Vec<TrainingSet>, error_calcu
             break;
         }
 
-        let mut threads = Vec::with_capacity(nb_threads);
-        crossbeam::scope(|scope| {
-            for _ in 0..nb_threads {
-                let thread = scope.spawn(|| iteration_mod1(&best_cesure, &training_sets, &error_calculation, actual_magnitude));
+        let threads_rands : Vec<StdRng> = (0..nb_threads).map(|_| derive_rng(rand)).collect();
+        let results : Vec<Option<CesureAndError<O, M>>> = crossbeam::scope(|scope| {
+            let mut threads = Vec::with_capacity(nb_threads);
+            for mut thread_rand in threads_rands {
+                let best_cesure = &best_cesure;
+                let thread = scope.spawn(move || iteration_mod1(best_cesure, training_sets, error_calculation, weighting, actual_magnitude, &mut thread_rand));
                 threads.push(thread);
             }
+            threads.into_iter().map(|thread| thread.join()).collect()
         });
 
-        for thread in threads {
-            if let Some(cesure_and_error) = thread.join() {
-                if cesure_and_error.error < best_cesure.error {
-                    best_cesure = cesure_and_error;
-                }
+        for cesure_and_error in results.into_iter().flatten() {
+            if cesure_and_error.error < best_cesure.error {
+                best_cesure = cesure_and_error;
             }
         }
 
@@ -157,9 +165,10 @@ pub fn train(cesure : &mut Cesure, training_sets: &Vec<TrainingSet>, error_calcu
         },
     }
 }
-fn iteration_mod1(cesure: &CesureAndError, training_sets: &Vec<TrainingSet>, error_calculation: &ErrorCalculation, magnitude: f64) -> Option<CesureAndError> {
-    let mut computed_cesure = cesure.cesure.clone_randomized(magnitude, &mut rand::thread_rng());
-    let computed_error = computed_cesure.calculate_error_sum_multi(&training_sets, &error_calculation);
+fn iteration_mod1<O: Gate, M: Gate, R: Rng>(cesure: &CesureAndError<O, M>, training_sets: &Vec<TrainingSet>, error_calculation: &ErrorCalculation, weighting: &SetWeighting,
+                                            magnitude: f64, rand: &mut R) -> Option<CesureAndError<O, M>> {
+    let mut computed_cesure = cesure.cesure.clone_randomized(magnitude, rand);
+    let computed_error = computed_cesure.calculate_error_weighted(training_sets, error_calculation, weighting);
     if computed_error > cesure.error {
         return None;
     } else {
@@ -167,7 +176,8 @@ fn iteration_mod1(cesure: &CesureAndError, training_sets: &Vec<TrainingSet>, err
     }
 }
 
-pub fn train_mod2(cesure : &mut Cesure, training_sets: &Vec<TrainingSet>, error_calculation: &ErrorCalculation, magnitude_0: f64, magnitude_1: f64, iterations: usize) {
+pub fn train_mod2<O: Gate, M: Gate, R: Rng>(cesure : &mut Cesure<O, M>, training_sets: &Vec<TrainingSet>, error_calculation: &ErrorCalculation, magnitude_0: f64, magnitude_1: f64, iterations: usize,
+                                            rand: &mut R) {
 
     let mut best_cesure = cesure.clone();
 
@@ -254,10 +264,13 @@ pub fn train_mod2(cesure : &mut Cesure, training_sets: &Vec<TrainingSet>, error_
         }
 
 
-        let mut new_cesure = best_cesure.clone_randomized(actual_magnitude, &mut rand::thread_rng());
+        let mut new_cesure = best_cesure.clone_randomized(actual_magnitude, rand);
 
-        let mut best_cesure_error = (error_calculation.calculate)(&best_cesure.compute_next(), &training_sets[training_set_i].compute_sequence[training_set_tick_i]).get_abs_avg();
-        let new_cesure_error = (error_calculation.calculate)(&new_cesure.compute_next(), &training_sets[training_set_i].compute_sequence[training_set_tick_i]).get_abs_avg();
+        let ideal_chord = &training_sets[training_set_i].compute_sequence[training_set_tick_i];
+        let best_cesure_output = best_cesure.compute_next();
+        let mut best_cesure_error = best_cesure.calculate_output_error(&best_cesure_output, ideal_chord, error_calculation).value;
+        let new_cesure_output = new_cesure.compute_next();
+        let new_cesure_error = new_cesure.calculate_output_error(&new_cesure_output, ideal_chord, error_calculation).value;
         if new_cesure_error < best_cesure_error {
             best_cesure = new_cesure;
             best_cesure_error = new_cesure_error;
@@ -295,7 +308,7 @@ pub fn train_mod2(cesure : &mut Cesure, training_sets: &Vec<TrainingSet>, error_
 }
 
 
-fn ui_save_cesure(cesure: &Cesure, stdin: &mut Arc<Mutex<AsyncStdinReader>>) {
+fn ui_save_cesure<O: Gate, M: Gate>(cesure: &Cesure<O, M>, stdin: &mut Arc<Mutex<AsyncStdinReader>>) {
     println!("Type the file name to save Cesure in (type nothing if you don't want to save) :");
     let answer = stdin.read_line_blocking();
     match answer.as_str() {
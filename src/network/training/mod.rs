@@ -3,4 +3,5 @@ pub mod genetic;
 
 pub mod training_set;
 pub mod error_calculation;
-pub mod cesure_and_error;
\ No newline at end of file
+pub mod cesure_and_error;
+pub mod corpus;
\ No newline at end of file
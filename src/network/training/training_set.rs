@@ -1,3 +1,6 @@
+extern crate rand;
+
+use rand::Rng;
 
 use utils::matrix::Matrix;
 
@@ -6,17 +9,32 @@ use network::music::CHORD_DIMENSION;
 
 
 
+/**
+* - weight : The set's weight in the training loss
+*/
 pub struct TrainingSet {
     pub infos : Matrix,
     pub inject_sequence : Vec<Matrix>,
     pub compute_sequence : Vec<Matrix>,
+    pub weight : f64,
+}
+
+impl Clone for TrainingSet {
+    fn clone(&self) -> TrainingSet {
+        return TrainingSet {
+            infos : self.infos.clone(),
+            inject_sequence : self.inject_sequence.clone(),
+            compute_sequence : self.compute_sequence.clone(),
+            weight : self.weight,
+        }
+    }
 }
 
 
 impl TrainingSet {
 
     pub fn new(infos : Matrix, inject_sequence : Vec<Matrix>, compute_sequence : Vec<Matrix>) -> TrainingSet {
-        assert!(infos.is_row() && infos.len == INFOS_DIMENSION);
+        assert!(infos.is_row() && infos.len >= INFOS_DIMENSION);
         for i in 0..inject_sequence.len() {
             assert!(inject_sequence[i].is_row() && inject_sequence[i].len == CHORD_DIMENSION);
         }
@@ -27,7 +45,127 @@ impl TrainingSet {
             infos : infos,
             inject_sequence : inject_sequence,
             compute_sequence : compute_sequence,
+            weight : 1.0,
+        }
+    }
+
+}
+
+
+/**
+* How the TrainingSet objects are weighted in the training loss
+* - use_set_weights : Multiply every set's loss by its weight
+* - length_normalized : Divide every set's loss by its number of computed ticks,
+*   so that long pieces don't dominate the loss
+* - nb_sampled_sets : Train every epoch on this number of sets, drawn with replacement
+*   proportionally to their weights, 0 to train on every set
+*   The drawn sets' losses aren't multiplied by their weights again
+*/
+pub struct SetWeighting {
+    pub use_set_weights : bool,
+    pub length_normalized : bool,
+    pub nb_sampled_sets : usize,
+}
+
+impl Default for SetWeighting {
+    /**
+    * The weighting used by default : the sets' weights are applied, every tick of a set has the same weight
+    */
+    fn default() -> SetWeighting {
+        SetWeighting::new(true, false, 0)
+    }
+}
+
+impl SetWeighting {
+
+    pub fn new(use_set_weights : bool, length_normalized : bool, nb_sampled_sets : usize) -> SetWeighting {
+        return SetWeighting {
+            use_set_weights : use_set_weights,
+            length_normalized : length_normalized,
+            nb_sampled_sets : nb_sampled_sets,
+        }
+    }
+
+    /**
+    * Return the factor applied to the loss of every computed tick of a set
+    */
+    pub fn tick_factor(&self, training_set : &TrainingSet) -> f64 {
+        let mut factor = 1.0;
+        if self.use_set_weights && self.nb_sampled_sets == 0 {
+            factor *= training_set.weight;
+        }
+        if self.length_normalized && !training_set.compute_sequence.is_empty() {
+            factor /= training_set.compute_sequence.len() as f64;
+        }
+        factor
+    }
+
+    /**
+    * Return the indexes of the sets to train on during an epoch
+    * @input training_sets : The TrainingSet objects
+    * @input rand : The random number generator used to draw the sets
+    */
+    pub fn epoch_sets<R: Rng>(&self, training_sets : &Vec<TrainingSet>, rand : &mut R) -> Vec<usize> {
+        if self.nb_sampled_sets == 0 {
+            return (0..training_sets.len()).collect();
+        }
+        let weights : Vec<f64> = training_sets.iter().map(|set| match self.use_set_weights {
+            true => set.weight,
+            false => 1.0,
+        }).collect();
+        let weights_sum : f64 = weights.iter().sum();
+        assert!(weights_sum > 0.0, "Can't draw sets whose weights are all zero");
+        let mut indexes = Vec::with_capacity(self.nb_sampled_sets);
+        for _ in 0..self.nb_sampled_sets {
+            let x = rand.gen::<f64>() * weights_sum;
+            let mut cumulated = 0.0;
+            let mut drawn = weights.len() - 1;
+            for i in 0..weights.len() {
+                cumulated += weights[i];
+                if x < cumulated {
+                    drawn = i;
+                    break;
+                }
+            }
+            indexes.push(drawn);
         }
+        indexes
     }
 
-}
\ No newline at end of file
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use utils::random::new_rng;
+
+    fn weighted_set(weight : f64, nb_ticks : usize) -> TrainingSet {
+        let mut set = TrainingSet::new(Matrix::new_row(INFOS_DIMENSION), Vec::new(), vec![Matrix::new_row(CHORD_DIMENSION); nb_ticks]);
+        set.weight = weight;
+        set
+    }
+
+    #[test]
+    fn tick_factor() {
+        let set = weighted_set(3.0, 4);
+        assert_eq!(SetWeighting::default().tick_factor(&set), 3.0);
+        assert_eq!(SetWeighting::new(true, true, 0).tick_factor(&set), 0.75);
+        assert_eq!(SetWeighting::new(false, true, 0).tick_factor(&set), 0.25);
+        // The drawn sets are already weighted by their drawing
+        assert_eq!(SetWeighting::new(true, false, 2).tick_factor(&set), 1.0);
+    }
+
+    #[test]
+    fn epoch_sets_follow_the_weights() {
+        let sets = vec![weighted_set(1.0, 1), weighted_set(0.0, 1), weighted_set(3.0, 1)];
+        assert_eq!(SetWeighting::default().epoch_sets(&sets, &mut new_rng(Some(44))), vec![0, 1, 2]);
+
+        let drawn = SetWeighting::new(true, false, 4000).epoch_sets(&sets, &mut new_rng(Some(44)));
+        assert_eq!(drawn.len(), 4000);
+        let nb_drawn = |i : usize| drawn.iter().filter(|&&drawn_i| drawn_i == i).count();
+        assert_eq!(nb_drawn(1), 0);
+        assert!(nb_drawn(2) > 2 * nb_drawn(0) && nb_drawn(2) < 4 * nb_drawn(0));
+    }
+}
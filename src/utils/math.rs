@@ -5,7 +5,7 @@ use rand::Rng;
 
 
 #[inline]
-pub fn rand(min: f64, max: f64, rand: &mut rand::ThreadRng) -> f64 {
+pub fn rand<R: Rng>(min: f64, max: f64, rand: &mut R) -> f64 {
     match min == max {
         true => { return min; },
         false => {
@@ -47,4 +47,140 @@ pub fn tanh(x : f64) -> f64 {
 #[inline]
 pub fn tanh_deriv(x : f64) -> f64 {
     return 1.0/square(1.0+abs(x));
-}
\ No newline at end of file
+}
+
+
+
+#[inline]
+pub fn logistic(x : f64) -> f64 {
+    return 1.0 / (1.0 + (-x).exp());
+}
+#[inline]
+pub fn logistic_deriv(x : f64) -> f64 {
+    let y = logistic(x);
+    return y * (1.0 - y);
+}
+#[inline]
+pub fn tanh_exact(x : f64) -> f64 {
+    return x.tanh();
+}
+#[inline]
+pub fn tanh_exact_deriv(x : f64) -> f64 {
+    return 1.0 - square(x.tanh());
+}
+#[inline]
+pub fn relu(x : f64) -> f64 {
+    if x > 0.0 { x } else { 0.0 }
+}
+#[inline]
+pub fn relu_deriv(x : f64) -> f64 {
+    if x > 0.0 { 1.0 } else { 0.0 }
+}
+
+pub const LEAKY_RELU_SLOPE : f64 = 0.01;
+
+#[inline]
+pub fn leaky_relu(x : f64) -> f64 {
+    if x > 0.0 { x } else { LEAKY_RELU_SLOPE * x }
+}
+#[inline]
+pub fn leaky_relu_deriv(x : f64) -> f64 {
+    if x > 0.0 { 1.0 } else { LEAKY_RELU_SLOPE }
+}
+
+pub const ELU_ALPHA : f64 = 1.0;
+
+#[inline]
+pub fn elu(x : f64) -> f64 {
+    if x > 0.0 { x } else { ELU_ALPHA * (x.exp() - 1.0) }
+}
+#[inline]
+pub fn elu_deriv(x : f64) -> f64 {
+    if x > 0.0 { 1.0 } else { ELU_ALPHA * x.exp() }
+}
+
+const GELU_SQRT_2_OVER_PI : f64 = 0.7978845608028654;
+const GELU_COEFF : f64 = 0.044715;
+
+/**
+* GELU, using its usual tanh approximation
+*/
+#[inline]
+pub fn gelu(x : f64) -> f64 {
+    let inner = GELU_SQRT_2_OVER_PI * (x + GELU_COEFF * x * x * x);
+    return 0.5 * x * (1.0 + inner.tanh());
+}
+#[inline]
+pub fn gelu_deriv(x : f64) -> f64 {
+    let inner = GELU_SQRT_2_OVER_PI * (x + GELU_COEFF * x * x * x);
+    let tanh_inner = inner.tanh();
+    let inner_deriv = GELU_SQRT_2_OVER_PI * (1.0 + 3.0 * GELU_COEFF * x * x);
+    return 0.5 * (1.0 + tanh_inner) + 0.5 * x * (1.0 - square(tanh_inner)) * inner_deriv;
+}
+#[inline]
+pub fn softplus(x : f64) -> f64 {
+    // log(1+e^x), written to avoid overflowing for big x
+    if x > 0.0 {
+        return x + (-x).exp().ln_1p();
+    } else {
+        return x.exp().ln_1p();
+    }
+}
+#[inline]
+pub fn softplus_deriv(x : f64) -> f64 {
+    return logistic(x);
+}
+#[inline]
+pub fn linear(x : f64) -> f64 {
+    return x;
+}
+#[inline]
+pub fn linear_deriv(_x : f64) -> f64 {
+    return 1.0;
+}
+#[inline]
+pub fn hard_sigmoid(x : f64) -> f64 {
+    (0.2 * x + 0.5).clamp(0.0, 1.0)
+}
+#[inline]
+pub fn hard_sigmoid_deriv(x : f64) -> f64 {
+    if x > -2.5 && x < 2.5 { 0.2 } else { 0.0 }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DELTA : f64 = 1e-6;
+    const TOLERANCE : f64 = 1e-6;
+    // Away from the kinks of relu, leaky_relu, elu and hard_sigmoid
+    const POINTS : [f64; 8] = [-4.0, -2.2, -1.3, -0.4, 0.3, 1.1, 2.1, 3.7];
+
+    fn check_deriv(name : &str, f : fn(f64) -> f64, deriv : fn(f64) -> f64) {
+        for &x in POINTS.iter() {
+            let numeric = (f(x + DELTA) - f(x - DELTA)) / (2.0 * DELTA);
+            assert!((deriv(x) - numeric).abs() < TOLERANCE, "{}'({}) is {} but the finite difference gives {}", name, x, deriv(x), numeric);
+        }
+    }
+
+    #[test]
+    fn derivatives_match_finite_differences() {
+        check_deriv("logistic", logistic, logistic_deriv);
+        check_deriv("tanh_exact", tanh_exact, tanh_exact_deriv);
+        check_deriv("relu", relu, relu_deriv);
+        check_deriv("leaky_relu", leaky_relu, leaky_relu_deriv);
+        check_deriv("elu", elu, elu_deriv);
+        check_deriv("gelu", gelu, gelu_deriv);
+        check_deriv("softplus", softplus, softplus_deriv);
+        check_deriv("linear", linear, linear_deriv);
+        check_deriv("hard_sigmoid", hard_sigmoid, hard_sigmoid_deriv);
+    }
+
+    #[test]
+    fn softplus_doesnt_overflow() {
+        assert_eq!(softplus(1000.0), 1000.0);
+        assert!(softplus(-1000.0) >= 0.0 && softplus(-1000.0) < 1e-300);
+    }
+}
@@ -144,7 +144,7 @@ impl Matrix {
     }
 
     #[inline]
-    pub fn clone_randomized(&self, magnitude : f64, rand : &mut rand::ThreadRng) -> Matrix {
+    pub fn clone_randomized<R: Rng>(&self, magnitude : f64, rand : &mut R) -> Matrix {
         let mut new_datas = Vec::with_capacity(self.len);
         for i in 0..self.len {
             new_datas.push(self.datas[i] + math::rand(-magnitude, magnitude, rand));
@@ -184,13 +184,13 @@ impl Matrix {
         return self;
     }
     #[inline]
-    pub fn set_random(&mut self, min :f64, max :f64, mut rand : &mut rand::ThreadRng) {
+    pub fn set_random<R: Rng>(&mut self, min :f64, max :f64, rand : &mut R) {
         for index in 0..self.len {
             self.datas[index] = rand.gen_range::<f64>(min,max);
         }
     }
     #[inline]
-    pub fn set_random_int(&mut self, min :i32, max :i32, mut rand : &mut rand::ThreadRng) {
+    pub fn set_random_int<R: Rng>(&mut self, min :i32, max :i32, rand : &mut R) {
         for index in 0..self.len {
             self.datas[index] = rand.gen_range::<i32>(min, max+1) as f64;
         }
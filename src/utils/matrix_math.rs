@@ -189,6 +189,36 @@ pub fn row_merge_avg(matrix1 : &Matrix, matrix2 : &Matrix) -> Matrix {
     return Matrix::new_row_from_datas(new_datas);
 }
 
+pub fn row_softmax(matrix : &Matrix) -> Matrix {
+    assert!(matrix.is_row());
+    let mut max = f64::NEG_INFINITY;
+    for i in 0..matrix.len {
+        if matrix.datas[i] > max {
+            max = matrix.datas[i];
+        }
+    }
+    let mut new_datas = Vec::with_capacity(matrix.len);
+    let mut sum = 0.0;
+    for i in 0..matrix.len {
+        let exp = (matrix.datas[i] - max).exp();
+        sum += exp;
+        new_datas.push(exp);
+    }
+    for i in 0..matrix.len {
+        new_datas[i] /= sum;
+    }
+    return Matrix::new_row_from_datas(new_datas);
+}
+pub fn row_argmax(matrix : &Matrix) -> usize {
+    assert!(matrix.is_row());
+    let mut argmax = 0;
+    for i in 1..matrix.len {
+        if matrix.datas[i] > matrix.datas[argmax] {
+            argmax = i;
+        }
+    }
+    argmax
+}
 
 /*
 #[allow(dead_code)]
@@ -3,4 +3,5 @@ pub mod matrix_math;
 pub mod math;
 pub mod string;
 pub mod traits;
-pub mod io;
\ No newline at end of file
+pub mod io;
+pub mod random;
\ No newline at end of file
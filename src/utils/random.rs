@@ -0,0 +1,29 @@
+extern crate rand;
+
+use rand::{Rng, SeedableRng, StdRng};
+
+
+
+/**
+* Create the random number generator used for a whole run
+* @input seed : The seed, None for a seed taken from the OS
+* @return The random number generator
+*/
+pub fn new_rng(seed : Option<usize>) -> StdRng {
+    match seed {
+        Some(seed) => SeedableRng::from_seed(&[seed][..]),
+        None => StdRng::new().expect("Couldn't create the random number generator"),
+    }
+}
+
+/**
+* Create a new random number generator seeded from another one
+* Used to give every worker thread its own generator while keeping
+* the run reproducible
+* @input rand : The parent random number generator
+* @return The new random number generator
+*/
+pub fn derive_rng<R: Rng>(rand : &mut R) -> StdRng {
+    let seed : Vec<usize> = (0..4).map(|_| rand.gen::<usize>()).collect();
+    SeedableRng::from_seed(&seed[..])
+}
@@ -1,3 +1,6 @@
+use std::str::FromStr;
+
+
 
 
 pub fn is_alphanumeric(str: &str) -> bool {
@@ -16,4 +19,27 @@ pub fn is_control(str: &str) -> bool {
         }
     }
     false
+}
+
+/**
+* Parse the value following an option in the command line arguments
+* @input args : The command line arguments
+* @input option : The option, such as "--temperature"
+* @return The parsed value, None if the option isn't present
+*/
+pub fn parse_option<T: FromStr>(args : &Vec<String>, option : &str) -> Option<T> {
+    for i in 0..args.len() {
+        if args[i] == option {
+            match args.get(i+1) {
+                Some(value) => {
+                    match value.parse() {
+                        Ok(value) => { return Some(value); },
+                        Err(_) => panic!("Invalid argument on option {}", option),
+                    }
+                }
+                None => panic!("No argument on option {}", option),
+            }
+        }
+    }
+    None
 }
\ No newline at end of file